@@ -0,0 +1,1039 @@
+// SPDX-License-Identifier: MIT
+//!
+//! Format Comrak AST as CommonMark
+//!
+//! Unlike `comrak::format_commonmark`, source formatting kept in AST or `SourceFormats`
+//! (code fences, list markers, setext headings, code span backticks) is written back as is,
+//! and texts are escaped only where CommonMark syntax would be recognized.
+//!
+//! Other source formatting is normalized:
+//!
+//! - thematic breaks `***`, `___` as `---`
+//! - emphasis `_emph_`, `__strong__` as `*emph*`, `**strong**`
+//! - closing sequence of ATX headings `## Title ##` is dropped
+//! - hard line breaks with trailing spaces as backslash `\`
+//! - autolinks `<https://...>` as bare URL, if autolink extension is enabled
+//!
+
+use crate::cmark_xml::CmarkExtensions;
+use comrak::nodes::{
+    AstNode, ListDelimType, ListType, NodeCodeBlock, NodeLink, NodeList, NodeValue,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Placeholder of inline element following text, neither whitespace nor alphanumeric
+const INLINE: char = '\u{FFFC}';
+
+/// Form of reference link
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ReferenceForm {
+    /// `[text][label]`
+    Full,
+    /// `[label][]`
+    Collapsed,
+    /// `[label]`
+    Shortcut,
+}
+
+impl ReferenceForm {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Collapsed => "collapsed",
+            Self::Shortcut => "shortcut",
+        }
+    }
+
+    /// Full form for unknown string
+    pub(crate) fn from_attr(s: Option<&str>) -> Self {
+        match s {
+            Some("collapsed") => Self::Collapsed,
+            Some("shortcut") => Self::Shortcut,
+            _ => Self::Full,
+        }
+    }
+}
+
+/// Label of reference link or image
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LinkReference {
+    /// Label as written in source
+    pub label: String,
+    pub form: ReferenceForm,
+}
+
+/// Reference links and images, keyed by address of Link or Image node
+pub(crate) type LinkReferences = HashMap<usize, LinkReference>;
+
+/// Source formatting not kept in Comrak AST, keyed by address of node
+#[derive(Debug, Default)]
+pub(crate) struct SourceFormats {
+    /// Link and Image nodes written as reference links
+    pub references: LinkReferences,
+    /// HTML blocks of link reference definitions followed by paragraph without blank line
+    pub tight_definitions: HashSet<usize>,
+    /// Length of underlines of setext headings
+    pub underlines: HashMap<usize, usize>,
+    /// Code spans padded with spaces, `` `` a`b `` ``
+    pub padded_codes: HashSet<usize>,
+}
+
+/// Key of node in `LinkReferences` and `SourceFormats`
+pub(crate) fn node_key<'a>(node: &'a AstNode<'a>) -> usize {
+    node as *const AstNode<'a> as usize
+}
+
+/// Link reference definition, `[label]: url "title"`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LinkDefinition {
+    /// Label as written in source
+    pub label: String,
+    pub url: String,
+    pub title: String,
+}
+
+/// Normalize link label to match references with definitions
+///
+/// Case-insensitive, and consecutive whitespaces are treated as one space.
+pub(crate) fn normalize_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Format Comrak AST as CommonMark text
+///
+/// Source formatting of nodes in `formats` is written back, such as reference links.
+pub(crate) fn format_commonmark<'a>(
+    root: &'a AstNode<'a>,
+    extensions: &CmarkExtensions,
+    formats: &SourceFormats,
+) -> String {
+    let mut f = Formatter::new(*extensions, formats);
+    f.format(root);
+    if !f.out.is_empty() && !f.out.ends_with('\n') {
+        f.out.push('\n');
+    }
+    f.out
+}
+
+/// Format link reference definitions, one definition per line
+pub(crate) fn format_link_definitions(definitions: &[LinkDefinition]) -> String {
+    let formats = SourceFormats::default();
+    let mut f = Formatter::new(CmarkExtensions::default(), &formats);
+    for definition in definitions {
+        f.write(&format!("[{}]: ", definition.label));
+        if definition.url.is_empty() {
+            // Destination can not be omitted in definition
+            f.write("<>");
+        }
+        f.format_destination(&definition.url, &definition.title);
+        f.cr();
+    }
+    f.flush_cr();
+    f.out
+}
+
+struct Formatter<'r> {
+    extensions: CmarkExtensions,
+    formats: &'r SourceFormats,
+    out: String,
+    /// Prefix of each line, such as "> " or list item indent
+    prefix: String,
+    /// Number of line breaks to be written before next output
+    need_cr: u8,
+    begin_line: bool,
+    begin_content: bool,
+    /// Soft breaks are written as space, in ATX heading
+    no_linebreaks: bool,
+    /// Not the first line of paragraph
+    continuation: bool,
+    in_tight_list_item: bool,
+    in_table: bool,
+    in_link: usize,
+    /// Output positions where texts of reference links start
+    reference_texts: Vec<usize>,
+}
+
+impl<'r> Formatter<'r> {
+    fn new(extensions: CmarkExtensions, formats: &'r SourceFormats) -> Self {
+        Self {
+            extensions,
+            formats,
+            out: String::new(),
+            prefix: String::new(),
+            need_cr: 0,
+            begin_line: true,
+            begin_content: true,
+            no_linebreaks: false,
+            continuation: false,
+            in_tight_list_item: false,
+            in_table: false,
+            in_link: 0,
+            reference_texts: Vec::new(),
+        }
+    }
+
+    fn cr(&mut self) {
+        self.need_cr = self.need_cr.max(1);
+    }
+
+    fn blankline(&mut self) {
+        self.need_cr = self.need_cr.max(2);
+    }
+
+    /// Write pending line breaks
+    fn flush_cr(&mut self) {
+        if self.in_tight_list_item && self.need_cr > 1 {
+            self.need_cr = 1;
+        }
+        // Line breaks already written are counted
+        let mut k = self.out.len() as isize - 1;
+        while self.need_cr > 0 {
+            if k < 0 || self.out.as_bytes()[k as usize] == b'\n' {
+                k -= 1;
+            } else {
+                self.out.push('\n');
+                if self.need_cr > 1 {
+                    self.out.push_str(self.prefix.trim_end());
+                }
+            }
+            self.begin_line = true;
+            self.begin_content = true;
+            self.need_cr -= 1;
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        if self.begin_line {
+            // No trailing whitespace on blank line
+            if c == '\n' {
+                self.out.push_str(self.prefix.trim_end());
+            } else {
+                self.out.push_str(&self.prefix);
+            }
+        }
+        self.out.push(c);
+        self.begin_line = c == '\n';
+        self.begin_content = c == '\n' || (self.begin_content && c.is_ascii_digit());
+    }
+
+    /// Write text as is
+    fn write(&mut self, s: &str) {
+        self.flush_cr();
+        for c in s.chars() {
+            self.push(c);
+        }
+    }
+
+    /// Write text, escaping characters which would be parsed as CommonMark syntax
+    ///
+    /// `follow` is the character written after this text, None if line ends.
+    fn write_text(&mut self, s: &str, follow: Option<char>) {
+        self.flush_cr();
+        let chars = s.chars().collect::<Vec<_>>();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let prev = if self.begin_line {
+                None
+            } else {
+                self.out.chars().next_back()
+            };
+            let next = chars.get(i + 1).copied().or(follow);
+
+            if matches!(c, '*' | '_' | '~') || (c == '^' && self.extensions.superscript) {
+                // Delimiter run may open or close emphasis
+                let len = chars[i..].iter().take_while(|&&d| d == c).count();
+                let after = chars.get(i + len).copied().or(follow);
+                let escape = !is_inert_delimiter(c, prev, after)
+                    || (self.begin_content && self.line_start_needs_escape(&chars[i..], follow));
+                for _ in 0..len {
+                    if escape {
+                        self.push('\\');
+                    }
+                    self.push(c);
+                }
+                i += len;
+                continue;
+            }
+
+            let escape =
+                match c {
+                    '\\' => next.is_none_or(|n| n.is_ascii_punctuation() || n == INLINE),
+                    '`' => true,
+                    '<' => next
+                        .is_some_and(|n| n.is_ascii_alphabetic() || matches!(n, '/' | '!' | '?')),
+                    '&' => is_entity(&chars[i..]),
+                    '!' => next == Some('['),
+                    '[' => self.in_link > 0,
+                    ']' => self.in_link > 0 || matches!(next, Some('(' | '[' | ':') | Some(INLINE)),
+                    '|' => self.in_table,
+                    _ => false,
+                } || (self.begin_content && self.line_start_needs_escape(&chars[i..], follow));
+            if escape {
+                self.push('\\');
+            }
+            self.push(c);
+            i += 1;
+        }
+    }
+
+    /// Check the character at the beginning of line starts block syntax
+    fn line_start_needs_escape(&self, rest: &[char], follow: Option<char>) -> bool {
+        let c = rest[0];
+        let next = rest.get(1).copied().or(follow);
+        let is_space = |c: Option<char>| c.is_none_or(char::is_whitespace);
+
+        // "1. " or "1) ", ordered list
+        let digits = self
+            .out
+            .chars()
+            .rev()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>();
+        if !digits.is_empty() {
+            // Only list starting with 1 can interrupt paragraph
+            return matches!(c, '.' | ')')
+                && is_space(next)
+                && digits.len() <= 9
+                && (!self.continuation || digits == "1");
+        }
+
+        // Whole line, if the line ends at the end of text
+        let whole_line = follow.is_none();
+        let only = |chars: &[char]| {
+            whole_line && rest.iter().all(|d| chars.contains(d) || d.is_whitespace())
+        };
+        match c {
+            '#' => {
+                let len = rest.iter().take_while(|&&d| d == '#').count();
+                len <= 6 && is_space(rest.get(len).copied().or(follow))
+            }
+            '>' => true,
+            // Bullet list
+            '-' | '+' | '*' if is_space(next) => true,
+            // Setext heading underline, or thematic break
+            '=' => only(&['=']),
+            '-' => only(&['-']),
+            '_' | '*' => only(&[c]) && rest.iter().filter(|&&d| d == c).count() >= 3,
+            // Code fence
+            '~' => rest.iter().take_while(|&&d| d == '~').count() >= 3,
+            _ => false,
+        }
+    }
+
+    fn format<'a>(&mut self, node: &'a AstNode<'a>) {
+        if self.format_node(node, true) {
+            for child in node.children() {
+                self.format(child);
+            }
+            self.format_node(node, false);
+        }
+    }
+
+    /// Format AST node, returns false if children should be skipped
+    fn format_node<'a>(&mut self, node: &'a AstNode<'a>, entering: bool) -> bool {
+        let is_item = matches!(node.data.borrow().value, NodeValue::Item(..));
+        if !(is_item && node.previous_sibling().is_none() && entering) {
+            self.in_tight_list_item = in_tight_list_item(node);
+        }
+
+        match &node.data.borrow().value {
+            NodeValue::Document => {}
+            NodeValue::FrontMatter(fm) => {
+                if entering {
+                    self.write(&from_utf8(fm));
+                }
+            }
+            NodeValue::BlockQuote => {
+                if entering {
+                    self.write("> ");
+                    self.begin_content = true;
+                    self.prefix.push_str("> ");
+                } else {
+                    self.prefix.truncate(self.prefix.len() - 2);
+                    self.blankline();
+                }
+            }
+            NodeValue::List(nl) => {
+                if !entering && needs_end_list_marker(node, nl) {
+                    // Prevent merging with following list or code block
+                    self.cr();
+                    self.write("<!-- end list -->");
+                    self.blankline();
+                }
+            }
+            NodeValue::Item(item) => self.format_item(node, item, entering),
+            NodeValue::DescriptionList
+            | NodeValue::DescriptionItem(..)
+            | NodeValue::DescriptionTerm => {}
+            NodeValue::DescriptionDetails => {
+                let (offset, padding) = match node.parent().map(|p| p.data.borrow().value.clone()) {
+                    Some(NodeValue::DescriptionItem(item)) => (item.marker_offset, item.padding),
+                    _ => (0, 2),
+                };
+                let padding = padding.max(2);
+                if entering {
+                    self.write(&" ".repeat(offset));
+                    self.write(":");
+                    self.write(&" ".repeat(padding - 1));
+                    self.prefix.push_str(&" ".repeat(offset + padding));
+                } else {
+                    self.prefix.truncate(self.prefix.len() - offset - padding);
+                }
+            }
+            NodeValue::Heading(nh) => {
+                let setext = nh.setext && nh.level <= 2;
+                if entering {
+                    if !setext {
+                        self.write(&"#".repeat(nh.level as usize));
+                        self.write(" ");
+                        self.no_linebreaks = true;
+                    }
+                    self.continuation = false;
+                } else {
+                    if setext {
+                        // Underline as in source, or as long as the heading text
+                        let line = self.out.rsplit('\n').next().unwrap_or("");
+                        let width = line[self.prefix.len().min(line.len())..].chars().count();
+                        let width = self
+                            .formats
+                            .underlines
+                            .get(&node_key(node))
+                            .copied()
+                            .unwrap_or(width);
+                        let underline = if nh.level == 1 { "=" } else { "-" };
+                        self.cr();
+                        self.write(&underline.repeat(width.max(1)));
+                    }
+                    self.no_linebreaks = false;
+                    self.blankline();
+                }
+            }
+            NodeValue::CodeBlock(ncb) => {
+                if entering {
+                    self.format_code_block(node, ncb);
+                }
+            }
+            NodeValue::HtmlBlock(nhb) => {
+                if entering {
+                    if node.previous_sibling().is_some() {
+                        self.blankline();
+                    }
+                    self.write(from_utf8(&nhb.literal).trim_end_matches('\n'));
+                    if self.formats.tight_definitions.contains(&node_key(node))
+                        && starts_paragraph_text(node.next_sibling())
+                    {
+                        self.cr();
+                    } else {
+                        self.blankline();
+                    }
+                }
+            }
+            NodeValue::ThematicBreak => {
+                if entering {
+                    self.blankline();
+                    self.write("---");
+                    self.blankline();
+                }
+            }
+            NodeValue::Paragraph => {
+                if entering {
+                    self.continuation = false;
+                } else {
+                    self.blankline();
+                }
+            }
+            NodeValue::Text(t) => {
+                if entering {
+                    self.write_text(&from_utf8(t), follow_char(node));
+                }
+            }
+            NodeValue::LineBreak => {
+                if entering {
+                    self.write("\\");
+                    self.cr();
+                    self.continuation = true;
+                }
+            }
+            NodeValue::SoftBreak => {
+                if entering {
+                    if self.no_linebreaks {
+                        self.write(" ");
+                    } else {
+                        self.cr();
+                        self.continuation = true;
+                    }
+                }
+            }
+            NodeValue::Code(code) => {
+                if entering {
+                    let padded = self.formats.padded_codes.contains(&node_key(node));
+                    self.format_code(&from_utf8(&code.literal), code.num_backticks, padded);
+                }
+            }
+            NodeValue::HtmlInline(literal) => {
+                if entering {
+                    self.write(&from_utf8(literal));
+                }
+            }
+            NodeValue::Strong => self.write("**"),
+            NodeValue::Emph => {
+                // Nested emphasis *_text_*
+                let nested = node
+                    .parent()
+                    .is_some_and(|p| matches!(p.data.borrow().value, NodeValue::Emph))
+                    && node.next_sibling().is_none()
+                    && node.previous_sibling().is_none();
+                self.write(if nested { "_" } else { "*" });
+            }
+            NodeValue::TaskItem(checked) => {
+                if entering {
+                    self.write(if *checked { "[x] " } else { "[ ] " });
+                }
+            }
+            NodeValue::Strikethrough => self.write("~~"),
+            NodeValue::Superscript => self.write("^"),
+            NodeValue::Link(_) | NodeValue::Image(_)
+                if self.formats.references.contains_key(&node_key(node)) =>
+            {
+                self.format_reference(node, entering);
+            }
+            NodeValue::Link(nl) => {
+                if self.is_bare_autolink(node, nl) {
+                    // Link text is URL itself
+                    return true;
+                }
+                if is_autolink(node, &nl.url, &nl.title) {
+                    if entering {
+                        let url = from_utf8(&nl.url);
+                        self.write("<");
+                        self.write(url.strip_prefix("mailto:").unwrap_or(&url));
+                        self.write(">");
+                    }
+                    return false;
+                }
+                if entering {
+                    self.write("[");
+                    self.in_link += 1;
+                } else {
+                    self.in_link -= 1;
+                    self.write("](");
+                    self.format_destination(&from_utf8(&nl.url), &from_utf8(&nl.title));
+                    self.write(")");
+                }
+            }
+            NodeValue::Image(nl) => {
+                if entering {
+                    self.write("![");
+                    self.in_link += 1;
+                } else {
+                    self.in_link -= 1;
+                    self.write("](");
+                    self.format_destination(&from_utf8(&nl.url), &from_utf8(&nl.title));
+                    self.write(")");
+                }
+            }
+            NodeValue::Table(..) => {
+                self.in_table = entering;
+                self.blankline();
+            }
+            NodeValue::TableRow(..) => {
+                if entering {
+                    self.cr();
+                    self.write("|");
+                }
+            }
+            NodeValue::TableCell => self.format_table_cell(node, entering),
+            NodeValue::FootnoteDefinition(name) => {
+                if entering {
+                    self.blankline();
+                    self.write(&format!("[^{}]: ", from_utf8(name)));
+                    self.prefix.push_str("    ");
+                } else {
+                    self.prefix.truncate(self.prefix.len() - 4);
+                }
+            }
+            NodeValue::FootnoteReference(r) => {
+                if entering {
+                    self.write(&format!("[^{}]", from_utf8(r)));
+                }
+            }
+        }
+        true
+    }
+
+    /// Reference link or image, `[text][label]`
+    ///
+    /// Collapsed and shortcut forms are kept only while link text matches the label.
+    fn format_reference<'a>(&mut self, node: &'a AstNode<'a>, entering: bool) {
+        let formats = self.formats;
+        let reference = &formats.references[&node_key(node)];
+        if entering {
+            let is_image = matches!(node.data.borrow().value, NodeValue::Image(_));
+            self.write(if is_image { "![" } else { "[" });
+            self.in_link += 1;
+            self.reference_texts.push(self.out.len());
+            return;
+        }
+        self.in_link -= 1;
+        let start = self.reference_texts.pop().unwrap_or(self.out.len());
+        let form = if normalize_label(&self.out[start..]) != normalize_label(&reference.label) {
+            ReferenceForm::Full
+        } else if reference.form == ReferenceForm::Shortcut
+            && matches!(follow_char(node), Some('(' | '[' | ':'))
+        {
+            // "[label](...)" would be inline link
+            ReferenceForm::Collapsed
+        } else {
+            reference.form
+        };
+        match form {
+            ReferenceForm::Full => {
+                let label = format!("][{}]", reference.label);
+                self.write(&label);
+            }
+            ReferenceForm::Collapsed => self.write("][]"),
+            ReferenceForm::Shortcut => self.write("]"),
+        }
+    }
+
+    /// Link written as bare URL, recognized by autolink extension
+    fn is_bare_autolink<'a>(&self, node: &'a AstNode<'a>, nl: &NodeLink) -> bool {
+        if !self.extensions.autolink || !nl.title.is_empty() {
+            return false;
+        }
+        let text = match node.first_child() {
+            Some(child) if child.next_sibling().is_none() => match &child.data.borrow().value {
+                NodeValue::Text(t) => from_utf8(t),
+                _ => return false,
+            },
+            _ => return false,
+        };
+        let url = from_utf8(&nl.url);
+        let is_url = (url == text
+            && ["http://", "https://", "ftp://"]
+                .iter()
+                .any(|scheme| text.starts_with(scheme)))
+            || (text.starts_with("www.") && url == format!("http://{}", text))
+            || (text.contains('@') && url == format!("mailto:{}", text));
+
+        // Bare URL is recognized only between whitespaces or punctuations
+        let prev_ok = preceding_char(node)
+            .is_none_or(|c| c.is_whitespace() || matches!(c, '(' | '*' | '_' | '~'));
+        let next_ok = follow_char(node).is_none_or(|c| {
+            c.is_whitespace()
+                || matches!(
+                    c,
+                    '.' | ',' | ':' | ';' | '!' | '?' | ')' | '<' | '"' | '\''
+                )
+        });
+        is_url && prev_ok && next_ok
+    }
+
+    fn format_item<'a>(&mut self, node: &'a AstNode<'a>, item: &NodeList, entering: bool) {
+        let list = match node.parent().map(|p| p.data.borrow().value.clone()) {
+            Some(NodeValue::List(nl)) => nl,
+            _ => *item,
+        };
+        let marker = if list.list_type == ListType::Bullet {
+            let bullet = match (item.bullet_char, list.bullet_char) {
+                (0, 0) => b'-',
+                (0, c) | (c, _) => c,
+            };
+            char::from(bullet).to_string()
+        } else {
+            let number = if item.start != 0 {
+                item.start
+            } else {
+                list.start + node.preceding_siblings().skip(1).count()
+            };
+            let delimiter = if item.delimiter == ListDelimType::Paren {
+                ')'
+            } else {
+                '.'
+            };
+            format!("{}{}", number, delimiter)
+        };
+        let padding = item.padding.max(marker.len() + 1);
+        let width = item.marker_offset + padding;
+
+        if entering {
+            self.write(&" ".repeat(item.marker_offset));
+            self.write(&marker);
+            if node.first_child().is_some() {
+                self.write(&" ".repeat(padding - marker.len()));
+            }
+            self.begin_content = true;
+            self.prefix.push_str(&" ".repeat(width));
+        } else {
+            self.prefix.truncate(self.prefix.len() - width);
+            self.cr();
+        }
+    }
+
+    fn format_code_block<'a>(&mut self, node: &'a AstNode<'a>, ncb: &NodeCodeBlock) {
+        if !is_first_in_list_item(node) {
+            self.blankline();
+        }
+        let literal = from_utf8(&ncb.literal);
+
+        if is_indented_code_block(node, ncb) {
+            self.prefix.push_str("    ");
+            self.write(&literal);
+            self.prefix.truncate(self.prefix.len() - 4);
+        } else {
+            let info = from_utf8(&ncb.info);
+            let fence_char = match ncb.fence_char {
+                b'~' => '~',
+                b'`' if !info.contains('`') => '`',
+                0 if !info.contains('`') => '`',
+                _ => '~',
+            };
+            // Fence must be longer than fence-like lines in code
+            let fence_length = ncb
+                .fence_length
+                .max(longest_closing_fence(&literal, fence_char) + 1)
+                .max(3);
+            let fence = fence_char.to_string().repeat(fence_length);
+
+            self.prefix.push_str(&" ".repeat(ncb.fence_offset));
+            self.write(&fence);
+            self.write(&info);
+            self.cr();
+            self.write(&literal);
+            self.cr();
+            self.write(&fence);
+            self.prefix.truncate(self.prefix.len() - ncb.fence_offset);
+        }
+        self.blankline();
+    }
+
+    fn format_code(&mut self, literal: &str, num_backticks: usize, padded: bool) {
+        // Backticks of the same length in code closes code span
+        let runs = literal
+            .split(|c| c != '`')
+            .map(str::len)
+            .collect::<std::collections::HashSet<_>>();
+        let ticks = if num_backticks > 0 && !runs.contains(&num_backticks) {
+            num_backticks
+        } else {
+            (1..).find(|n| !runs.contains(n)).unwrap_or(1)
+        };
+        let fence = "`".repeat(ticks);
+        // Parser strips one space from both sides
+        let pad = padded
+            || literal.is_empty()
+            || literal.starts_with('`')
+            || literal.ends_with('`')
+            || (literal.starts_with(' ')
+                && literal.ends_with(' ')
+                && !literal.chars().all(|c| c == ' '));
+
+        self.write(&fence);
+        if pad {
+            self.write(" ");
+        }
+        if self.in_table {
+            self.write(&literal.replace('|', "\\|"));
+        } else {
+            self.write(literal);
+        }
+        if pad {
+            self.write(" ");
+        }
+        self.write(&fence);
+    }
+
+    /// Link destination and title
+    fn format_destination(&mut self, url: &str, title: &str) {
+        let mut depth = 0_i32;
+        let balanced = url.chars().all(|c| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            depth >= 0
+        }) && depth == 0;
+        if balanced
+            && !url
+                .chars()
+                .any(|c| c.is_whitespace() || c == '<' || c == '>')
+        {
+            self.write(&escape_backslash(url, &[]));
+        } else {
+            self.write("<");
+            self.write(&escape_backslash(url, &['<', '>']));
+            self.write(">");
+        }
+        if !title.is_empty() {
+            self.write(" \"");
+            self.write(&escape_backslash(title, &['"']));
+            self.write("\"");
+        }
+    }
+
+    fn format_table_cell<'a>(&mut self, node: &'a AstNode<'a>, entering: bool) {
+        use comrak::nodes::TableAlignment;
+        if entering {
+            self.write(" ");
+            return;
+        }
+        self.write(" |");
+
+        let Some(row) = node.parent() else {
+            return;
+        };
+        let in_header = matches!(row.data.borrow().value, NodeValue::TableRow(true));
+        if in_header && node.next_sibling().is_none() {
+            if let Some(table) = row.parent() {
+                if let NodeValue::Table(alignments) = &table.data.borrow().value {
+                    self.cr();
+                    self.write("|");
+                    for align in alignments {
+                        self.write(match align {
+                            TableAlignment::Left => " :-- |",
+                            TableAlignment::Center => " :-: |",
+                            TableAlignment::Right => " --: |",
+                            TableAlignment::None => " --- |",
+                        });
+                    }
+                    self.cr();
+                }
+            }
+        }
+    }
+}
+
+/// Comrak keeps texts as bytes, they are always UTF-8 since parsed from &str
+fn from_utf8(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Check the block containing the node is an item of tight list
+/// Node is paragraph starting with text, which can follow link reference definition directly
+///
+/// Title of definition may be written on the next line, `"title"`, `'title'` or `(title)`.
+fn starts_paragraph_text<'a>(node: Option<&'a AstNode<'a>>) -> bool {
+    let Some(node) = node.filter(|n| matches!(n.data.borrow().value, NodeValue::Paragraph)) else {
+        return false;
+    };
+    match node
+        .first_child()
+        .map(|child| child.data.borrow().value.clone())
+    {
+        Some(NodeValue::Text(t)) => from_utf8(&t)
+            .chars()
+            .next()
+            .is_some_and(|c| !c.is_whitespace() && !matches!(c, '"' | '\'' | '(')),
+        _ => true,
+    }
+}
+
+fn in_tight_list_item<'a>(node: &'a AstNode<'a>) -> bool {
+    let block = match node.ancestors().find(|n| n.data.borrow().value.block()) {
+        Some(block) => block,
+        None => return false,
+    };
+    [Some(block), block.parent()]
+        .into_iter()
+        .flatten()
+        .find(|n| matches!(n.data.borrow().value, NodeValue::Item(..)))
+        .and_then(|item| item.parent())
+        .is_some_and(|list| match &list.data.borrow().value {
+            NodeValue::List(nl) => nl.tight,
+            _ => false,
+        })
+}
+
+fn is_first_in_list_item<'a>(node: &'a AstNode<'a>) -> bool {
+    node.previous_sibling().is_none()
+        && node
+            .parent()
+            .is_some_and(|p| matches!(p.data.borrow().value, NodeValue::Item(..)))
+}
+
+/// Code block can be written as indented code block
+fn is_indented_code_block<'a>(node: &'a AstNode<'a>, ncb: &NodeCodeBlock) -> bool {
+    // Leading and trailing blank lines are not a part of indented code block
+    let literal = from_utf8(&ncb.literal);
+    let mut lines = literal.lines();
+    let not_blank = |line: Option<&str>| line.is_some_and(|l| !l.trim().is_empty());
+    !ncb.fenced
+        && ncb.info.is_empty()
+        && !is_first_in_list_item(node)
+        && not_blank(lines.next())
+        && not_blank(literal.lines().next_back())
+}
+
+/// List followed by same kind of list or indented code block needs explicit end
+fn needs_end_list_marker<'a>(node: &'a AstNode<'a>, nl: &NodeList) -> bool {
+    match node
+        .next_sibling()
+        .map(|n| (n, n.data.borrow().value.clone()))
+    {
+        Some((_, NodeValue::List(next))) => {
+            next.list_type == nl.list_type
+                && next.bullet_char == nl.bullet_char
+                && next.delimiter == nl.delimiter
+        }
+        Some((next, NodeValue::CodeBlock(ncb))) => is_indented_code_block(next, &ncb),
+        _ => false,
+    }
+}
+
+/// Character written after the text node, None if line ends
+fn follow_char<'a>(node: &'a AstNode<'a>) -> Option<char> {
+    match node.next_sibling() {
+        Some(next) => match &next.data.borrow().value {
+            NodeValue::Text(t) => from_utf8(t).chars().next().or(Some(INLINE)),
+            NodeValue::SoftBreak | NodeValue::LineBreak => None,
+            NodeValue::Link(_) | NodeValue::FootnoteReference(_) => Some('['),
+            NodeValue::Image(_) => Some('!'),
+            NodeValue::Code(_) => Some('`'),
+            NodeValue::Emph | NodeValue::Strong => Some('*'),
+            NodeValue::Strikethrough => Some('~'),
+            NodeValue::HtmlInline(_) => Some('<'),
+            _ => Some(INLINE),
+        },
+        // End of inline container, such as emphasis or link
+        None if node
+            .parent()
+            .is_some_and(|p| !p.data.borrow().value.block()) =>
+        {
+            Some(INLINE)
+        }
+        None => None,
+    }
+}
+
+/// Character written before the inline node, None if line starts
+fn preceding_char<'a>(node: &'a AstNode<'a>) -> Option<char> {
+    match node.previous_sibling() {
+        Some(prev) => match &prev.data.borrow().value {
+            NodeValue::Text(t) => from_utf8(t).chars().next_back().or(Some(INLINE)),
+            NodeValue::SoftBreak | NodeValue::LineBreak => None,
+            NodeValue::Emph | NodeValue::Strong => Some('*'),
+            NodeValue::Strikethrough => Some('~'),
+            _ => Some(INLINE),
+        },
+        // Beginning of inline container, such as emphasis
+        None if node
+            .parent()
+            .is_some_and(|p| !p.data.borrow().value.block()) =>
+        {
+            Some(INLINE)
+        }
+        None => None,
+    }
+}
+
+/// Delimiter run which can neither open nor close emphasis
+fn is_inert_delimiter(c: char, prev: Option<char>, next: Option<char>) -> bool {
+    let is_space = |c: Option<char>| c.is_none_or(char::is_whitespace);
+    let is_alnum = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    (is_space(prev) && is_space(next)) || (c == '_' && is_alnum(prev) && is_alnum(next))
+}
+
+/// Check text starts with entity or numeric character reference, like &amp; or &#123;
+fn is_entity(chars: &[char]) -> bool {
+    let end = match chars.iter().position(|&c| c == ';') {
+        Some(end) => end,
+        None => return false,
+    };
+    let name = chars[1..end].iter().collect::<String>();
+    if let Some(num) = name.strip_prefix('#') {
+        if let Some(hex) = num.strip_prefix(['x', 'X']) {
+            (1..=6).contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit())
+        } else {
+            (1..=7).contains(&num.len()) && num.chars().all(|c| c.is_ascii_digit())
+        }
+    } else {
+        name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name.chars().all(|c| c.is_ascii_alphanumeric())
+    }
+}
+
+/// Escape backslash before punctuation and given characters
+fn escape_backslash(s: &str, special: &[char]) -> String {
+    let mut escaped = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let next = chars.peek().copied();
+        if special.contains(&c) || (c == '\\' && next.is_none_or(|n| n.is_ascii_punctuation())) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn is_autolink<'a>(node: &'a AstNode<'a>, url: &[u8], title: &[u8]) -> bool {
+    let url = from_utf8(url);
+    let has_scheme = url.split_once(':').is_some_and(|(scheme, _)| {
+        (2..=32).contains(&scheme.len())
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+    });
+    if !has_scheme || !title.is_empty() {
+        return false;
+    }
+    match node.first_child() {
+        Some(child) if child.next_sibling().is_none() => match &child.data.borrow().value {
+            NodeValue::Text(t) => url.strip_prefix("mailto:").unwrap_or(&url) == from_utf8(t),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Longest line in code which would be parsed as closing code fence
+fn longest_closing_fence(literal: &str, fence_char: char) -> usize {
+    literal
+        .lines()
+        .filter_map(|line| {
+            let fence = line.trim_start_matches(' ');
+            let indent = line.len() - fence.len();
+            let fence = fence.trim_end();
+            (indent <= 3 && !fence.is_empty() && fence.chars().all(|c| c == fence_char))
+                .then_some(fence.len())
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmark_xml::cmark_from_xml;
+
+    fn cmark(body: &str) -> String {
+        cmark_from_xml(&format!("<body xmlns='markdown'>{}</body>", body), false).unwrap()
+    }
+
+    #[test]
+    fn test_escape_text() {
+        // Inert characters are written as is
+        assert_eq!(
+            cmark("<p>Hello! 2 * 3 = 6, snake_case, AT&amp;T, [note]</p>"),
+            "Hello! 2 * 3 = 6, snake_case, AT&T, [note]\n"
+        );
+        // Characters which would be parsed as syntax
+        assert_eq!(
+            cmark("<p>*a* _b_ `c` &amp;amp; [d](e) &lt;br&gt; \\</p>"),
+            "\\*a\\* \\_b\\_ \\`c\\` \\&amp; [d\\](e) \\<br> \\\\\n"
+        );
+        // Block syntax at the beginning of line
+        assert_eq!(
+            cmark("<p># a<wbr/>- b<wbr/>&gt; c<wbr/>===<wbr/>2. d</p><p>1. e</p>"),
+            "\\# a\n\\- b\n\\> c\n\\===\n2. d\n\n1\\. e\n"
+        );
+        // Emphasis next to text
+        assert_eq!(cmark("<p><em>a*</em>b</p>"), "*a\\**b\n");
+    }
+}
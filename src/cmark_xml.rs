@@ -3,15 +3,54 @@
 //! Convert CommonMark <=> XML
 //!
 
+use crate::cmark_fmt::{
+    node_key, normalize_label, LinkDefinition, LinkReference, LinkReferences, ReferenceForm,
+    SourceFormats,
+};
+use crate::error::{Error, Result};
+use std::collections::{HashMap, HashSet};
+
 /// XML namespace
-const NS: &str = "markdown";
+pub(crate) const NS: &str = "markdown";
+
+/// CommonMark extensions, GitHub Flavored Markdown by default
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct CmarkExtensions {
+    pub strikethrough: bool,
+    pub table: bool,
+    /// Bare URL, `https://...` or `www....`
+    pub autolink: bool,
+    /// `- [x] done`
+    pub tasklist: bool,
+    /// `[^note]` and `[^note]: ...`
+    pub footnotes: bool,
+    /// `Term` followed by `: Details`
+    pub description_lists: bool,
+    /// `e = mc^2^`
+    pub superscript: bool,
+}
+
+impl Default for CmarkExtensions {
+    fn default() -> Self {
+        Self {
+            strikethrough: true,
+            table: true,
+            autolink: true,
+            tasklist: true,
+            footnotes: true,
+            description_lists: false,
+            superscript: false,
+        }
+    }
+}
 
 /// Read CommonMark with frontmatter
 ///
 /// Returns tuple, (CommonMark body, frontmatter)
 pub fn read_cmark_with_frontmatter<R: std::io::Read>(
     reader: &mut R,
-) -> std::io::Result<(String, String, Option<String>)> {
+) -> Result<(String, String, Option<String>)> {
     log::debug!("start read_cmark_with_frontmatter.");
     let mut buf = String::new();
     let mut delimiter = String::new();
@@ -26,6 +65,19 @@ pub fn read_cmark_with_frontmatter<R: std::io::Read>(
         delimiter = String::from("---");
         log::debug!("delimiter. {}", &delimiter);
         split_frontmatter(&buf, delimiter)
+    } else if buf.starts_with(";;;") {
+        // JSON frontmatter
+        delimiter = String::from(";;;");
+        log::debug!("delimiter. {}", &delimiter);
+        split_frontmatter(&buf, delimiter)
+    } else if let Some(len) = json_frontmatter_len(&buf) {
+        // JSON frontmatter without delimiter, { ... }
+        let (frontmatter, cmark_body) = buf.split_at(len);
+        Ok((
+            cmark_body.to_string(),
+            delimiter,
+            Some(frontmatter.to_string()),
+        ))
     } else {
         // No frontmatter, only CommonMark body
         Ok((buf, delimiter, None))
@@ -36,7 +88,7 @@ pub fn read_cmark_with_frontmatter<R: std::io::Read>(
 fn split_frontmatter(
     filebody: &str,
     delimiter: String,
-) -> std::io::Result<(String, String, Option<String>)> {
+) -> Result<(String, String, Option<String>)> {
     let mut iter = filebody.splitn(3, delimiter.as_str());
     let _ = iter.next(); // should empty
     let frontmatter = iter.next();
@@ -50,21 +102,56 @@ fn split_frontmatter(
         ))
     } else {
         // second delimiter can not be found.
-        Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof))
+        Err(Error::Parse(format!(
+            "closing frontmatter delimiter {} NOT found",
+            delimiter
+        )))
     }
 }
 
+/// Length of JSON object at the beginning of text, None if it is not a JSON object
+fn json_frontmatter_len(buf: &str) -> Option<usize> {
+    if !buf.starts_with('{') {
+        return None;
+    }
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in buf.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '{' if !in_string => depth += 1,
+            '}' if !in_string => {
+                depth -= 1;
+                if depth == 0 {
+                    // Shortcode or MDX expression may also start with {
+                    let len = i + 1;
+                    return serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(
+                        &buf[..len],
+                    )
+                    .ok()
+                    .map(|_| len);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
 /// Convert CommonMark text to XML string
 ///
 /// If CommonMark text contains Jinja style shortcode {{ ... }} used in Hugo, Zora, etc.,
 /// set escape_shortcode to true.
-pub fn xml_from_cmark(cmark_text: &str, escape_shortcode: bool) -> String {
+pub fn xml_from_cmark(cmark_text: &str, escape_shortcode: bool) -> Result<String> {
     let mut buf = Vec::<u8>::new();
 
     let xml_root = xmldom_from_cmark(cmark_text, escape_shortcode);
-    xml_root.write_to(&mut buf).unwrap();
+    xml_root.write_to(&mut buf)?;
 
-    String::from_utf8(buf).unwrap()
+    String::from_utf8(buf).map_err(|e| Error::Xml(e.to_string()))
 }
 
 /// Convert CommonMark text to XML DOM
@@ -72,21 +159,32 @@ pub fn xml_from_cmark(cmark_text: &str, escape_shortcode: bool) -> String {
 /// If CommonMark text contains Jinja style shortcode {{ ... }} used in Hugo, Zora, etc.,
 /// set escape_shortcode to true.
 pub fn xmldom_from_cmark(cmark_text: &str, escape_shortcode: bool) -> minidom::Element {
+    xmldom_from_cmark_with_extensions(cmark_text, escape_shortcode, &CmarkExtensions::default())
+}
+
+/// Convert CommonMark text to XML DOM, with CommonMark extensions
+pub fn xmldom_from_cmark_with_extensions(
+    cmark_text: &str,
+    escape_shortcode: bool,
+    extensions: &CmarkExtensions,
+) -> minidom::Element {
     // parse body as comrak AST
     let arena = comrak::Arena::new();
 
-    let ast_root = if escape_shortcode {
+    let cmark_text = if escape_shortcode {
         // pre-process shortcodes
-        let escaped = escape_all_shortcodes(&cmark_text);
+        let escaped = escape_all_shortcodes(cmark_text);
         log::trace!("escape_shortcode: {:?}\n", escaped);
-        comrak::parse_document(&arena, &escaped, &comrak_options())
+        escaped
     } else {
         // no escape
-        comrak::parse_document(&arena, cmark_text, &comrak_options())
+        cmark_text.to_string()
     };
+    let ast_root = parse_document(&arena, &cmark_text, extensions);
 
-    if let minidom::Node::Element(xml) = xml_from_ast(&ast_root) {
-        xml
+    if let minidom::Node::Element(mut xml) = xml_from_ast(ast_root) {
+        restore_source_formats(&mut xml, ast_root, &cmark_text);
+        restore_link_references(xml, ast_root, &cmark_text, extensions)
     } else {
         // incase of no element, returns empty <body/>
         minidom::Element::bare("body", NS)
@@ -96,28 +194,888 @@ pub fn xmldom_from_cmark(cmark_text: &str, escape_shortcode: bool) -> minidom::E
 /// Convert XML text back to CommonMark text
 ///
 /// If XML contains escaped shortcode, set escape_shortcode to true.
-pub fn cmark_from_xml(xml_str: &str, escape_shortcode: bool) -> minidom::Result<String> {
+pub fn cmark_from_xml(xml_str: &str, escape_shortcode: bool) -> Result<String> {
     let xml_root: minidom::Element = xml_str.parse()?;
-    Ok(cmark_from_xmldom(&xml_root, escape_shortcode))
+    cmark_from_xmldom(&xml_root, escape_shortcode)
 }
 
 /// Convert XML DOM back to CommonMark text
 ///
 /// If XML contains escaped shortcode, set escape_shortcode to true.
-pub fn cmark_from_xmldom(xml_root: &minidom::Element, escape_shortcode: bool) -> String {
+pub fn cmark_from_xmldom(xml_root: &minidom::Element, escape_shortcode: bool) -> Result<String> {
+    cmark_from_xmldom_with_extensions(xml_root, escape_shortcode, &CmarkExtensions::default())
+}
+
+/// Convert XML DOM back to CommonMark text, with CommonMark extensions
+pub fn cmark_from_xmldom_with_extensions(
+    xml_root: &minidom::Element,
+    escape_shortcode: bool,
+    extensions: &CmarkExtensions,
+) -> Result<String> {
     // Convert XML to Comrak AST
     let arena = comrak::Arena::new();
-    let ast_root = ast_from_xml(&arena, &xml_root);
+    let mut formats = SourceFormats::default();
+    let ast_root = ast_from_xml(&arena, xml_root, &mut formats);
+    formats.references = link_references_from_xml(xml_root, ast_root);
 
     // AST to plain CommonMark
-    let mut buf = Vec::<u8>::new();
-    comrak::format_commonmark(ast_root, &comrak_options(), &mut buf).unwrap();
-    let cmark_text = String::from_utf8(buf).unwrap();
+    let cmark_text = crate::cmark_fmt::format_commonmark(ast_root, extensions, &formats);
     if escape_shortcode {
-        unescape_all_shortcodes(&cmark_text)
+        Ok(unescape_all_shortcodes(&cmark_text))
     } else {
-        cmark_text
+        Ok(cmark_text)
+    }
+}
+
+/// Rewrite link destinations in XML DOM
+///
+/// `rewrite` returns new destination, or None to keep it as is.
+pub fn rewrite_links<F: Fn(&str) -> Option<String>>(xml_elm: &mut minidom::Element, rewrite: &F) {
+    if xml_elm.name() == "a" || xml_elm.name() == "link" {
+        if let Some(href) = xml_elm.attr("href").and_then(rewrite) {
+            xml_elm.set_attr("href", href);
+        }
+    }
+    for child in xml_elm.children_mut() {
+        rewrite_links(child, rewrite);
+    }
+}
+
+/// Parse CommonMark text as Comrak AST, keeping footnote definitions in place
+///
+/// Comrak moves footnote definitions to the end of document, and drops definitions never referenced.
+/// All definitions are referenced from a probe paragraph appended to the text,
+/// then the definitions are moved back before the block following them in source.
+fn parse_document<'a>(
+    arena: &'a comrak::Arena<comrak::nodes::AstNode<'a>>,
+    cmark_text: &str,
+    extensions: &CmarkExtensions,
+) -> &'a comrak::nodes::AstNode<'a> {
+    use comrak::nodes::NodeValue::*;
+    let options = comrak_options(extensions);
+    if !extensions.footnotes {
+        return comrak::parse_document(arena, cmark_text, &options);
+    }
+
+    let labels = cmark_text
+        .lines()
+        .filter_map(|line| footnote_definition_label(strip_container_markers(line)))
+        .collect::<Vec<_>>();
+    let mut probed = None;
+    if !labels.is_empty() {
+        let text = cmark_text.trim_end_matches('\n');
+        let probe_line = text.split('\n').count() + 2;
+        let references = labels
+            .iter()
+            .map(|label| format!("[^{}]", label))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let root =
+            comrak::parse_document(arena, &format!("{}\n\n{}\n", text, references), &options);
+        // Probe paragraph may be a part of other block, e.g. unclosed code fence
+        let probe = root
+            .children()
+            .filter(|node| !matches!(node.data.borrow().value, FootnoteDefinition(_)))
+            .last()
+            .filter(|node| {
+                let ast = node.data.borrow();
+                matches!(ast.value, Paragraph) && ast.start_line as usize == probe_line
+            });
+        if let Some(probe) = probe {
+            probe.detach();
+            probed = Some(root);
+        }
+    }
+    let ast_root = probed.unwrap_or_else(|| comrak::parse_document(arena, cmark_text, &options));
+    restore_footnote_labels(ast_root, cmark_text);
+
+    let mut definitions = ast_root
+        .children()
+        .filter(|node| matches!(node.data.borrow().value, FootnoteDefinition(_)))
+        .collect::<Vec<_>>();
+    definitions.sort_by_key(|node| node.data.borrow().start_line);
+    for definition in definitions {
+        definition.detach();
+        let start = definition.data.borrow().start_line;
+        let next = ast_root.children().find(|node| {
+            let ast = node.data.borrow();
+            !matches!(ast.value, FootnoteDefinition(_)) && ast.start_line > start
+        });
+        match next {
+            Some(next) => next.insert_before(definition),
+            None => ast_root.append(definition),
+        }
+    }
+    ast_root
+}
+
+/// Label of line starting footnote definition, `[^label]: ...`
+fn footnote_definition_label(line: &str) -> Option<&str> {
+    definition_label(line)?
+        .strip_prefix('^')
+        .filter(|label| !label.contains(char::is_whitespace))
+}
+
+/// Restore footnote labels, which Comrak renumbers as `[^1]`, `[^2]`, ...
+///
+/// Original labels are read from the lines where definitions start.
+fn restore_footnote_labels<'a>(ast_root: &'a comrak::nodes::AstNode<'a>, cmark_text: &str) {
+    use comrak::nodes::NodeValue::*;
+    let lines = cmark_text.lines().collect::<Vec<_>>();
+
+    let mut labels = std::collections::HashMap::new();
+    for node in ast_root.children() {
+        let mut ast = node.data.borrow_mut();
+        let line = (ast.start_line as usize)
+            .checked_sub(1)
+            .and_then(|i| lines.get(i));
+        if let (FootnoteDefinition(name), Some(line)) = (&mut ast.value, line) {
+            // "[^label]: text"
+            let label = line
+                .split_once("[^")
+                .and_then(|(_, rest)| rest.split_once("]:"))
+                .map(|(label, _)| label.as_bytes().to_vec());
+            if let Some(label) = label {
+                labels.insert(name.clone(), label.clone());
+                *name = label;
+            }
+        }
+    }
+
+    // References in definitions are not renumbered
+    for node in ast_root.children() {
+        if matches!(node.data.borrow().value, FootnoteDefinition(_)) {
+            continue;
+        }
+        for desc in node.descendants() {
+            if let FootnoteReference(name) = &mut desc.data.borrow_mut().value {
+                if let Some(label) = labels.get(name) {
+                    *name = label.clone();
+                }
+            }
+        }
+    }
+}
+
+/// Restore source formatting not kept in Comrak AST, as attributes of XML elements
+///
+/// Setext headings get `underline` (length of underline) attribute,
+/// code spans get `padded` attribute if the code is padded with spaces in source.
+fn restore_source_formats<'a>(
+    xml_root: &mut minidom::Element,
+    ast_root: &'a comrak::nodes::AstNode<'a>,
+    cmark_text: &str,
+) {
+    use comrak::nodes::NodeValue::*;
+    let lines = cmark_text.lines().collect::<Vec<_>>();
+
+    let underlines = ast_root
+        .descendants()
+        .filter_map(|node| {
+            let ast = node.data.borrow();
+            match &ast.value {
+                Heading(nh) if nh.setext => Some(setext_underline(&lines, ast.start_line as usize)),
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Match code spans with code spans in source, except code blocks
+    let code_lines = code_lines(ast_root);
+    let text = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if code_lines.contains(&(i + 1)) {
+                ""
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let spans = source_code_spans(&text);
+    let mut next_span = 0;
+    let mut paddings = Vec::new();
+    for node in ast_root.descendants() {
+        if let Code(code) = &node.data.borrow().value {
+            let literal = from_utf8(&code.literal);
+            let found = spans[next_span..]
+                .iter()
+                .position(|(content, _)| *content == literal);
+            paddings.push(found.map(|k| spans[next_span + k].1));
+            if let Some(k) = found {
+                next_span += k + 1;
+            }
+        }
     }
+
+    set_source_formats(
+        xml_root,
+        &mut underlines.into_iter(),
+        &mut paddings.into_iter(),
+    );
+}
+
+/// Lines of code and HTML blocks
+fn code_lines<'a>(ast_root: &'a comrak::nodes::AstNode<'a>) -> HashSet<usize> {
+    use comrak::nodes::NodeValue::*;
+    let mut code_lines = HashSet::new();
+    for node in ast_root.descendants() {
+        let ast = node.data.borrow();
+        let start = ast.start_line as usize;
+        let len = match &ast.value {
+            CodeBlock(cb) => from_utf8(&cb.literal).lines().count() + 2 * cb.fenced as usize,
+            HtmlBlock(hb) => from_utf8(&hb.literal).lines().count(),
+            _ => 0,
+        };
+        code_lines.extend(start..start + len);
+    }
+    code_lines
+}
+
+/// Code spans in text, as code and whether the code is padded with spaces
+fn source_code_spans(text: &str) -> Vec<(String, bool)> {
+    let bytes = text.as_bytes();
+    let run = |i: usize| bytes[i..].iter().take_while(|&&b| b == b'`').count();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => {
+                let n = run(i);
+                let start = i + n;
+                // Closed by backticks of the same length
+                let mut j = start;
+                let end = loop {
+                    match bytes[j..].iter().position(|&b| b == b'`') {
+                        Some(k) if run(j + k) == n => break Some(j + k),
+                        Some(k) => j += k + run(j + k),
+                        None => break None,
+                    }
+                };
+                match end {
+                    Some(end) => {
+                        // Parser converts line endings to spaces, and strips one space from both sides
+                        let content = text[start..end].replace('\n', " ");
+                        let padded = content.len() >= 2
+                            && content.starts_with(' ')
+                            && content.ends_with(' ')
+                            && !content.bytes().all(|b| b == b' ');
+                        let code = if padded {
+                            content[1..content.len() - 1].to_string()
+                        } else {
+                            content
+                        };
+                        spans.push((code, padded));
+                        i = end + n;
+                    }
+                    None => i = start,
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    spans
+}
+
+/// Length of underline following setext heading starting at line `start`
+fn setext_underline(lines: &[&str], start: usize) -> Option<usize> {
+    lines.iter().skip(start).find_map(|line| {
+        // Lines in list items are indented, lines in block quotes are prefixed with `>`
+        let mut content = line.trim();
+        while let Some(rest) = content.strip_prefix('>') {
+            content = rest.trim_start();
+        }
+        let underline = content
+            .bytes()
+            .next()
+            .filter(|c| *c == b'=' || *c == b'-')?;
+        content
+            .bytes()
+            .all(|c| c == underline)
+            .then_some(content.len())
+    })
+}
+
+/// Set attributes of source formatting to XML elements in document order
+fn set_source_formats<U: Iterator<Item = Option<usize>>, P: Iterator<Item = Option<bool>>>(
+    xml_elm: &mut minidom::Element,
+    underlines: &mut U,
+    paddings: &mut P,
+) {
+    for child in xml_elm.children_mut() {
+        match child.name() {
+            "h1" | "h2" if child.attr("setext") == Some("1") => {
+                if let Some(underline) = underlines.next().flatten() {
+                    child.set_attr("underline", underline);
+                }
+                set_source_formats(child, underlines, paddings);
+            }
+            "code" => {
+                if paddings.next().flatten() == Some(true) {
+                    child.set_attr("padded", 1);
+                }
+            }
+            _ => set_source_formats(child, underlines, paddings),
+        }
+    }
+}
+
+/// Restore reference links and link reference definitions, which Comrak resolves as inline links
+///
+/// Definitions are read from the source lines, and kept as `<nav>` at their position,
+/// also in block quotes and list items.
+/// `<a>` and `<img>` of references get `label` and `reference` (form of reference) attributes.
+fn restore_link_references<'a>(
+    mut xml_root: minidom::Element,
+    ast_root: &'a comrak::nodes::AstNode<'a>,
+    cmark_text: &str,
+    extensions: &CmarkExtensions,
+) -> minidom::Element {
+    use comrak::nodes::NodeValue::*;
+    let lines = cmark_text.lines().collect::<Vec<_>>();
+
+    // Lines of code and HTML blocks, and lines where blocks start
+    let code_lines = code_lines(ast_root);
+    let block_lines = ast_root
+        .descendants()
+        .skip(1)
+        .map(|node| node.data.borrow().start_line as usize)
+        .collect::<HashSet<_>>();
+
+    // Definitions are at the beginning of paragraphs, the rest of paragraph is text
+    let contents = lines
+        .iter()
+        .map(|line| strip_container_markers(line))
+        .collect::<Vec<_>>();
+    let is_blank = |i: usize| contents[i].trim().is_empty();
+    let mut groups = Vec::new();
+    let mut definitions = HashMap::new();
+    let mut n = 0;
+    while n < lines.len() {
+        let starts_paragraph =
+            n == 0 || is_blank(n - 1) || code_lines.contains(&n) || block_lines.contains(&(n + 1));
+        if !starts_paragraph
+            || code_lines.contains(&(n + 1))
+            || definition_label(contents[n]).is_none()
+        {
+            n += 1;
+            continue;
+        }
+        let len = (n..lines.len())
+            .take_while(|&i| {
+                i == n
+                    || !(is_blank(i)
+                        || code_lines.contains(&(i + 1))
+                        || block_lines.contains(&(i + 1)))
+            })
+            .count();
+        let group = (1..=len)
+            .rev()
+            .find_map(|k| parse_link_definitions(&contents[n..n + k], extensions).map(|g| (k, g)));
+        if let Some((k, group)) = group {
+            for definition in &group {
+                definitions
+                    .entry(normalize_label(&definition.label))
+                    .or_insert_with(|| (definition.url.clone(), definition.title.clone()));
+            }
+            groups.push((n + 1, k, group));
+        }
+        n += len;
+    }
+    if groups.is_empty() {
+        return xml_root;
+    }
+
+    // References in source, except code and definitions
+    let mut skip_lines = code_lines;
+    for (start, len, _) in &groups {
+        skip_lines.extend(*start..start + len);
+    }
+    let text = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if skip_lines.contains(&(i + 1)) {
+                ""
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let is_defined = |label: &str| {
+        !(extensions.footnotes && label.starts_with('^'))
+            && definitions.contains_key(&normalize_label(label))
+    };
+    let tokens = reference_tokens(&text, &is_defined);
+
+    // Match links with references in source order
+    let mut links = Vec::new();
+    for child in ast_root.children() {
+        let start = child.data.borrow().start_line;
+        let mut child_links = Vec::new();
+        ast_links(child, &mut child_links);
+        links.extend(child_links.into_iter().map(|link| (start, link)));
+    }
+    let mut order = (0..links.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| links[i].0);
+    let mut references = vec![None; links.len()];
+    let mut next_token = 0;
+    for i in order {
+        let link = links[i].1;
+        let (nl, is_image) = match &link.data.borrow().value {
+            Link(nl) => (nl.clone(), false),
+            Image(nl) => (nl.clone(), true),
+            _ => continue,
+        };
+        if is_autolink_text(link, &nl) {
+            continue;
+        }
+        let destination = (from_utf8(&nl.url), from_utf8(&nl.title));
+        let found = tokens[next_token..].iter().position(|(image, reference)| {
+            *image == is_image
+                && definitions.get(&normalize_label(&reference.label)) == Some(&destination)
+        });
+        if let Some(k) = found {
+            references[i] = Some(tokens[next_token + k].1.clone());
+            next_token += k + 1;
+        }
+    }
+    set_link_references(&mut xml_root, &mut references.into_iter());
+
+    // Definitions are located by parsing marker paragraphs in place of them
+    let mut probe = lines
+        .iter()
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>();
+    for (g, (start, len, _)) in groups.iter().enumerate() {
+        for i in start - 1..start - 1 + len {
+            let prefix = &lines[i][..lines[i].len() - contents[i].len()];
+            probe[i] = format!("{}{}{}", prefix, DEFINITION_MARKER, g);
+        }
+    }
+    let arena = comrak::Arena::new();
+    let probe_root = parse_document(&arena, &probe.join("\n"), extensions);
+    let groups = groups
+        .into_iter()
+        .map(|(_, _, group)| group)
+        .collect::<Vec<_>>();
+    insert_link_definitions(&xml_root, ast_root, probe_root, &groups)
+}
+
+/// Insert `<nav>` of definitions where marker paragraphs are in probe AST
+///
+/// Probe AST has the same blocks as AST, except marker paragraphs.
+fn insert_link_definitions<'a, 'b>(
+    xml_elm: &minidom::Element,
+    ast_node: &'a comrak::nodes::AstNode<'a>,
+    probe_node: &'b comrak::nodes::AstNode<'b>,
+    groups: &[Vec<LinkDefinition>],
+) -> minidom::Element {
+    use comrak::nodes::NodeValue::*;
+    let mut builder = minidom::Element::builder(xml_elm.name(), NS);
+    for (name, value) in xml_elm.attrs() {
+        builder = builder.attr(name, value);
+    }
+    let mut elm = builder.build();
+    let mut children = xml_elm.nodes().zip(ast_node.children());
+    for probe_child in probe_node.children() {
+        if let Some((group, followed)) = definition_marker(probe_child) {
+            if let Some(group) = groups.get(group) {
+                let mut nav = xml_from_link_definitions(group);
+                if followed {
+                    // Paragraph follows definitions without blank line
+                    nav.set_attr("tight", 1);
+                }
+                elm.append_child(nav);
+            }
+            if !followed {
+                continue;
+            }
+        }
+        let Some((node, ast_child)) = children.next() else {
+            break;
+        };
+        let is_container = matches!(
+            ast_child.data.borrow().value,
+            BlockQuote
+                | List(_)
+                | Item(_)
+                | FootnoteDefinition(_)
+                | DescriptionList
+                | DescriptionItem(_)
+                | DescriptionDetails
+        ) && std::mem::discriminant(&ast_child.data.borrow().value)
+            == std::mem::discriminant(&probe_child.data.borrow().value);
+        match node {
+            minidom::Node::Element(child) if is_container => {
+                elm.append_child(insert_link_definitions(
+                    child,
+                    ast_child,
+                    probe_child,
+                    groups,
+                ));
+            }
+            _ => elm.append_node(node.clone()),
+        }
+    }
+    for (node, _) in children {
+        elm.append_node(node.clone());
+    }
+    elm
+}
+
+/// Marker of link reference definitions in probe text, followed by group number
+const DEFINITION_MARKER: char = '\u{E000}';
+
+/// Group number of marker paragraph, and whether text follows the marker lines
+fn definition_marker<'b>(node: &'b comrak::nodes::AstNode<'b>) -> Option<(usize, bool)> {
+    use comrak::nodes::NodeValue::*;
+    if !matches!(node.data.borrow().value, Paragraph) {
+        return None;
+    }
+    let group = match &node.first_child()?.data.borrow().value {
+        Text(t) => from_utf8(t).strip_prefix(DEFINITION_MARKER)?.parse().ok()?,
+        _ => return None,
+    };
+    let followed = node
+        .children()
+        .any(|child| match &child.data.borrow().value {
+            Text(t) => !from_utf8(t).starts_with(DEFINITION_MARKER),
+            SoftBreak => false,
+            _ => true,
+        });
+    Some((group, followed))
+}
+
+/// Label of line starting link reference definition, `[label]: ...`
+fn definition_label(line: &str) -> Option<&str> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let rest = line[indent..].strip_prefix('[').filter(|_| indent <= 3)?;
+    let mut escaped = false;
+    for (i, c) in rest.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '[' => return None,
+            ']' => {
+                let label = &rest[..i];
+                return (!label.trim().is_empty() && rest[i + 1..].starts_with(':'))
+                    .then_some(label);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Line without markers of block quotes, list items and description details
+fn strip_container_markers(mut line: &str) -> &str {
+    loop {
+        let rest = line.trim_start();
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let marker = match rest.as_bytes().get(digits) {
+            Some(b'>') if digits == 0 => {
+                line = &rest[1..];
+                continue;
+            }
+            Some(b'-' | b'*' | b'+' | b':') if digits == 0 => 1,
+            Some(b'.' | b')') if digits > 0 => digits + 1,
+            _ => return rest,
+        };
+        let after = &rest[marker..];
+        if !(after.is_empty() || after.starts_with([' ', '\t'])) {
+            return rest;
+        }
+        line = after;
+    }
+}
+
+/// Parse lines of link reference definitions with Comrak
+///
+/// None if the lines contain other than definitions, or a label is defined twice.
+fn parse_link_definitions(
+    lines: &[&str],
+    extensions: &CmarkExtensions,
+) -> Option<Vec<LinkDefinition>> {
+    use comrak::nodes::NodeValue::*;
+    let labels = lines
+        .iter()
+        .filter_map(|line| definition_label(line))
+        .collect::<Vec<_>>();
+    let normalized = labels
+        .iter()
+        .map(|label| normalize_label(label))
+        .collect::<HashSet<_>>();
+    if normalized.len() != labels.len()
+        || (extensions.footnotes && labels.iter().any(|label| label.starts_with('^')))
+    {
+        return None;
+    }
+
+    // Only paragraphs of probe links remain, if all lines are definitions
+    let mut probe = lines.join("\n") + "\n\n";
+    for label in &labels {
+        probe += &format!("[probe][{}]\n\n", label);
+    }
+    let arena = comrak::Arena::new();
+    let root = comrak::parse_document(&arena, &probe, &comrak_options(extensions));
+    if root.children().count() != labels.len() {
+        return None;
+    }
+    labels
+        .iter()
+        .zip(root.children())
+        .map(|(label, paragraph)| {
+            let link = paragraph.first_child()?;
+            let ast = link.data.borrow();
+            match &ast.value {
+                Link(nl) => Some(LinkDefinition {
+                    label: label.to_string(),
+                    url: from_utf8(&nl.url),
+                    title: from_utf8(&nl.title),
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// References in CommonMark text, in order of opening brackets
+///
+/// Returns tuples, (image or not, reference)
+fn reference_tokens<F: Fn(&str) -> bool>(text: &str, is_defined: &F) -> Vec<(bool, LinkReference)> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let run = |i: usize, c: char| chars[i..].iter().take_while(|&&d| d == c).count();
+    let mut openers = Vec::new();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '`' => {
+                // Skip code span
+                let len = run(i, '`');
+                let mut j = i + len;
+                while j < chars.len() && !(chars[j] == '`' && run(j, '`') == len) {
+                    j += run(j, chars[j]);
+                }
+                if j < chars.len() {
+                    i = j + len - 1;
+                } else {
+                    i += len - 1;
+                }
+            }
+            '<' if chars
+                .get(i + 1)
+                .is_some_and(|c| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?')) =>
+            {
+                // Skip autolink and HTML tag
+                if let Some(len) = chars[i..].iter().position(|&c| c == '>' || c == '\n') {
+                    if chars[i + len] == '>' {
+                        i += len;
+                    }
+                }
+            }
+            '[' => {
+                let is_image = i > 0 && chars[i - 1] == '!' && !(i > 1 && chars[i - 2] == '\\');
+                openers.push((i, is_image));
+            }
+            ']' => {
+                if let Some((open, is_image)) = openers.pop() {
+                    let text = chars[open + 1..i].iter().collect::<String>();
+                    let rest = &chars[i + 1..];
+                    let reference = match rest.first() {
+                        // Inline link
+                        Some('(') => None,
+                        Some('[') => rest[1..].iter().position(|&c| c == ']').and_then(|len| {
+                            let label = rest[1..1 + len].iter().collect::<String>();
+                            let reference = if label.trim().is_empty() {
+                                LinkReference {
+                                    label: text,
+                                    form: ReferenceForm::Collapsed,
+                                }
+                            } else {
+                                LinkReference {
+                                    label,
+                                    form: ReferenceForm::Full,
+                                }
+                            };
+                            is_defined(&reference.label).then(|| {
+                                i += len + 2;
+                                reference
+                            })
+                        }),
+                        _ => is_defined(&text).then_some(LinkReference {
+                            label: text,
+                            form: ReferenceForm::Shortcut,
+                        }),
+                    };
+                    if let Some(reference) = reference {
+                        tokens.push((open, is_image, reference));
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    tokens.sort_by_key(|(open, _, _)| *open);
+    tokens
+        .into_iter()
+        .map(|(_, is_image, reference)| (is_image, reference))
+        .collect()
+}
+
+/// Link and Image nodes in document order, except in image descriptions
+fn ast_links<'a>(
+    ast_node: &'a comrak::nodes::AstNode<'a>,
+    links: &mut Vec<&'a comrak::nodes::AstNode<'a>>,
+) {
+    use comrak::nodes::NodeValue::*;
+    for child in ast_node.children() {
+        match &child.data.borrow().value {
+            Image(_) => links.push(child),
+            Link(_) => {
+                links.push(child);
+                ast_links(child, links);
+            }
+            _ => ast_links(child, links),
+        }
+    }
+}
+
+/// Link text is URL itself, autolink `<...>` or bare URL
+fn is_autolink_text<'a>(
+    ast_node: &'a comrak::nodes::AstNode<'a>,
+    nl: &comrak::nodes::NodeLink,
+) -> bool {
+    let text = match ast_node.first_child() {
+        Some(child) if child.next_sibling().is_none() => match &child.data.borrow().value {
+            comrak::nodes::NodeValue::Text(t) => from_utf8(t),
+            _ => return false,
+        },
+        _ => return false,
+    };
+    let url = from_utf8(&nl.url);
+    url == text || url == format!("http://{}", text) || url == format!("mailto:{}", text)
+}
+
+/// Set references to `<a>` and `<img>` in document order
+fn set_link_references<I: Iterator<Item = Option<LinkReference>>>(
+    xml_elm: &mut minidom::Element,
+    references: &mut I,
+) {
+    for child in xml_elm.children_mut() {
+        match child.name() {
+            "header" | "pre" => {}
+            "a" | "img" => {
+                if let Some(reference) = references.next().flatten() {
+                    child.set_attr("label", reference.label);
+                    child.set_attr("reference", reference.form.as_str());
+                }
+                if child.name() == "a" {
+                    set_link_references(child, references);
+                }
+            }
+            _ => set_link_references(child, references),
+        }
+    }
+}
+
+/// `<nav>` of link reference definitions
+fn xml_from_link_definitions(definitions: &[LinkDefinition]) -> minidom::Element {
+    minidom::Element::builder("nav", NS)
+        .append_all(definitions.iter().map(|definition| {
+            minidom::Element::builder("link", NS)
+                .attr("label", &definition.label)
+                .attr("href", &definition.url)
+                .attr("title", &definition.title)
+                .build()
+        }))
+        .build()
+}
+
+/// Link reference definitions in `<nav>`
+fn link_definitions_from_xml(xml_elm: &minidom::Element) -> Vec<LinkDefinition> {
+    xml_elm
+        .children()
+        .filter(|child| child.name() == "link")
+        .map(|child| LinkDefinition {
+            label: child.attr("label").unwrap_or("").to_string(),
+            url: child.attr("href").unwrap_or("").to_string(),
+            title: child.attr("title").unwrap_or("").to_string(),
+        })
+        .collect()
+}
+
+/// Reference links of Comrak AST created from XML DOM
+///
+/// Links are written as references only if the definition has the same destination and title,
+/// since links and definitions may be rewritten separately.
+fn link_references_from_xml<'a>(
+    xml_root: &minidom::Element,
+    ast_root: &'a comrak::nodes::AstNode<'a>,
+) -> LinkReferences {
+    fn walk<'e>(
+        xml_elm: &'e minidom::Element,
+        links: &mut Vec<&'e minidom::Element>,
+        definitions: &mut HashMap<String, (String, String)>,
+    ) {
+        for child in xml_elm.children() {
+            match child.name() {
+                "header" | "pre" => {}
+                "nav" => {
+                    for definition in link_definitions_from_xml(child) {
+                        definitions
+                            .entry(normalize_label(&definition.label))
+                            .or_insert((definition.url, definition.title));
+                    }
+                }
+                "img" => links.push(child),
+                "a" => {
+                    links.push(child);
+                    walk(child, links, definitions);
+                }
+                _ => walk(child, links, definitions),
+            }
+        }
+    }
+    let mut xml_links = Vec::new();
+    let mut definitions = HashMap::new();
+    walk(xml_root, &mut xml_links, &mut definitions);
+    let mut ast_link_nodes = Vec::new();
+    ast_links(ast_root, &mut ast_link_nodes);
+
+    let mut references = LinkReferences::new();
+    for (xml_link, ast_link) in xml_links.into_iter().zip(ast_link_nodes) {
+        let label = match xml_link.attr("label") {
+            Some(label) => label,
+            None => continue,
+        };
+        let href = if xml_link.name() == "img" {
+            "src"
+        } else {
+            "href"
+        };
+        let destination = (
+            xml_link.attr(href).unwrap_or("").to_string(),
+            xml_link.attr("title").unwrap_or("").to_string(),
+        );
+        if definitions.get(&normalize_label(label)) == Some(&destination) {
+            references.insert(
+                crate::cmark_fmt::node_key(ast_link),
+                LinkReference {
+                    label: label.to_string(),
+                    form: ReferenceForm::from_attr(xml_link.attr("reference")),
+                },
+            );
+        }
+    }
+    references
 }
 
 /// Escape shortcode {{...}} with <!--{{...}}-->, {%...%} with <!--{%...%}-->
@@ -202,21 +1160,23 @@ fn unescape_all_shortcodes(escaped: &str) -> String {
 }
 
 /// Create XML DOM from Comrak AST
+/// Comrak keeps texts as bytes, they are always UTF-8 since parsed from &str
+fn from_utf8(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
 fn xml_from_ast<'a>(ast_node: &'a comrak::nodes::AstNode<'a>) -> minidom::node::Node {
     use comrak::nodes::{ListType::*, NodeValue::*};
     use minidom::node::Node;
     use minidom::Element;
-    use std::str::from_utf8;
     let ast = &ast_node.data.borrow();
 
     // Convert Markdown AST to XML nodes
     let xml_node = match &ast.value {
         Document => Node::Element(Element::bare("body", NS)),
-        FrontMatter(t) => Node::Element(
-            Element::builder("header", NS)
-                .append(std::str::from_utf8(t).unwrap())
-                .build(),
-        ),
+        FrontMatter(t) => {
+            Node::Element(Element::builder("header", NS).append(from_utf8(t)).build())
+        }
         BlockQuote => Node::Element(Element::bare("blockquote", NS)),
         List(nl) => {
             use comrak::nodes::{ListDelimType::*, ListType::*};
@@ -227,6 +1187,7 @@ fn xml_from_ast<'a>(ast_node: &'a comrak::nodes::AstNode<'a>) -> minidom::node::
                 .attr("padding", nl.padding)
                 .attr("start", nl.start)
                 .attr("delimiter", if nl.delimiter == Period { "." } else { ")" })
+                .attr("bullet", bullet_from_ast(nl))
                 .attr("tight", nl.tight as i32)
                 .build();
             Node::Element(elm)
@@ -239,6 +1200,7 @@ fn xml_from_ast<'a>(ast_node: &'a comrak::nodes::AstNode<'a>) -> minidom::node::
                 .attr("padding", nl.padding)
                 .attr("start", nl.start)
                 .attr("delimiter", if nl.delimiter == Period { "." } else { ")" })
+                .attr("bullet", bullet_from_ast(nl))
                 .attr("tight", nl.tight as i32)
                 .build();
             Node::Element(elm)
@@ -254,26 +1216,34 @@ fn xml_from_ast<'a>(ast_node: &'a comrak::nodes::AstNode<'a>) -> minidom::node::
         DescriptionDetails => Node::Element(Element::bare("dd", NS)),
         CodeBlock(cb) => Node::Element(
             Element::builder("pre", NS)
-                .attr("info", from_utf8(&cb.info).unwrap())
-                .append(from_utf8(&cb.literal).unwrap())
+                .attr("info", from_utf8(&cb.info))
+                .attr("fenced", cb.fenced as i32)
+                .attr(
+                    "fence",
+                    cb.fenced.then(|| char::from(cb.fence_char).to_string()),
+                )
+                .attr("length", cb.fenced.then_some(cb.fence_length))
+                .attr("offset", cb.fenced.then_some(cb.fence_offset))
+                .append(from_utf8(&cb.literal))
                 .build(),
         ),
         HtmlBlock(hb) => Node::Element(
             Element::builder("object", NS)
                 .attr("type", hb.block_type as i32)
-                .attr("literal", from_utf8(&hb.literal).unwrap())
+                .attr("literal", from_utf8(&hb.literal))
                 .build(),
         ),
         Paragraph => Node::Element(Element::bare("p", NS)),
         Heading(hd) => Node::Element(
             Element::builder(format!("h{}", hd.level), NS)
                 .attr("level", hd.level)
+                .attr("setext", hd.setext as i32)
                 .build(),
         ),
         ThematicBreak => Node::Element(Element::bare("hr", NS)),
         FootnoteDefinition(t) => Node::Element(
             Element::builder("footer", NS)
-                .attr("name", from_utf8(t).unwrap())
+                .attr("name", from_utf8(t))
                 .build(),
         ),
         Table(align) => {
@@ -304,7 +1274,7 @@ fn xml_from_ast<'a>(ast_node: &'a comrak::nodes::AstNode<'a>) -> minidom::node::
         TableCell => Node::Element(Element::bare("td", NS)),
         Text(t) => {
             // Remove escape prefix (Workaround for shortcode escape)
-            Node::Text(String::from_utf8(t.clone()).unwrap().replace("$$$$", ""))
+            Node::Text(from_utf8(t).replace("$$$$", ""))
         }
         TaskItem(checked) => Node::Element(
             Element::builder("input", NS)
@@ -315,12 +1285,13 @@ fn xml_from_ast<'a>(ast_node: &'a comrak::nodes::AstNode<'a>) -> minidom::node::
         LineBreak => Node::Element(Element::bare("br", NS)),
         Code(t) => Node::Element(
             Element::builder("code", NS)
-                .attr("literal", from_utf8(&t.literal).unwrap())
+                .attr("literal", from_utf8(&t.literal))
+                .attr("backticks", t.num_backticks)
                 .build(),
         ),
         HtmlInline(t) => Node::Element(
             Element::builder("embed", NS)
-                .attr("literal", from_utf8(t).unwrap())
+                .attr("literal", from_utf8(t))
                 .build(),
         ),
         Emph => Node::Element(Element::bare("em", NS)),
@@ -329,19 +1300,19 @@ fn xml_from_ast<'a>(ast_node: &'a comrak::nodes::AstNode<'a>) -> minidom::node::
         Superscript => Node::Element(Element::bare("sup", NS)),
         Link(url) => Node::Element(
             Element::builder("a", NS)
-                .attr("href", from_utf8(&url.url).unwrap())
-                .attr("title", from_utf8(&url.title).unwrap())
+                .attr("href", from_utf8(&url.url))
+                .attr("title", from_utf8(&url.title))
                 .build(),
         ),
         Image(url) => Node::Element(
             Element::builder("img", NS)
-                .attr("src", from_utf8(&url.url).unwrap())
-                .attr("title", from_utf8(&url.title).unwrap())
+                .attr("src", from_utf8(&url.url))
+                .attr("title", from_utf8(&url.title))
                 .build(),
         ),
         FootnoteReference(t) => Node::Element(
             Element::builder("sub", NS)
-                .attr("name", from_utf8(t).unwrap())
+                .attr("name", from_utf8(t))
                 .build(),
         ),
     };
@@ -354,7 +1325,7 @@ fn xml_from_ast<'a>(ast_node: &'a comrak::nodes::AstNode<'a>) -> minidom::node::
                 if let Some(alt_node) = ast_node.first_child() {
                     if let Text(alt) = &alt_node.data.borrow().value {
                         // with alt
-                        xml_elm.set_attr("alt", from_utf8(alt).unwrap())
+                        xml_elm.set_attr("alt", from_utf8(alt))
                     }
                 };
             }
@@ -373,9 +1344,12 @@ fn xml_from_ast<'a>(ast_node: &'a comrak::nodes::AstNode<'a>) -> minidom::node::
 }
 
 /// Create Comrak AST from XML DOM
+///
+/// Source formatting not kept in AST nodes is recorded in `formats`.
 fn ast_from_xml<'a>(
     arena: &'a comrak::Arena<comrak::nodes::AstNode<'a>>,
     xml_elm: &minidom::Element,
+    formats: &mut SourceFormats,
 ) -> &'a comrak::nodes::AstNode<'a> {
     use comrak::nodes::NodeValue::*;
 
@@ -383,8 +1357,8 @@ fn ast_from_xml<'a>(
         "body" => Document,
         "header" => FrontMatter(xml_elm.text().into_bytes()),
         "blockquote" => BlockQuote,
-        "ul" | "ol" => List(node_list_from_xml(&xml_elm)),
-        "li" => Item(node_list_from_xml(&xml_elm)),
+        "ul" | "ol" => List(node_list_from_xml(xml_elm)),
+        "li" => Item(node_list_from_xml(xml_elm)),
         "dl" => DescriptionList,
         "di" => DescriptionItem(comrak::nodes::NodeDescriptionItem {
             marker_offset: xml_elm.attr("offset").map_or(0, |v| v.parse().unwrap_or(0)),
@@ -395,10 +1369,13 @@ fn ast_from_xml<'a>(
         "dt" => DescriptionTerm,
         "dd" => DescriptionDetails,
         "pre" => CodeBlock(comrak::nodes::NodeCodeBlock {
-            fenced: true,
-            fence_char: b'`',
-            fence_length: 3,
-            fence_offset: 0,
+            // fence_char and fence_length 0 are chosen when writing CommonMark
+            fenced: xml_elm.attr("fenced") != Some("0"),
+            fence_char: xml_elm
+                .attr("fence")
+                .map_or(0, |v| v.bytes().next().unwrap_or(0)),
+            fence_length: xml_elm.attr("length").map_or(0, |v| v.parse().unwrap_or(0)),
+            fence_offset: xml_elm.attr("offset").map_or(0, |v| v.parse().unwrap_or(0)),
             info: Vec::from(xml_elm.attr("info").unwrap_or("")),
             literal: xml_elm.text().into(),
         }),
@@ -409,7 +1386,7 @@ fn ast_from_xml<'a>(
         "p" => Paragraph,
         "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => Heading(comrak::nodes::NodeHeading {
             level: xml_elm.attr("level").map_or(1, |v| v.parse().unwrap_or(1)),
-            setext: false,
+            setext: xml_elm.attr("setext") == Some("1"),
         }),
         "hr" => ThematicBreak,
         "footer" => FootnoteDefinition(Vec::from(xml_elm.attr("name").unwrap_or(""))),
@@ -417,7 +1394,7 @@ fn ast_from_xml<'a>(
             use comrak::nodes::TableAlignment::*;
             let align = xml_elm
                 .attr("align")
-                .unwrap()
+                .unwrap_or("")
                 .chars()
                 .map(|c| match c {
                     'l' => Left,
@@ -435,7 +1412,10 @@ fn ast_from_xml<'a>(
         "wbr" => SoftBreak,
         "br" => LineBreak,
         "code" => Code(comrak::nodes::NodeCode {
-            num_backticks: 1,
+            // 0 is chosen when writing CommonMark
+            num_backticks: xml_elm
+                .attr("backticks")
+                .map_or(0, |v| v.parse().unwrap_or(0)),
             literal: Vec::from(xml_elm.attr("literal").unwrap_or("")),
         }),
         "embed" => HtmlInline(Vec::from(xml_elm.attr("literal").unwrap_or(""))),
@@ -452,13 +1432,27 @@ fn ast_from_xml<'a>(
             title: Vec::from(xml_elm.attr("title").unwrap_or("")),
         }),
         "sub" => FootnoteReference(Vec::from(xml_elm.attr("name").unwrap_or(""))),
+        "nav" => HtmlBlock(comrak::nodes::NodeHtmlBlock {
+            block_type: 0,
+            literal: crate::cmark_fmt::format_link_definitions(&link_definitions_from_xml(xml_elm))
+                .into_bytes(),
+        }),
         _ => Text(vec![]), // empty text for unknown XML element
     };
 
     let ast_node = arena.alloc(comrak::nodes::AstNode::from(nodeval));
+    if xml_elm.name() == "nav" && xml_elm.attr("tight") == Some("1") {
+        formats.tight_definitions.insert(node_key(ast_node));
+    }
+    if let Some(underline) = xml_elm.attr("underline").and_then(|v| v.parse().ok()) {
+        formats.underlines.insert(node_key(ast_node), underline);
+    }
+    if xml_elm.name() == "code" && xml_elm.attr("padded") == Some("1") {
+        formats.padded_codes.insert(node_key(ast_node));
+    }
 
     match xml_elm.name() {
-        "header" | "pre" => {
+        "header" | "pre" | "nav" => {
             // Already parsed child texts
         }
         "img" => {
@@ -476,7 +1470,7 @@ fn ast_from_xml<'a>(
                 match xml_child {
                     minidom::Node::Element(element) => {
                         // recursively parse elements
-                        let ast_child = ast_from_xml(arena, element);
+                        let ast_child = ast_from_xml(arena, element, formats);
                         ast_node.append(ast_child);
                     }
                     minidom::Node::Text(text) => {
@@ -507,25 +1501,40 @@ fn node_list_from_xml(xml_elm: &minidom::Element) -> comrak::nodes::NodeList {
             .attr("padding")
             .map_or(0, |v| v.parse().unwrap_or(0)),
         start: xml_elm.attr("start").map_or(0, |v| v.parse().unwrap_or(0)),
-        delimiter: comrak::nodes::ListDelimType::Period,
-        bullet_char: b'-',
+        delimiter: if Some(")") == xml_elm.attr("delimiter") {
+            comrak::nodes::ListDelimType::Paren
+        } else {
+            comrak::nodes::ListDelimType::Period
+        },
+        bullet_char: xml_elm
+            .attr("bullet")
+            .map_or(0, |v| v.bytes().next().unwrap_or(0)),
         tight: Some("1") == xml_elm.attr("tight"),
     }
 }
 
+/// Bullet character of XML element, None for ordered list
+fn bullet_from_ast(nl: &comrak::nodes::NodeList) -> Option<String> {
+    if nl.list_type == comrak::nodes::ListType::Bullet {
+        Some(char::from(nl.bullet_char).to_string())
+    } else {
+        None
+    }
+}
+
 /// Comrak options for CommonMark-XML conversion
-fn comrak_options() -> comrak::ComrakOptions {
+fn comrak_options(extensions: &CmarkExtensions) -> comrak::ComrakOptions {
     comrak::ComrakOptions {
         extension: comrak::ComrakExtensionOptions {
-            strikethrough: true,
+            strikethrough: extensions.strikethrough,
             tagfilter: false,
-            table: true,
-            autolink: false,
-            tasklist: false,
-            superscript: false,
+            table: extensions.table,
+            autolink: extensions.autolink,
+            tasklist: extensions.tasklist,
+            superscript: extensions.superscript,
             header_ids: None,
-            footnotes: false,
-            description_lists: false,
+            footnotes: extensions.footnotes,
+            description_lists: extensions.description_lists,
             front_matter_delimiter: Some(String::from("+++")),
         },
         parse: comrak::ComrakParseOptions {
@@ -571,13 +1580,41 @@ mod tests {
         assert_eq!(result.2, Some("\ntitle = \"Sample\"\n".to_string()));
     }
 
+    #[test]
+    fn test_read_cmark_with_frontmatter_json_frontmatter() {
+        let cmark_text = ";;;\n{\"title\": \"Sample\"}\n;;;\nThis is a test";
+        let mut reader = Cursor::new(cmark_text);
+        let result = read_cmark_with_frontmatter(&mut reader).unwrap();
+        assert_eq!(result.0, "\nThis is a test");
+        assert_eq!(result.1, ";;;");
+        assert_eq!(result.2, Some("\n{\"title\": \"Sample\"}\n".to_string()));
+
+        // Without delimiter
+        let cmark_text = "{\n  \"title\": \"{Sample}\"\n}\nThis is a test";
+        let mut reader = Cursor::new(cmark_text);
+        let result = read_cmark_with_frontmatter(&mut reader).unwrap();
+        assert_eq!(result.0, "\nThis is a test");
+        assert_eq!(result.1, "");
+        assert_eq!(
+            result.2,
+            Some("{\n  \"title\": \"{Sample}\"\n}".to_string())
+        );
+
+        // Shortcode is not frontmatter
+        let cmark_text = "{{< note >}}\nThis is a test";
+        let mut reader = Cursor::new(cmark_text);
+        let result = read_cmark_with_frontmatter(&mut reader).unwrap();
+        assert_eq!(result.0, cmark_text);
+        assert_eq!(result.2, None);
+    }
+
     #[test]
     fn test_xml_from_cmark() {
         let cmark_text = "This is **bold** and *italic*";
         let expected_xml =
             "<body xmlns='markdown'><p>This is <strong>bold</strong> and <em>italic</em></p></body>";
 
-        let xml = xml_from_cmark(cmark_text, false);
+        let xml = xml_from_cmark(cmark_text, false).unwrap();
         assert_eq!(xml, expected_xml);
     }
 
@@ -609,7 +1646,222 @@ mod tests {
         let expected_cmark = "This is **bold** and *italic*\n";
 
         let xml_root: Element = xml_str.parse().unwrap();
-        let cmark = cmark_from_xmldom(&xml_root, false);
+        let cmark = cmark_from_xmldom(&xml_root, false).unwrap();
         assert_eq!(cmark, expected_cmark);
     }
+
+    #[test]
+    fn test_cmark_round_trip() {
+        let corpus = [
+            // headings
+            "Title\n=====\n\nSection\n-------\n\n### Sub section\n\nText.\n",
+            "Title\n===\n\nLong section\n-\n\n> Quoted\n> title\n> ==========\n",
+            // code blocks
+            "~~~\nplain\n~~~\n\n````markdown\n```rust\n```\n````\n\n  ```toml\n  a = 1\n\n  b = 2\n  ```\n",
+            // code spans
+            "Use `a`, ``b`` and ``` c`` ```.\n",
+            // bullet lists
+            "* one\n* two\n\n+ three\n\n  four\n\n+ five\n",
+            "- one\n  - nested\n    - deep\n- two\n",
+            "-   wide\n-   marker\n",
+            // ordered lists
+            "1) first\n2) second\n\n3. third\n4. fourth\n",
+            "1. one\n1. one\n1. one\n",
+            "9. nine\n10. ten\n",
+            // block quotes
+            "> quote\n>\n> - item\n>\n> ```\n> code\n> ```\n",
+            // inlines
+            "Some *em*, **strong**, [link](https://example.com \"title\") and ![img](a.png).\nNext line\\\nbreak and 2 * 3 = 6, snake_case.\n",
+        ];
+        for cmark_text in corpus {
+            let xml = xml_from_cmark(cmark_text, false).unwrap();
+            let cmark = cmark_from_xml(&xml, false).unwrap();
+            assert_eq!(cmark, cmark_text);
+        }
+    }
+
+    #[test]
+    fn test_cmark_from_xml_nested_fence() {
+        // Fence recorded in XML is too short for code containing fence
+        let xml_str = "<body xmlns=\"markdown\"><pre info=\"markdown\" fenced=\"1\" fence=\"`\" length=\"3\" offset=\"0\">```rust\nlet a = 1;\n```\n</pre></body>";
+        let cmark = cmark_from_xml(xml_str, false).unwrap();
+        assert_eq!(cmark, "````markdown\n```rust\nlet a = 1;\n```\n````\n");
+
+        // Without fence attributes
+        let xml_str = "<body xmlns=\"markdown\"><pre info=\"\">````\n```\n</pre></body>";
+        let cmark = cmark_from_xml(xml_str, false).unwrap();
+        assert_eq!(cmark, "`````\n````\n```\n`````\n");
+
+        let cmark_text = "`````md\n````\n```\n````\n`````\n";
+        let xml = xml_from_cmark(cmark_text, false).unwrap();
+        assert_eq!(cmark_from_xml(&xml, false).unwrap(), cmark_text);
+    }
+
+    #[test]
+    fn test_cmark_from_xml_tilde_fence() {
+        let cmark_text = "~~~\n```\nnot closed\n~~~\n\n~~~~`info`\n~~~\n~~~~\n";
+        let xml = xml_from_cmark(cmark_text, false).unwrap();
+        assert_eq!(cmark_from_xml(&xml, false).unwrap(), cmark_text);
+
+        // Backtick fence can not have info string containing backtick
+        let xml_str = "<body xmlns=\"markdown\"><pre info=\"`a`\" fenced=\"1\" fence=\"`\" length=\"3\" offset=\"0\">code\n</pre></body>";
+        let cmark = cmark_from_xml(xml_str, false).unwrap();
+        assert_eq!(cmark, "~~~`a`\ncode\n~~~\n");
+    }
+
+    #[test]
+    fn test_cmark_from_xml_indented_code_block() {
+        let cmark_text = "Code:\n\n    let a = 1;\n\n    let b = 2;\n\n- item\n\n      indented\n";
+        let xml = xml_from_cmark(cmark_text, false).unwrap();
+        assert_eq!(cmark_from_xml(&xml, false).unwrap(), cmark_text);
+    }
+
+    #[test]
+    fn test_cmark_from_xml_code_span_backticks() {
+        let cmark_text = "Use `` a`b `` and ``` `` ```.\n";
+        let xml = xml_from_cmark(cmark_text, false).unwrap();
+        let cmark = cmark_from_xml(&xml, false).unwrap();
+        assert_eq!(cmark, cmark_text);
+
+        // Padding is written back only where it is in source
+        let cmark_text = "`` a`b `` and ``a`b``, ` a ` and `  b  `, `` ` `` and ``\na\n``.\n";
+        let xml = xml_from_cmark(cmark_text, false).unwrap();
+        let cmark = cmark_from_xml(&xml, false).unwrap();
+        assert_eq!(
+            cmark,
+            "`` a`b `` and ``a`b``, ` a ` and `  b  `, `` ` `` and `` a ``.\n"
+        );
+
+        // Backticks recorded in XML are the same as backticks in code
+        let xml_str =
+            "<body xmlns=\"markdown\"><p><code literal=\"a`b\" backticks=\"1\"/></p></body>";
+        let cmark = cmark_from_xml(xml_str, false).unwrap();
+        assert_eq!(cmark, "``a`b``\n");
+    }
+
+    #[test]
+    fn test_cmark_round_trip_extensions() {
+        let extensions = CmarkExtensions {
+            description_lists: true,
+            ..Default::default()
+        };
+        let cmark_texts = [
+            // footnotes keep their labels
+            "Text with a note[^note] and another[^2].\n\n[^note]: Footnote text.\n\n[^2]: Second note.\n\n    With code.\n",
+            // task list
+            "- [x] done\n- [ ] todo\n",
+            // bare autolinks
+            "Visit https://example.com or www.example.com, or mail foo@example.com.\n",
+            // description list
+            "Term\n\n: Details here\n\nTerm 2\n\n: More\n  continued\n",
+        ];
+        for cmark_text in cmark_texts {
+            let xml_root = xmldom_from_cmark_with_extensions(cmark_text, false, &extensions);
+            let cmark = cmark_from_xmldom_with_extensions(&xml_root, false, &extensions).unwrap();
+            assert_eq!(cmark, cmark_text);
+        }
+
+        // superscript conflicts with footnote references
+        let extensions = CmarkExtensions {
+            footnotes: false,
+            superscript: true,
+            ..Default::default()
+        };
+        let cmark_text = "E = mc^2^ and 2 ^ 3, not x\\^y\\^\n";
+        let xml_root = xmldom_from_cmark_with_extensions(cmark_text, false, &extensions);
+        let cmark = cmark_from_xmldom_with_extensions(&xml_root, false, &extensions).unwrap();
+        assert_eq!(cmark, cmark_text);
+    }
+
+    #[test]
+    fn test_cmark_footnote_definitions() {
+        // Footnote definitions are kept at their position, also if never referenced
+        let cmark_texts = [
+            "[^a]: unused note\n\n[^b]: Second.\n\nText[^c] and[^b].\n\n[^c]: First.\n\nLast paragraph.\n",
+            "> Quote[^q].\n\n[^q]: Note.\n\n- Item[^i]\n\n[^i]: Item note.\n\n[^u]: Unused.\n",
+        ];
+        for cmark_text in cmark_texts {
+            let xml_root = xmldom_from_cmark(cmark_text, false);
+            let cmark = cmark_from_xmldom(&xml_root, false).unwrap();
+            assert_eq!(cmark, cmark_text);
+        }
+
+        // Unclosed code fence swallows probe paragraph, referenced definitions are still in place
+        let cmark_text = "[^a]: Note.\n\n[^u]: Unused.\n\nText[^a].\n\n```\ncode\n";
+        let xml_root = xmldom_from_cmark(cmark_text, false);
+        let cmark = cmark_from_xmldom(&xml_root, false).unwrap();
+        assert_eq!(cmark, "[^a]: Note.\n\nText[^a].\n\n```\ncode\n```\n");
+    }
+
+    #[test]
+    fn test_cmark_round_trip_link_references() {
+        let cmark_text = "See [the docs][docs], [Motoko][] or [Candid].\n\n![logo][img] and [same url](https://example.com/docs).\n\n```\n[docs]: https://example.com/code\n```\n\n[docs]: https://example.com/docs \"Docs\"\n[Motoko]: <https://example.com/motoko guide>\n[candid]: https://example.com/candid\n[img]: ./logo.png\n\n[unused]: https://example.com/unused\n";
+        let xml_root = xmldom_from_cmark(cmark_text, false);
+        let cmark = cmark_from_xmldom(&xml_root, false).unwrap();
+        assert_eq!(cmark, cmark_text);
+
+        let xml = xml_from_cmark("[a][x] [b](/y)\n\n[x]: /y\n", false).unwrap();
+        assert_eq!(
+            xml,
+            "<body xmlns='markdown'><p><a href=\"/y\" label=\"x\" reference=\"full\" title=\"\">a</a> <a href=\"/y\" title=\"\">b</a></p><nav><link href=\"/y\" label=\"x\" title=\"\"/></nav></body>"
+        );
+
+        // Definitions in block quotes and list items, and followed by paragraph
+        let cmark_texts = [
+            "> [x]: /y\n\nText [x].\n",
+            "- a\n\n  [x]: /y\n\n- b [x]\n",
+            "- [x]: /y\n- b [x]\n",
+            "Text.\n\n1. a\n   > [x]: /y\n   > [z]: /z\n   > Quote [x] [z].\n",
+            "[a]: https://a.com\nText after def [a].\n",
+        ];
+        for cmark_text in cmark_texts {
+            let xml_root = xmldom_from_cmark(cmark_text, false);
+            assert!(!xml_root.children().any(|e| e.name() == "object"));
+            let cmark = cmark_from_xmldom(&xml_root, false).unwrap();
+            assert_eq!(cmark, cmark_text);
+        }
+        let xml = xml_from_cmark("> [x]: /y\n> Quote [x].\n", false).unwrap();
+        assert_eq!(
+            xml,
+            "<body xmlns='markdown'><blockquote><nav tight=\"1\"><link href=\"/y\" label=\"x\" title=\"\"/></nav><p>Quote <a href=\"/y\" label=\"x\" reference=\"shortcut\" title=\"\">x</a>.</p></blockquote></body>"
+        );
+    }
+
+    #[test]
+    fn test_cmark_from_xml_link_references() {
+        // Link text is translated
+        let xml_str = "<body xmlns='markdown'><p><a href=\"/y\" label=\"X\" reference=\"shortcut\" title=\"\">translated</a> <a href=\"/y\" label=\"X\" reference=\"shortcut\" title=\"\">x</a>(1)</p><nav><link href=\"/y\" label=\"X\" title=\"\"/></nav></body>";
+        let cmark = cmark_from_xml(xml_str, false).unwrap();
+        assert_eq!(cmark, "[translated][X] [x][](1)\n\n[X]: /y\n");
+
+        // Link rewritten without definition is written as inline link
+        let xml_str = "<body xmlns='markdown'><p><a href=\"/z\" label=\"x\" reference=\"full\" title=\"\">a</a></p><nav><link href=\"/y\" label=\"x\" title=\"\"/></nav></body>";
+        let cmark = cmark_from_xml(xml_str, false).unwrap();
+        assert_eq!(cmark, "[a](/z)\n\n[x]: /y\n");
+    }
+
+    #[test]
+    fn test_rewrite_links() {
+        let cmark_text = "See [intro](intro.md) and [site](https://example.com)";
+        let mut xml_root = xmldom_from_cmark(cmark_text, false);
+        rewrite_links(&mut xml_root, &|href: &str| {
+            href.strip_suffix(".md")
+                .map(|stem| format!("{}.ja.md", stem))
+        });
+        let cmark = cmark_from_xmldom(&xml_root, false).unwrap();
+        assert_eq!(
+            cmark,
+            "See [intro](intro.ja.md) and [site](https://example.com)\n"
+        );
+
+        // Reference links and definitions are rewritten together
+        let cmark_text = "See [intro][].\n\n[intro]: intro.md\n";
+        let mut xml_root = xmldom_from_cmark(cmark_text, false);
+        rewrite_links(&mut xml_root, &|href: &str| {
+            href.strip_suffix(".md")
+                .map(|stem| format!("{}.ja.md", stem))
+        });
+        let cmark = cmark_from_xmldom(&xml_root, false).unwrap();
+        assert_eq!(cmark, "See [intro][].\n\n[intro]: intro.ja.md\n");
+    }
 }
@@ -3,72 +3,189 @@
 //! DeepL REST API wrapper
 //!
 
-use regex::Regex;
-use reqwest_middleware::ClientBuilder;
+use crate::cmark_xml::CmarkExtensions;
+use crate::error::{Error, Result};
+use crate::frontmatter::{FrontmatterKeys, DEFAULT_FRONTMATTER_KEYS};
+pub use crate::language::{DeeplLanguage, SourceLanguage, TargetLanguage};
+use crate::memory::TranslationMemory;
+use crate::ratelimit::{RateLimitMiddleware, RateLimiter};
+use crate::translator::{resolve_glossary_id, Translator};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
 use reqwest_tracing::TracingMiddleware;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 pub const MAX_TRANSLATE_LENGTH: usize = 500_000;
 
+/// Default size limit of texts in one request, DeepL rejects request body larger than 128 KiB
+pub const MAX_REQUEST_BYTES: usize = 120 * 1024;
+
+/// Default rate limit of API requests
+pub const DEFAULT_REQUESTS_PER_SECOND: f64 = 5.0;
+
+/// Tags whose content should not be translated
+pub(crate) const IGNORE_TAGS: &str =
+    "header,embed,object,pre,code,style,script,nav,link,ignore-tag";
+/// Tags which split sentences
+pub(crate) const SPLITTING_TAGS: &str = "blockquote,li,dt,dd,p,h1,h2,h3,h4,h5,h6,th,td";
+/// Tags which do not split sentences
+pub(crate) const NON_SPLITTING_TAGS: &str = "embed,em,strong,del,a,img";
+
 #[derive(Debug, Clone)]
 pub struct Deepl {
     pub config: DeeplConfig,
+    memory: Option<Arc<TranslationMemory>>,
+    /// HTTP client with retry and rate limit, shared by idempotent requests
+    client: ClientWithMiddleware,
+    /// HTTP client without retry, for glossary creation and deletion.
+    /// Retry after timeout may register the same glossary twice.
+    client_without_retry: ClientWithMiddleware,
+    /// Resolved glossary IDs by language pair, glossaries are listed once per run
+    glossary_ids: Arc<Mutex<HashMap<String, Option<String>>>>,
 }
 
 impl Deepl {
     // New DeepL instance from default config file (deepl.toml or ~/.deepl.toml)
-    pub fn new() -> std::io::Result<Self> {
+    pub fn new() -> Result<Self> {
         let deepl_config = DeeplConfig::new()?;
-
-        Ok(Self {
-            config: deepl_config,
-        })
+        Self::from_config(deepl_config)
     }
 
     /// New DeepL instance from specific config file
-    pub fn with_config<P: AsRef<std::path::Path>>(config_path: P) -> std::io::Result<Self> {
+    pub fn with_config<P: AsRef<std::path::Path>>(config_path: P) -> Result<Self> {
         let deepl_config = DeeplConfig::with_config(config_path)?;
+        Self::from_config(deepl_config)
+    }
+
+    // Open translation memory if configured
+    fn from_config(deepl_config: DeeplConfig) -> Result<Self> {
+        let memory = match deepl_config.translation_memory_path() {
+            Some(path) => Some(Arc::new(TranslationMemory::open(path)?)),
+            None => None,
+        };
+
+        let (client, client_without_retry) =
+            Self::build_clients(deepl_config.requests_per_second());
 
         Ok(Self {
             config: deepl_config,
+            memory,
+            client,
+            client_without_retry,
+            glossary_ids: Arc::default(),
         })
     }
 
-    /// Translate single text string
-    #[allow(dead_code)]
-    pub async fn translate(
-        &self,
-        from_lang: Language,
-        to_lang: Language,
-        formality: Formality,
-        body: &str,
-    ) -> reqwest::Result<String> {
-        let mut result = self
-            .translate_strings(from_lang, to_lang, formality, &vec![body])
+    // HTTP clients with and without retry, sharing the rate limit
+    fn build_clients(requests_per_second: f64) -> (ClientWithMiddleware, ClientWithMiddleware) {
+        // Retry up to 3 times with increasing intervals between attempts.
+        // Rate limiter is applied to each attempt, and honors Retry-After.
+        let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
+        let limiter = Arc::new(RateLimiter::new(requests_per_second));
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(TracingMiddleware::default())
+            .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+            .with(RateLimitMiddleware(limiter.clone()))
+            .build();
+        let client_without_retry = ClientBuilder::new(reqwest::Client::new())
+            .with(TracingMiddleware::default())
+            .with(RateLimitMiddleware(limiter))
+            .build();
+        (client, client_without_retry)
+    }
+
+    /// Change rate limit of API requests for this instance
+    pub fn with_requests_per_second(mut self, requests_per_second: f64) -> Self {
+        self.config.requests_per_second = Some(requests_per_second);
+        (self.client, self.client_without_retry) = Self::build_clients(requests_per_second);
+        self
+    }
+
+    // Get supported languages, `kind` is "source" or "target"
+    async fn languages(&self, kind: &str) -> Result<Vec<DeeplLanguage>> {
+        // Make DeepL API request
+        let resp = self
+            .client
+            .get(self.config.endpoint("languages"))
+            .header(
+                "authorization",
+                format!("DeepL-Auth-Key {}", self.config.api_key),
+            )
+            .query(&[("type", kind)])
+            .send()
             .await?;
-        if 0 < result.len() {
-            Ok(result.swap_remove(0))
-        } else {
-            // Empty response
-            Ok(String::new())
+
+        // Returns error
+        let resp = check_status(resp).await?;
+
+        // Parse response
+        Ok(resp.json::<Vec<DeeplLanguage>>().await?)
+    }
+
+    // Forget resolved glossary IDs, glossaries are changed
+    fn clear_glossary_ids(&self) {
+        self.glossary_ids
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    /// Disable translation memory for this instance
+    pub fn without_memory(mut self) -> Self {
+        self.memory = None;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl Translator for Deepl {
+    fn config(&self) -> &DeeplConfig {
+        &self.config
+    }
+
+    fn memory(&self) -> Option<&TranslationMemory> {
+        self.memory.as_deref()
+    }
+
+    async fn glossary_id(
+        &self,
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
+    ) -> Result<Option<String>> {
+        let key = format!("{}_{}", from_lang.as_langcode(), to_lang.as_langcode());
+        if let Some(id) = self
+            .glossary_ids
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&key)
+        {
+            return Ok(id.clone());
         }
+
+        let id = resolve_glossary_id(self, from_lang, to_lang).await?;
+        self.glossary_ids
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, id.clone());
+        Ok(id)
     }
 
-    pub async fn translate_strings(
+    async fn translate_strings(
         &self,
-        from_lang: Language,
-        to_lang: Language,
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
         formality: Formality,
-        body: &Vec<&str>,
-    ) -> reqwest::Result<Vec<String>> {
+        body: &[&str],
+    ) -> Result<Vec<String>> {
         let mut params = vec![
             ("source_lang", from_lang.as_langcode()),
             ("target_lang", to_lang.as_langcode()),
             ("preserve_formatting", "1"),
             ("formality", formality.to_str()),
         ];
-        if let Some(glossary_id) = self.config.glossary(from_lang, to_lang) {
+        let glossary_id = self.glossary_id(from_lang, to_lang).await?;
+        if let Some(glossary_id) = &glossary_id {
             log::debug!("Use glossary {}", glossary_id);
             params.push(("glossary_id", glossary_id));
         }
@@ -78,15 +195,9 @@ impl Deepl {
             params.push(("text", *t));
         }
 
-        // Retry up to 3 times with increasing intervals between attempts.
-        let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
-        let client = ClientBuilder::new(reqwest::Client::new())
-            .with(TracingMiddleware::default())
-            .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-            .build();
-
         // Make DeepL API request
-        let resp = client
+        let resp = self
+            .client
             .post(self.config.endpoint("translate"))
             .header(
                 "authorization",
@@ -94,11 +205,10 @@ impl Deepl {
             )
             .form(&params)
             .send()
-            .await
-            .unwrap();
+            .await?;
 
         // Returns error
-        resp.error_for_status_ref()?;
+        let resp = check_status(resp).await?;
 
         // Parse response
         let deepl_resp = resp.json::<DeeplTranslationResponse>().await?;
@@ -109,18 +219,45 @@ impl Deepl {
             .collect())
     }
 
+    /// Detect language by translating text without source language
+    ///
+    /// Characters of the text are counted in the usage, as well as translation.
+    async fn detect_language(
+        &self,
+        to_lang: TargetLanguage,
+        text: &str,
+    ) -> Result<Option<SourceLanguage>> {
+        // Make DeepL API request
+        let resp = self
+            .client
+            .post(self.config.endpoint("translate"))
+            .header(
+                "authorization",
+                format!("DeepL-Auth-Key {}", self.config.api_key),
+            )
+            .form(&[("target_lang", to_lang.as_langcode()), ("text", text)])
+            .send()
+            .await?;
+
+        // Returns error
+        let resp = check_status(resp).await?;
+
+        // Parse response
+        let deepl_resp = resp.json::<DeeplTranslationResponse>().await?;
+        Ok(deepl_resp
+            .translations
+            .first()
+            .and_then(|t| t.detected_source_language.parse().ok()))
+    }
+
     /// Translate XML string
-    pub async fn translate_xml(
+    async fn translate_xml(
         &self,
-        from_lang: Language,
-        to_lang: Language,
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
         formality: Formality,
-        target_name: &str,
         xml_body: &str,
-    ) -> reqwest::Result<String> {
-        // TODO: ignore_tags, splitting_tags, non_splitting_tags
-        let ignore_tags = "header,embed,object,pre,code,style,script,ignore-tag";
-
+    ) -> Result<String> {
         // Prepare request parameters
         let mut params = vec![
             ("source_lang", from_lang.as_langcode()),
@@ -128,40 +265,22 @@ impl Deepl {
             ("preserve_formatting", "1"),
             ("formality", formality.to_str()),
             ("tag_handling", "xml"),
-            ("ignore_tags", ignore_tags),
-            (
-                "splitting_tags",
-                "blockquote,li,dt,dd,p,h1,h2,h3,h4,h5,h6,th,td",
-            ),
-            ("non_splitting_tags", "embed,em,strong,del,a,img"),
+            ("ignore_tags", IGNORE_TAGS),
+            ("splitting_tags", SPLITTING_TAGS),
+            ("non_splitting_tags", NON_SPLITTING_TAGS),
         ];
 
-        let glossaries = self.list_glossaries().await.unwrap();
-        let glossary_map = glossaries
-            .into_iter()
-            .map(|x| (x.name.clone(), x))
-            .collect::<BTreeMap<_, _>>();
-
-        let glossary_id = if glossary_map.contains_key(target_name) {
-            glossary_map
-                .get(self.config.project_name.as_str())
-                .unwrap()
-                .glossary_id
-                .clone()
-        } else {
-            "".to_string()
-        };
-        if glossary_id != "".to_string() {
-            println!("Use glossary {}", glossary_id);
+        let glossary_id = self.glossary_id(from_lang, to_lang).await?;
+        if let Some(glossary_id) = &glossary_id {
             log::debug!("Use glossary {}", glossary_id);
-            params.push(("glossary_id", glossary_id.as_str().clone()));
+            params.push(("glossary_id", glossary_id));
         }
 
-        params.push(("text", &xml_body));
+        params.push(("text", xml_body));
 
         // Make DeepL API request
-        let client = reqwest::Client::new();
-        let resp = client
+        let resp = self
+            .client
             .post(self.config.endpoint("translate"))
             .header(
                 "authorization",
@@ -172,62 +291,32 @@ impl Deepl {
             .await?;
 
         // Returns error
-        resp.error_for_status_ref()?;
+        let resp = check_status(resp).await?;
 
         // Parse response
         let mut deepl_resp = resp.json::<DeeplTranslationResponse>().await?;
-        if 0 < deepl_resp.translations.len() {
-            Ok(deepl_resp.translations.swap_remove(0).text)
-        } else {
+        if deepl_resp.translations.is_empty() {
             // Empty response
             Ok(String::new())
+        } else {
+            Ok(deepl_resp.translations.swap_remove(0).text)
         }
     }
 
-    pub async fn add_ignore_tags(&self, target_name: &str, xml_body: &str) -> String {
-        let ignores = &self.config.ignores;
-        match ignores {
-            Some(ignores) => match ignores.get(target_name) {
-                Some(ignore_trans_words) => {
-                    let mut ignore_trans_words = ignore_trans_words.clone();
-                    ignore_trans_words.sort_by(|a, b| b.len().cmp(&a.len()));
-                    let replaced_xml_body =
-                        ignore_trans_words
-                            .iter()
-                            .fold(xml_body.clone().to_owned(), |acc, w| {
-                                let re = Regex::new(&format!(r###"(?i){}"###, w)).unwrap();
-                                re.replace_all(&acc, |caps: &regex::Captures| {
-                                    format!("<ignore-tag>{}</ignore-tag>", &caps[0])
-                                })
-                                .to_string()
-                            });
-                    replaced_xml_body
-                }
-                None => xml_body.to_string(),
-            },
-            None => xml_body.to_string(),
-        }
-    }
-
-    pub async fn remove_ignore_tags(&self, body: &str) -> String {
-        let re = Regex::new(r###"(<ignore-tag>|</ignore-tag>)"###).unwrap();
-        re.replace_all(&body, "").to_string()
-    }
-
     /// Register new glossary
-    pub async fn register_glossaries<S: AsRef<str>>(
+    async fn register_glossaries(
         &self,
         name: &str,
-        from_lang: Language,
-        to_lang: Language,
-        glossaries: &[(S, S)],
-    ) -> reqwest::Result<DeeplGlossary> {
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
+        glossaries: &[(&str, &str)],
+    ) -> Result<DeeplGlossary> {
         // Remove spaces, empty items
         let mut filtered_glossaries = glossaries
             .iter()
             .filter_map(|(from, to)| {
-                let from_trimed = from.as_ref().trim();
-                let to_trimed = to.as_ref().trim();
+                let from_trimed = from.trim();
+                let to_trimed = to.trim();
                 if from_trimed.is_empty() || to_trimed.is_empty() {
                     None
                 } else {
@@ -237,7 +326,7 @@ impl Deepl {
             .collect::<Vec<_>>();
 
         // Check duplicates
-        filtered_glossaries.sort_by(|(from1, _), (from2, _)| from1.cmp(from2));
+        filtered_glossaries.sort_by_key(|(from, _)| *from);
         filtered_glossaries.iter().fold("", |prev_from, (from, _)| {
             if prev_from == *from {
                 // Duplicated
@@ -258,8 +347,8 @@ impl Deepl {
             .join("\n");
 
         // Make DeepL API request
-        let client = reqwest::Client::new();
-        let resp = client
+        let resp = self
+            .client_without_retry
             .post(self.config.endpoint("glossaries"))
             .header(
                 "authorization",
@@ -268,31 +357,27 @@ impl Deepl {
             .form(&[
                 ("name", name),
                 ("source_lang", from_lang.as_langcode()),
-                ("target_lang", to_lang.as_langcode()),
+                ("target_lang", to_lang.glossary_langcode()),
                 ("entries_format", "tsv"),
                 ("entries", &tsv),
             ])
             .send()
             .await?;
 
-        if let Err(err) = resp.error_for_status_ref() {
-            // Returns error with printing details
-            if let Ok(err_body_text) = resp.text().await {
-                log::error!("{}", err_body_text);
-            }
-            Err(err)
-        } else {
-            // Success, parse response
-            let deepl_resp = resp.json::<DeeplGlossary>().await?;
-            Ok(deepl_resp)
-        }
+        // Returns error
+        let resp = check_status(resp).await?;
+
+        // Parse response
+        let deepl_resp = resp.json::<DeeplGlossary>().await?;
+        self.clear_glossary_ids();
+        Ok(deepl_resp)
     }
 
     /// List registered glossaries
-    pub async fn list_glossaries(&self) -> reqwest::Result<Vec<DeeplGlossary>> {
+    async fn list_glossaries(&self) -> Result<Vec<DeeplGlossary>> {
         // Make DeepL API request
-        let client = reqwest::Client::new();
-        let resp = client
+        let resp = self
+            .client
             .get(self.config.endpoint("glossaries"))
             .header(
                 "authorization",
@@ -302,18 +387,44 @@ impl Deepl {
             .await?;
 
         // Returns error
-        resp.error_for_status_ref()?;
+        let resp = check_status(resp).await?;
 
         // Parse response
         let deepl_resp = resp.json::<DeeplListGlossariesResponse>().await?;
         Ok(deepl_resp.glossaries)
     }
 
+    /// Get entries of registered glossary
+    async fn glossary_entries(&self, id: &str) -> Result<Vec<(String, String)>> {
+        // Make DeepL API request
+        let resp = self
+            .client
+            .get(self.config.endpoint(&format!("glossaries/{}/entries", id)))
+            .header(
+                "authorization",
+                format!("DeepL-Auth-Key {}", self.config.api_key),
+            )
+            .header("accept", "text/tab-separated-values")
+            .send()
+            .await?;
+
+        // Returns error
+        let resp = check_status(resp).await?;
+
+        // Parse TSV response
+        let tsv = resp.text().await?;
+        Ok(tsv
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect())
+    }
+
     /// Remove registered glossaries
-    pub async fn remove_glossary(&self, id: &str) -> reqwest::Result<()> {
+    async fn remove_glossary(&self, id: &str) -> Result<()> {
         // Make DeepL API request
-        let client = reqwest::Client::new();
-        let resp = client
+        let resp = self
+            .client_without_retry
             .delete(self.config.endpoint(&format!("glossaries/{}", id)))
             .header(
                 "authorization",
@@ -323,16 +434,27 @@ impl Deepl {
             .await?;
 
         // Check response
-        resp.error_for_status()?;
+        check_status(resp).await?;
+        self.clear_glossary_ids();
 
         Ok(())
     }
 
+    /// Supported source languages
+    async fn source_languages(&self) -> Result<Vec<DeeplLanguage>> {
+        self.languages("source").await
+    }
+
+    /// Supported target languages
+    async fn target_languages(&self) -> Result<Vec<DeeplLanguage>> {
+        self.languages("target").await
+    }
+
     /// Get usage, returns translated characters
-    pub async fn get_usage(&self) -> reqwest::Result<i32> {
+    async fn get_usage(&self) -> Result<i32> {
         // Make DeepL API request
-        let client = reqwest::Client::new();
-        let resp = client
+        let resp = self
+            .client
             .get(self.config.endpoint("usage"))
             .header(
                 "authorization",
@@ -342,7 +464,7 @@ impl Deepl {
             .await?;
 
         // Returns error
-        resp.error_for_status_ref()?;
+        let resp = check_status(resp).await?;
 
         // Parse response
         let deepl_resp = resp.json::<DeeplUsageResponse>().await?;
@@ -350,68 +472,29 @@ impl Deepl {
     }
 }
 
-#[derive(Clone, Copy, serde::Deserialize)]
-pub enum Language {
-    De,
-    Es,
-    En,
-    Fr,
-    It,
-    Ja,
-    Nl,
-    Pt,
-    PtBr,
-    Ru,
-}
-
-impl Language {
-    pub fn as_langcode(&self) -> &'static str {
-        match self {
-            Self::De => "de",
-            Self::Es => "es",
-            Self::En => "en",
-            Self::Fr => "fr",
-            Self::It => "it",
-            Self::Ja => "ja",
-            Self::Nl => "nl",
-            Self::Pt => "pt-br",
-            Self::PtBr => "pt-br",
-            Self::Ru => "ru",
-        }
-    }
-}
-
-impl std::str::FromStr for Language {
-    type Err = std::io::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let lowcase = s.to_ascii_lowercase();
-        match lowcase.as_str() {
-            "de" => Ok(Self::De),
-            "es" => Ok(Self::Es),
-            "en" => Ok(Self::En),
-            "fr" => Ok(Self::Fr),
-            "it" => Ok(Self::It),
-            "ja" => Ok(Self::Ja),
-            "nl" => Ok(Self::Nl),
-            "pt" => Ok(Self::Pt),
-            "pt-br" => Ok(Self::PtBr),
-            "ru" => Ok(Self::Ru),
-            _ => Err(std::io::Error::from(std::io::ErrorKind::InvalidInput)),
-        }
+/// Returns error with response body if status is not success
+async fn check_status(resp: reqwest::Response) -> Result<reqwest::Response> {
+    let status = resp.status();
+    if status.is_success() {
+        Ok(resp)
+    } else {
+        let message = resp.text().await.unwrap_or_default();
+        log::error!("HTTP {} : {}", status, message);
+        Err(Error::from_status(status, message))
     }
 }
 
 /// Translation output formality
-#[derive(Clone, Copy, serde::Deserialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
 pub enum Formality {
+    #[default]
     Default,
     Formal,
     Informal,
 }
 
 impl Formality {
-    pub fn to_str(&self) -> &'static str {
+    pub fn to_str(self) -> &'static str {
         match self {
             Self::Default => "default",
             Self::Formal => "prefer_more",
@@ -420,46 +503,55 @@ impl Formality {
     }
 }
 
-impl Default for Formality {
-    fn default() -> Self {
-        Self::Default
-    }
-}
-
 impl std::str::FromStr for Formality {
-    type Err = std::io::Error;
+    type Err = Error;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+    fn from_str(s: &str) -> Result<Self> {
         let lowcase = s.to_ascii_lowercase();
         match lowcase.as_str() {
             "default" => Ok(Self::Default),
             "formal" => Ok(Self::Formal),
             "informal" => Ok(Self::Informal),
-            _ => Err(std::io::Error::from(std::io::ErrorKind::InvalidInput)),
+            _ => Err(Error::Parse(format!("unknown formality \"{}\"", s))),
         }
     }
 }
 
-#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct DeeplConfig {
     api_key: String,
+    /// API base URL, e.g. "http://localhost:8080/v2/". Selected by API key if not specified.
+    pub endpoint: Option<String>,
     pub project_name: String,
+    /// Glossary name or ID used for translation, project_name if not specified
+    pub glossary: Option<String>,
     pub backup_original_text: bool,
     pub target_extensions: Option<HashMap<String, Vec<String>>>,
     glossaries: HashMap<String, HashMap<String, String>>,
-    ignores: Option<HashMap<String, Vec<String>>>,
+    pub(crate) ignores: Option<HashMap<String, Vec<String>>>,
+    /// Frontmatter keys to be translated, for each project and file extension
+    frontmatter_keys: Option<HashMap<String, HashMap<String, Vec<String>>>>,
+    /// CommonMark extensions, for each project. Kept as table to know explicitly set options
+    cmark_extensions: Option<HashMap<String, toml::value::Table>>,
+    /// Translation memory file (JSON lines), relative to config file
+    pub translation_memory: Option<String>,
+    /// Size limit of texts in one request (URL encoded bytes)
+    pub max_request_bytes: Option<usize>,
+    /// Rate limit of API requests
+    pub requests_per_second: Option<f64>,
+    /// Path of loaded config file
+    #[serde(skip)]
+    config_path: Option<std::path::PathBuf>,
 }
 
 impl DeeplConfig {
     // Search default config file
-    fn new() -> std::io::Result<Self> {
+    fn new() -> Result<Self> {
         use std::path::PathBuf;
         let config_files = [
             PathBuf::new().join("deepl.toml"),
-            dirs::home_dir()
-                .unwrap_or(PathBuf::new())
-                .join(".deepl.toml"),
+            dirs::home_dir().unwrap_or_default().join(".deepl.toml"),
         ];
 
         for config_file in config_files {
@@ -468,41 +560,64 @@ impl DeeplConfig {
                     log::debug!("Read config file {:?}", config_file);
                     return Ok(conf);
                 }
+                Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+                    log::debug!("Config file {:?} NOT found.", &config_file);
+                }
                 Err(err) => {
-                    if err.kind() == std::io::ErrorKind::NotFound {
-                        log::debug!("Config file {:?} NOT found.", &config_file);
-                    } else {
-                        // Other err, stop searching
-                        log::error!("Can not parse config file {:?} : {:?}", &config_file, err);
-                        return Err(err);
-                    }
+                    // Other err, stop searching
+                    log::error!("Can not parse config file {:?} : {:?}", &config_file, err);
+                    return Err(err);
                 }
             }
         }
 
         // Config file not found
-        Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "deepl.toml NOT found",
-        ))
+        Err(Error::Config(String::from("deepl.toml NOT found")))
     }
 
     // Config from specific file
-    fn with_config<P: AsRef<std::path::Path>>(config_path: P) -> std::io::Result<Self> {
+    fn with_config<P: AsRef<std::path::Path>>(config_path: P) -> Result<Self> {
         use std::io::Read;
         let mut file = std::fs::File::open(&config_path)?;
 
         // Read .deepl as TOML
         let mut config = String::new();
         file.read_to_string(&mut config)?;
-        let deepl_config: DeeplConfig = toml::from_str(&config)?;
+        let mut deepl_config: DeeplConfig = toml::from_str(&config)
+            .map_err(|e| Error::Config(format!("{} : {}", config_path.as_ref().display(), e)))?;
+        deepl_config.config_path = Some(config_path.as_ref().to_path_buf());
+
+        // Check frontmatter keys before translation
+        for keys in deepl_config
+            .frontmatter_keys
+            .iter()
+            .flat_map(|k| k.values())
+        {
+            for keys in keys.values() {
+                FrontmatterKeys::new(keys).map_err(|e| {
+                    Error::Config(format!("{} : {:?}", config_path.as_ref().display(), e))
+                })?;
+            }
+        }
 
         Ok(deepl_config)
     }
 
+    // Translation memory file path, relative path is resolved from config file directory
+    fn translation_memory_path(&self) -> Option<std::path::PathBuf> {
+        let path = std::path::Path::new(self.translation_memory.as_deref()?);
+        match self.config_path.as_deref().and_then(|p| p.parent()) {
+            Some(config_dir) if path.is_relative() => Some(config_dir.join(path)),
+            _ => Some(path.to_path_buf()),
+        }
+    }
+
     // DeepL endpoint URL
     fn endpoint(&self, api: &str) -> String {
-        if self.is_free_api_key() {
+        if let Some(base_url) = &self.endpoint {
+            // Custom endpoint, local mock server, proxy gateway, etc.
+            format!("{}/{}", base_url.trim_end_matches('/'), api)
+        } else if self.is_free_api_key() {
             // API free plan key
             format!("https://api-free.deepl.com/v2/{}", api)
         } else {
@@ -511,20 +626,108 @@ impl DeeplConfig {
         }
     }
 
+    // Size limit of texts in one request
+    pub fn max_request_bytes(&self) -> usize {
+        self.max_request_bytes.unwrap_or(MAX_REQUEST_BYTES)
+    }
+
+    // Rate limit of API requests
+    pub fn requests_per_second(&self) -> f64 {
+        self.requests_per_second
+            .unwrap_or(DEFAULT_REQUESTS_PER_SECOND)
+    }
+
     // Check API key is free plan
     pub fn is_free_api_key(&self) -> bool {
         self.api_key.ends_with(":fx")
     }
 
-    // Find glossary
-    fn glossary<'a>(&'a self, from_lang: Language, to_lang: Language) -> Option<&'a str> {
-        let glossary_key = format!("{}_{}", from_lang.as_langcode(), to_lang.as_langcode());
-        for (_key, value) in &self.glossaries {
-            if !value.get(&glossary_key).is_none() {
-                return value.get(&glossary_key).map(|v| v.as_str());
-            };
+    /// Replace glossary ID stored as `"<from>_<to>" = "<ID>"` in config file
+    ///
+    /// Returns true if the config file is updated.
+    pub fn update_glossary_id(&self, old_id: &str, new_id: &str) -> Result<bool> {
+        let stored = self
+            .glossaries
+            .values()
+            .flat_map(|table| table.values())
+            .any(|id| id == old_id);
+        let Some(config_path) = self.config_path.as_ref().filter(|_| stored) else {
+            return Ok(false);
+        };
+
+        let config = std::fs::read_to_string(config_path)?;
+        let updated = config.replace(&format!("\"{}\"", old_id), &format!("\"{}\"", new_id));
+        if updated == config {
+            return Ok(false);
         }
-        None
+        std::fs::write(config_path, updated)?;
+        Ok(true)
+    }
+
+    /// Frontmatter keys to be translated in files with the extension
+    ///
+    /// `[frontmatter_keys.<project_name>]` table maps extension to keys, `"*"` for other extensions.
+    /// If not configured, no key for Markdown (`md`, `mdx`) as before, `DEFAULT_FRONTMATTER_KEYS` for others.
+    pub fn frontmatter_keys(&self, extension: &str) -> Result<FrontmatterKeys> {
+        let keys = self
+            .frontmatter_keys
+            .as_ref()
+            .and_then(|keys| keys.get(&self.project_name))
+            .and_then(|keys| keys.get(extension).or_else(|| keys.get("*")));
+        match keys {
+            Some(keys) => FrontmatterKeys::new(keys),
+            None if matches!(extension, "md" | "mdx") => FrontmatterKeys::new::<&str>(&[]),
+            None => FrontmatterKeys::new(&DEFAULT_FRONTMATTER_KEYS),
+        }
+    }
+
+    /// CommonMark extensions enabled in `[cmark_extensions.<project_name>]`
+    ///
+    /// GitHub Flavored Markdown extensions if not configured.
+    /// `footnotes` and `superscript` can't be enabled together,
+    /// because footnote references `[^note]` are parsed as superscript.
+    /// `superscript = true` turns off `footnotes` enabled by default,
+    /// error only if both are explicitly set to true.
+    pub fn cmark_extensions(&self) -> Result<CmarkExtensions> {
+        let Some(table) = self
+            .cmark_extensions
+            .as_ref()
+            .and_then(|extensions| extensions.get(&self.project_name))
+        else {
+            return Ok(CmarkExtensions::default());
+        };
+        let mut extensions: CmarkExtensions =
+            toml::Value::Table(table.clone()).try_into().map_err(|e| {
+                Error::Config(format!("cmark_extensions.{} : {}", self.project_name, e))
+            })?;
+        if extensions.footnotes && extensions.superscript {
+            if table.contains_key("footnotes") {
+                return Err(Error::Config(format!(
+                    "cmark_extensions.{} : footnotes and superscript can't be enabled together",
+                    self.project_name
+                )));
+            }
+            extensions.footnotes = false;
+        }
+        Ok(extensions)
+    }
+
+    /// Name or ID of glossary used for translation
+    pub fn glossary_name(&self) -> &str {
+        self.glossary.as_deref().unwrap_or(&self.project_name)
+    }
+
+    /// Glossary ID stored as `"<from>_<to>" = "<ID>"` in `[glossaries.<glossary name>]`
+    pub fn stored_glossary_id(
+        &self,
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
+    ) -> Option<&str> {
+        let glossary_key = format!("{}_{}", from_lang.as_langcode(), to_lang.as_langcode());
+        self.glossaries
+            .get(self.glossary_name())
+            .and_then(|table| table.get(&glossary_key))
+            .map(|id| id.as_str())
     }
 }
 
@@ -539,7 +742,6 @@ struct DeeplTranslationResponse {
 #[derive(serde::Deserialize)]
 #[serde(rename_all = "snake_case")]
 struct DeeplTranslationResponseInner {
-    #[allow(dead_code)]
     detected_source_language: String,
     text: String,
 }
@@ -552,7 +754,7 @@ struct DeeplListGlossariesResponse {
 }
 
 /// DeepL response JSON for each glossaries
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct DeeplGlossary {
     pub glossary_id: String,
@@ -564,6 +766,60 @@ pub struct DeeplGlossary {
     pub entry_count: i32,
 }
 
+impl DeeplGlossary {
+    /// Check glossary is for the language pair, regional variants are ignored
+    pub fn is_language_pair(&self, from_lang: SourceLanguage, to_lang: TargetLanguage) -> bool {
+        let primary = |code: &str| code.split('-').next().unwrap_or_default().to_string();
+        primary(&self.source_lang).eq_ignore_ascii_case(from_lang.as_langcode())
+            && primary(&self.target_lang).eq_ignore_ascii_case(to_lang.glossary_langcode())
+    }
+}
+
+/// Find glossary for the language pair by ID or name
+///
+/// If several glossaries have the same name, the newest one is used.
+pub fn find_glossary<'a>(
+    glossaries: &'a [DeeplGlossary],
+    name_or_id: &str,
+    from_lang: SourceLanguage,
+    to_lang: TargetLanguage,
+) -> Option<&'a DeeplGlossary> {
+    let candidates = glossaries
+        .iter()
+        .filter(|g| g.is_language_pair(from_lang, to_lang));
+    candidates
+        .clone()
+        .find(|g| g.glossary_id == name_or_id)
+        .or_else(|| {
+            candidates
+                .filter(|g| g.name == name_or_id)
+                .max_by(|a, b| a.creation_time.cmp(&b.creation_time))
+        })
+}
+
+/// Source languages of glossaries for the target language by ID or name
+pub fn glossary_source_languages(
+    glossaries: &[DeeplGlossary],
+    name_or_id: &str,
+    to_lang: TargetLanguage,
+) -> Vec<String> {
+    let mut sources = glossaries
+        .iter()
+        .filter(|g| g.glossary_id == name_or_id || g.name == name_or_id)
+        .filter(|g| {
+            g.target_lang
+                .split('-')
+                .next()
+                .unwrap_or_default()
+                .eq_ignore_ascii_case(to_lang.glossary_langcode())
+        })
+        .map(|g| g.source_lang.to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    sources.sort();
+    sources.dedup();
+    sources
+}
+
 /// DeepL usage response JSON
 #[derive(serde::Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -580,26 +836,254 @@ mod tests {
     // DeeplConfig::with_config 関数のテスト
     #[test]
     fn test_deepl_config_with_config() {
-        let deepl_config = DeeplConfig::with_config("deepl.toml");
+        let deepl_config = DeeplConfig::with_config("deepl.sample.toml");
         assert!(deepl_config.is_ok());
     }
 
+    // DeeplConfig::frontmatter_keys 関数のテスト
+    #[test]
+    fn test_deepl_config_frontmatter_keys() {
+        use crate::frontmatter::PathSegment;
+        let title = [PathSegment::Key(String::from("title"))];
+        let summary = [
+            PathSegment::Key(String::from("extra")),
+            PathSegment::Key(String::from("summary")),
+        ];
+
+        let mut deepl_config: DeeplConfig = toml::from_str(
+            r#"
+            api_key = "123ab456:fx"
+            project_name = "test"
+            backup_original_text = false
+            [glossaries]
+            [frontmatter_keys.test]
+            md = []
+            "*" = ["extra.summary"]
+            "#,
+        )
+        .unwrap();
+        assert!(deepl_config.frontmatter_keys("md").unwrap().is_empty());
+        let keys = deepl_config.frontmatter_keys("html").unwrap();
+        assert!(keys.matches(&summary));
+        assert!(!keys.matches(&title));
+
+        // Not configured for this project, Markdown frontmatter is not translated
+        deepl_config.project_name = String::from("other");
+        assert!(deepl_config.frontmatter_keys("md").unwrap().is_empty());
+        assert!(deepl_config.frontmatter_keys("mdx").unwrap().is_empty());
+        let keys = deepl_config.frontmatter_keys("html").unwrap();
+        assert!(keys.matches(&title));
+        assert!(!keys.matches(&summary));
+    }
+
+    // DeeplConfig::cmark_extensions 関数のテスト
+    #[test]
+    fn test_deepl_config_cmark_extensions() {
+        let mut deepl_config: DeeplConfig = toml::from_str(
+            r#"
+            api_key = "123ab456:fx"
+            project_name = "test"
+            backup_original_text = false
+            [glossaries]
+            [cmark_extensions.test]
+            footnotes = false
+            description_lists = true
+            "#,
+        )
+        .unwrap();
+        let extensions = deepl_config.cmark_extensions().unwrap();
+        assert!(!extensions.footnotes);
+        assert!(extensions.description_lists);
+        // Not specified keys are GFM defaults
+        assert!(extensions.tasklist);
+        assert!(!extensions.superscript);
+
+        // Not configured for this project
+        deepl_config.project_name = String::from("other");
+        assert_eq!(
+            deepl_config.cmark_extensions().unwrap(),
+            CmarkExtensions::default()
+        );
+
+        // superscript turns off footnotes enabled by default
+        let mut deepl_config: DeeplConfig = toml::from_str(
+            r#"
+            api_key = "123ab456:fx"
+            project_name = "test"
+            backup_original_text = false
+            [glossaries]
+            [cmark_extensions.test]
+            superscript = true
+            [cmark_extensions.both]
+            superscript = true
+            footnotes = true
+            "#,
+        )
+        .unwrap();
+        let extensions = deepl_config.cmark_extensions().unwrap();
+        assert!(extensions.superscript);
+        assert!(!extensions.footnotes);
+
+        // superscript conflicts with footnotes explicitly enabled
+        deepl_config.project_name = String::from("both");
+        assert!(matches!(
+            deepl_config.cmark_extensions(),
+            Err(Error::Config(_))
+        ));
+    }
+
+    // DeeplConfig::endpoint 関数のテスト
+    #[test]
+    fn test_deepl_config_endpoint() {
+        let mut deepl_config = DeeplConfig {
+            api_key: String::from("123ab456:fx"),
+            ..Default::default()
+        };
+        assert_eq!(
+            deepl_config.endpoint("usage"),
+            "https://api-free.deepl.com/v2/usage"
+        );
+
+        deepl_config.api_key = String::from("123ab456");
+        assert_eq!(
+            deepl_config.endpoint("usage"),
+            "https://api.deepl.com/v2/usage"
+        );
+
+        deepl_config.endpoint = Some(String::from("http://localhost:8080/v2/"));
+        assert_eq!(
+            deepl_config.endpoint("glossaries/abc"),
+            "http://localhost:8080/v2/glossaries/abc"
+        );
+    }
+
+    // find_glossary 関数のテスト
+    #[test]
+    fn test_find_glossary() {
+        let glossary = |id: &str, name: &str, to: &str, time: &str| DeeplGlossary {
+            glossary_id: id.to_string(),
+            name: name.to_string(),
+            ready: true,
+            source_lang: String::from("en"),
+            target_lang: to.to_string(),
+            creation_time: time.to_string(),
+            entry_count: 1,
+        };
+        let glossaries = [
+            glossary("old-ja", "test", "ja", "2023-01-01T00:00:00Z"),
+            glossary("new-ja", "test", "ja", "2023-02-01T00:00:00Z"),
+            glossary("de", "test", "de", "2023-01-01T00:00:00Z"),
+            glossary("pt", "test", "pt", "2023-01-01T00:00:00Z"),
+        ];
+        let find = |name_or_id: &str, to_lang: TargetLanguage| {
+            find_glossary(&glossaries, name_or_id, SourceLanguage::En, to_lang)
+                .map(|g| g.glossary_id.as_str())
+        };
+
+        // By name, the newest one
+        assert_eq!(find("test", TargetLanguage::Ja), Some("new-ja"));
+        assert_eq!(find("test", TargetLanguage::De), Some("de"));
+        assert_eq!(find("test", TargetLanguage::PtBr), Some("pt"));
+        assert_eq!(find("test", TargetLanguage::Fr), None);
+        // By ID, language pair should match
+        assert_eq!(find("old-ja", TargetLanguage::Ja), Some("old-ja"));
+        assert_eq!(find("old-ja", TargetLanguage::De), None);
+    }
+
+    // glossary_source_languages 関数のテスト
+    #[test]
+    fn test_glossary_source_languages() {
+        let glossary = |id: &str, from: &str, to: &str| DeeplGlossary {
+            glossary_id: id.to_string(),
+            name: String::from("test"),
+            ready: true,
+            source_lang: from.to_string(),
+            target_lang: to.to_string(),
+            creation_time: String::new(),
+            entry_count: 1,
+        };
+        let glossaries = [
+            glossary("en-ja", "en", "ja"),
+            glossary("de-ja", "de", "ja"),
+            glossary("en-de", "en", "de"),
+        ];
+        assert_eq!(
+            glossary_source_languages(&glossaries, "test", TargetLanguage::Ja),
+            vec!["de", "en"]
+        );
+        assert_eq!(
+            glossary_source_languages(&glossaries, "en-de", TargetLanguage::Ja),
+            Vec::<String>::new()
+        );
+    }
+
+    // DeeplConfig::stored_glossary_id 関数のテスト
+    #[test]
+    fn test_deepl_config_stored_glossary_id() {
+        let mut deepl_config: DeeplConfig = toml::from_str(
+            "api_key = \"\"\nproject_name = \"test\"\nbackup_original_text = false\n[glossaries.test]\nen_ja = \"test-id\"\n[glossaries.other]\nen_de = \"other-id\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            deepl_config.stored_glossary_id(SourceLanguage::En, TargetLanguage::Ja),
+            Some("test-id")
+        );
+        assert_eq!(
+            deepl_config.stored_glossary_id(SourceLanguage::En, TargetLanguage::De),
+            None
+        );
+
+        deepl_config.glossary = Some(String::from("other"));
+        assert_eq!(
+            deepl_config.stored_glossary_id(SourceLanguage::En, TargetLanguage::De),
+            Some("other-id")
+        );
+    }
+
+    // DeeplConfig::update_glossary_id 関数のテスト
+    #[test]
+    fn test_deepl_config_update_glossary_id() {
+        let config_path = std::env::temp_dir().join(format!(
+            "cmark-translate-update-glossary-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &config_path,
+            "api_key = \"key\"\nproject_name = \"test\"\nbackup_original_text = false\n[glossaries.test]\n\"red\" = \"赤\"\nen_ja = \"old-id\" # stored ID\n",
+        )
+        .unwrap();
+
+        let deepl_config = DeeplConfig::with_config(&config_path).unwrap();
+        assert!(!deepl_config
+            .update_glossary_id("unknown-id", "new-id")
+            .unwrap());
+        assert!(deepl_config.update_glossary_id("old-id", "new-id").unwrap());
+
+        let deepl_config = DeeplConfig::with_config(&config_path).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        assert_eq!(
+            deepl_config.stored_glossary_id(SourceLanguage::En, TargetLanguage::Ja),
+            Some("new-id")
+        );
+    }
+
     // Deepl::with_config 関数のテスト
     #[tokio::test]
     async fn test_deepl_with_config() {
-        let deepl = Deepl::with_config("deepl.toml");
+        let deepl = Deepl::with_config("deepl.sample.toml");
         assert!(deepl.is_ok());
     }
 
     // Deepl::translate 関数のテスト
     #[tokio::test]
+    #[ignore = "requires deepl.toml and DeepL API access"]
     async fn test_deepl_translate() {
         let deepl = Deepl::with_config("deepl.toml").unwrap();
 
         let resp = deepl
             .translate(
-                Language::En,
-                Language::Ja,
+                SourceLanguage::En,
+                TargetLanguage::Ja,
                 Formality::Default,
                 "Hello, World!",
             )
@@ -613,6 +1097,7 @@ mod tests {
 
     // Deepl::list_glossaries 関数のテスト
     #[tokio::test]
+    #[ignore = "requires deepl.toml and DeepL API access"]
     async fn test_deepl_list_glossaries() {
         let deepl = Deepl::with_config("deepl.toml").unwrap();
 
@@ -622,13 +1107,19 @@ mod tests {
 
     // Deepl::register_glossaries 関数のテスト
     #[tokio::test]
+    #[ignore = "requires deepl.toml and DeepL API access"]
     async fn test_deepl_register_glossaries() {
         let deepl = Deepl::with_config("deepl.toml").unwrap();
         let glossary_name = "test_glossary";
         let glossaries = vec![("word1", "translation1"), ("word2", "translation2")];
 
         let result = deepl
-            .register_glossaries(glossary_name, Language::En, Language::Ja, &glossaries)
+            .register_glossaries(
+                glossary_name,
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                &glossaries,
+            )
             .await;
 
         assert!(result.is_ok());
@@ -638,6 +1129,7 @@ mod tests {
 
     // Deepl::remove_glossary 関数のテスト
     #[tokio::test]
+    #[ignore = "requires deepl.toml and DeepL API access"]
     async fn test_deepl_remove_glossary() {
         let deepl = Deepl::with_config("deepl.toml").unwrap();
 
@@ -650,6 +1142,7 @@ mod tests {
 
     // Deepl::get_usage 関数のテスト
     #[tokio::test]
+    #[ignore = "requires deepl.toml and DeepL API access"]
     async fn test_deepl_get_usage() {
         let deepl = Deepl::with_config("deepl.toml").unwrap();
 
@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: MIT
+//!
+//! Error type of this crate
+//!
+
+/// Errors returned from this crate
+#[derive(Debug)]
+pub enum Error {
+    /// Config file is not found or invalid
+    Config(String),
+    /// Failed to parse input (language code, frontmatter, glossary, API response, ...)
+    Parse(String),
+    /// DeepL character quota is exceeded
+    QuotaExceeded(String),
+    /// DeepL API key is rejected
+    Auth(String),
+    /// Failed to connect DeepL API
+    Network(reqwest_middleware::Error),
+    /// DeepL API returned error status
+    ApiStatus { status: u16, message: String },
+    /// Failed to convert CommonMark <=> XML, or translated XML is broken
+    Xml(String),
+    /// File I/O error
+    Io(std::io::Error),
+}
+
+/// Result type of this crate
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Error from DeepL API response status
+    pub(crate) fn from_status(status: reqwest::StatusCode, message: String) -> Self {
+        match status.as_u16() {
+            401 | 403 => Self::Auth(message),
+            456 => Self::QuotaExceeded(message),
+            status => Self::ApiStatus { status, message },
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Config(msg) => write!(f, "config error: {}", msg),
+            Self::Parse(msg) => write!(f, "parse error: {}", msg),
+            Self::QuotaExceeded(msg) => write!(f, "quota exceeded: {}", msg),
+            Self::Auth(msg) => write!(f, "authorization failed: {}", msg),
+            Self::Network(e) => write!(f, "network error: {}", e),
+            Self::ApiStatus { status, message } => {
+                write!(f, "DeepL API error: HTTP {} {}", status, message)
+            }
+            Self::Xml(msg) => write!(f, "XML error: {}", msg),
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Network(e) => Some(e),
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            Self::Parse(e.to_string())
+        } else {
+            Self::Network(reqwest_middleware::Error::Reqwest(e))
+        }
+    }
+}
+
+impl From<reqwest_middleware::Error> for Error {
+    fn from(e: reqwest_middleware::Error) -> Self {
+        match e {
+            reqwest_middleware::Error::Reqwest(e) => Self::from(e),
+            e => Self::Network(e),
+        }
+    }
+}
+
+impl From<minidom::Error> for Error {
+    fn from(e: minidom::Error) -> Self {
+        Self::Xml(e.to_string())
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Parse(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Parse(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_from_status() {
+        assert!(matches!(
+            Error::from_status(reqwest::StatusCode::FORBIDDEN, String::new()),
+            Error::Auth(_)
+        ));
+        assert!(matches!(
+            Error::from_status(reqwest::StatusCode::from_u16(456).unwrap(), String::new()),
+            Error::QuotaExceeded(_)
+        ));
+        assert!(matches!(
+            Error::from_status(reqwest::StatusCode::BAD_REQUEST, String::new()),
+            Error::ApiStatus { status: 400, .. }
+        ));
+    }
+}
@@ -0,0 +1,151 @@
+// SPDX-License-Identifier: MIT
+//!
+//! Frontmatter keys to be translated
+//!
+//! Keys are dotted paths with wildcards, shared by TOML, YAML and JSON frontmatter.
+//! `extra.summary`, `tags[*]`, `seo.*.text`
+//!
+
+use crate::error::{Error, Result};
+
+/// Keys translated if `frontmatter_keys` is not configured
+pub const DEFAULT_FRONTMATTER_KEYS: [&str; 5] = [
+    "title",
+    "description",
+    "sidebar_label",
+    "keywords[*]",
+    "extra.time",
+];
+
+/// Path segment to a value in frontmatter
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PatternSegment {
+    Key(String),
+    /// `*`, any key of table
+    AnyKey,
+    Index(usize),
+    /// `[*]`, any item of array
+    AnyIndex,
+}
+
+/// Pattern of frontmatter key
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyPattern(Vec<PatternSegment>);
+
+impl std::str::FromStr for KeyPattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Parse(format!("invalid frontmatter key \"{}\"", s));
+        let mut segments = Vec::new();
+        for part in s.split('.') {
+            // "key[0][*]" -> "key", "0]", "*]"
+            let mut parts = part.split('[');
+            match parts.next() {
+                Some("") if segments.is_empty() || !part.starts_with('[') => {
+                    return Err(invalid());
+                }
+                Some("") => {}
+                Some("*") => segments.push(PatternSegment::AnyKey),
+                Some(key) => segments.push(PatternSegment::Key(key.to_string())),
+                None => return Err(invalid()),
+            }
+            for index in parts {
+                let index = index.strip_suffix(']').ok_or_else(invalid)?;
+                if index == "*" {
+                    segments.push(PatternSegment::AnyIndex);
+                } else {
+                    let index = index.parse().map_err(|_| invalid())?;
+                    segments.push(PatternSegment::Index(index));
+                }
+            }
+        }
+        Ok(Self(segments))
+    }
+}
+
+impl KeyPattern {
+    /// Check path to a value matches this pattern
+    pub fn matches(&self, path: &[PathSegment]) -> bool {
+        self.0.len() == path.len()
+            && self
+                .0
+                .iter()
+                .zip(path)
+                .all(|(pattern, segment)| match (pattern, segment) {
+                    (PatternSegment::Key(p), PathSegment::Key(k)) => p == k,
+                    (PatternSegment::AnyKey, PathSegment::Key(_)) => true,
+                    (PatternSegment::Index(p), PathSegment::Index(i)) => p == i,
+                    (PatternSegment::AnyIndex, PathSegment::Index(_)) => true,
+                    _ => false,
+                })
+    }
+}
+
+/// Frontmatter keys to be translated
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrontmatterKeys(Vec<KeyPattern>);
+
+impl FrontmatterKeys {
+    /// Parse key patterns
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| pattern.as_ref().parse())
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self(patterns))
+    }
+
+    /// Check value at the path should be translated
+    pub fn matches(&self, path: &[PathSegment]) -> bool {
+        self.0.iter().any(|pattern| pattern.matches(path))
+    }
+
+    /// No key is translated
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(segments: &[&str]) -> Vec<PathSegment> {
+        segments
+            .iter()
+            .map(|s| match s.parse::<usize>() {
+                Ok(i) => PathSegment::Index(i),
+                Err(_) => PathSegment::Key(s.to_string()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_frontmatter_keys() {
+        let keys = FrontmatterKeys::new(&["extra.summary", "tags[*]", "seo.*.text", "authors[0]"])
+            .unwrap();
+        assert!(keys.matches(&path(&["extra", "summary"])));
+        assert!(keys.matches(&path(&["tags", "3"])));
+        assert!(keys.matches(&path(&["seo", "og", "text"])));
+        assert!(keys.matches(&path(&["authors", "0"])));
+
+        assert!(!keys.matches(&path(&["summary"])));
+        assert!(!keys.matches(&path(&["tags"])));
+        assert!(!keys.matches(&path(&["seo", "og", "url"])));
+        assert!(!keys.matches(&path(&["seo", "0", "text"])));
+        assert!(!keys.matches(&path(&["authors", "1"])));
+
+        assert!(FrontmatterKeys::new(&["a..b"]).is_err());
+        assert!(FrontmatterKeys::new(&["[*]"]).is_err());
+        assert!(FrontmatterKeys::new(&["tags[x]"]).is_err());
+        assert!(FrontmatterKeys::new(&["tags[*"]).is_err());
+        assert!(FrontmatterKeys::new(&DEFAULT_FRONTMATTER_KEYS).is_ok());
+    }
+}
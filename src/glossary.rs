@@ -1,17 +1,21 @@
 // SPDX-License-Identifier: MIT
 //!
-//! Read glossaries from .xlsx
+//! Read glossaries from TOML, .xlsx, TSV and CSV,
+//! and synchronize them with registered glossaries
 //!
 
+use crate::deepl::{DeeplGlossary, SourceLanguage, TargetLanguage};
+use crate::error::{Error, Result};
+use crate::translator::Translator;
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::{BufReader, Read};
-use toml;
 
-fn read_file(path: &std::path::Path) -> Result<String, String> {
+fn read_file(path: &std::path::Path) -> std::result::Result<String, String> {
     let mut file_content = String::new();
 
     let mut fr = fs::File::open(path)
-        .map(|f| BufReader::new(f))
+        .map(BufReader::new)
         .map_err(|e| e.to_string())?;
 
     fr.read_to_string(&mut file_content)
@@ -23,38 +27,325 @@ fn read_file(path: &std::path::Path) -> Result<String, String> {
 pub fn read_glossary<P: AsRef<std::path::Path>>(
     name: &str,
     path: P,
-) -> Result<Vec<(String, String)>, toml::de::Error> {
-    let s = match read_file(path.as_ref()) {
-        Ok(s) => s,
-        Err(e) => panic!("fail to read file: {}", e),
+) -> Result<Vec<(String, String)>> {
+    let s =
+        read_file(path.as_ref()).map_err(|e| Error::Config(format!("fail to read file: {}", e)))?;
+    let toml_value: toml::Value = toml::from_str(&s)?;
+
+    let glossaries_value = toml_value
+        .get("glossaries")
+        .ok_or_else(|| Error::Parse(String::from("[glossaries] NOT found")))?;
+    let glossary_map = glossaries_value
+        .get(name)
+        .and_then(|v| v.as_table())
+        .ok_or_else(|| Error::Parse(format!("[glossaries.{}] NOT found", name)))?;
+
+    let mut glossary = Vec::new();
+    for (from, to) in glossary_map {
+        if is_glossary_id_key(from) {
+            // `"<from>_<to>" = "<glossary ID>"` is not a term
+            continue;
+        }
+        let to = match to.as_str() {
+            Some(to) => to.to_string(),
+            None => to.to_string().replace('"', ""),
+        };
+        log::debug!("{} -> {}", from, &to);
+        glossary.push((from.to_string(), to));
+    }
+    Ok(glossary)
+}
+
+/// Read glossary from .xlsx
+///
+/// First row of the sheet is a header containing language codes, e.g. `en`, `ja`.
+/// Following rows are term pairs, columns are selected by `from_lang` and `to_lang`.
+/// The first sheet is used if `sheet` is not specified.
+pub fn read_glossary_xlsx<P: AsRef<std::path::Path>>(
+    path: P,
+    sheet: Option<&str>,
+    from_lang: SourceLanguage,
+    to_lang: TargetLanguage,
+) -> Result<Vec<(String, String)>> {
+    let book = umya_spreadsheet::reader::xlsx::read(path.as_ref())
+        .map_err(|e| Error::Parse(format!("{} : {}", path.as_ref().display(), e)))?;
+    let worksheet = match sheet {
+        Some(sheet) => book.get_sheet_by_name(sheet),
+        None => book.get_sheet(&0),
+    }
+    .map_err(|e| Error::Parse(format!("{} : {}", sheet.unwrap_or_default(), e)))?;
+
+    // Find language columns in header row
+    let headers = (1..=worksheet.get_highest_column())
+        .map(|col| worksheet.get_value((col, 1)))
+        .collect::<Vec<_>>();
+    let from_col =
+        language_column(&headers, from_lang.as_langcode(), worksheet.get_name())? as u32 + 1;
+    let to_col = language_column(&headers, to_lang.as_langcode(), worksheet.get_name())? as u32 + 1;
+
+    let mut glossary = Vec::new();
+    for row in 2..=worksheet.get_highest_row() {
+        let from = worksheet.get_value((from_col, row));
+        let to = worksheet.get_value((to_col, row));
+        if from.is_empty() && to.is_empty() {
+            continue;
+        }
+        log::debug!("{} -> {}", from, to);
+        glossary.push((from, to));
+    }
+    Ok(glossary)
+}
+
+/// Read glossary from TSV or CSV
+///
+/// First row is a header containing language codes, e.g. `en`, `ja`.
+/// Following rows are term pairs, columns are selected by `from_lang` and `to_lang`.
+pub fn read_glossary_delimited<P: AsRef<std::path::Path>>(
+    path: P,
+    format: GlossaryFormat,
+    from_lang: SourceLanguage,
+    to_lang: TargetLanguage,
+) -> Result<Vec<(String, String)>> {
+    let mut builder = csv::ReaderBuilder::new();
+    builder.has_headers(true).flexible(true);
+    if format == GlossaryFormat::Tsv {
+        // TSV has no quoting, same as DeepL glossary entries
+        builder.delimiter(b'\t').quoting(false);
+    }
+    let mut reader = builder.from_path(path.as_ref()).map_err(csv_error)?;
+
+    // Find language columns in header row
+    let headers = reader
+        .headers()
+        .map_err(csv_error)?
+        .iter()
+        .map(|h| h.to_string())
+        .collect::<Vec<_>>();
+    let file_name = path.as_ref().display().to_string();
+    let from_col = language_column(&headers, from_lang.as_langcode(), &file_name)?;
+    let to_col = language_column(&headers, to_lang.as_langcode(), &file_name)?;
+
+    let mut glossary = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(csv_error)?;
+        let from = record.get(from_col).unwrap_or_default();
+        let to = record.get(to_col).unwrap_or_default();
+        if from.is_empty() && to.is_empty() {
+            continue;
+        }
+        log::debug!("{} -> {}", from, to);
+        glossary.push((from.to_string(), to.to_string()));
+    }
+    Ok(glossary)
+}
+
+/// Read glossary file in given format, detected by extension if None
+///
+/// `name` is used for TOML, `sheet` is used for .xlsx.
+pub fn read_glossary_file<P: AsRef<std::path::Path>>(
+    name: &str,
+    path: P,
+    format: Option<GlossaryFormat>,
+    sheet: Option<&str>,
+    from_lang: SourceLanguage,
+    to_lang: TargetLanguage,
+) -> Result<Vec<(String, String)>> {
+    let format = format.unwrap_or_else(|| GlossaryFormat::from_path(&path));
+    match format {
+        GlossaryFormat::Toml => read_glossary(name, path),
+        GlossaryFormat::Xlsx => read_glossary_xlsx(path, sheet, from_lang, to_lang),
+        GlossaryFormat::Tsv | GlossaryFormat::Csv => {
+            read_glossary_delimited(path, format, from_lang, to_lang)
+        }
+    }
+}
+
+/// Difference of an entry between local and registered glossary
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GlossaryChange {
+    /// Only in local glossary, (source, target)
+    Added(String, String),
+    /// Only in registered glossary, (source, target)
+    Removed(String, String),
+    /// Target is changed, (source, registered target, local target)
+    Changed(String, String, String),
+}
+
+impl std::fmt::Display for GlossaryChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Added(from, to) => write!(f, "+ {} -> {}", from, to),
+            Self::Removed(from, to) => write!(f, "- {} -> {}", from, to),
+            Self::Changed(from, old, new) => write!(f, "~ {} -> {} (was {})", from, new, old),
+        }
+    }
+}
+
+/// Result of `sync_glossary`
+#[derive(Clone, Debug)]
+pub struct GlossarySync {
+    /// Entry level differences, empty if up to date
+    pub changes: Vec<GlossaryChange>,
+    /// Newly registered glossary, None if up to date
+    pub registered: Option<DeeplGlossary>,
+    /// IDs of replaced glossaries, already removed
+    pub removed_ids: Vec<String>,
+}
+
+/// Synchronize local glossary with registered glossary of the same name and language pair
+///
+/// If entries differ, new glossary is registered first, then old glossaries are removed.
+/// Old glossaries are kept if the registration fails.
+pub async fn sync_glossary<T: Translator, S: AsRef<str> + Sync>(
+    translator: &T,
+    name: &str,
+    from_lang: SourceLanguage,
+    to_lang: TargetLanguage,
+    glossaries: &[(S, S)],
+) -> Result<GlossarySync> {
+    let registered = translator
+        .list_glossaries()
+        .await?
+        .into_iter()
+        .filter(|g| g.name == name && g.is_language_pair(from_lang, to_lang))
+        .collect::<Vec<_>>();
+
+    let server_entries = match registered.first() {
+        Some(glossary) => translator.glossary_entries(&glossary.glossary_id).await?,
+        None => Vec::new(),
     };
-    let toml_reslut = toml::from_str(&s);
-    match toml_reslut {
-        Ok(v) => {
-            let mut glossary = Vec::new();
-            let toml_value: toml::Value = v;
-            let toml_map = toml_value.as_table().unwrap();
-            if toml_map.get("glossaries").is_none() {
-                panic!("fail to parse toml...");
-            }
-            let glossaries_value = toml_map.get("glossaries").unwrap();
-            println!("{:?}", glossaries_value);
-            if glossaries_value.get(name).is_none() {
-                panic!("fail to get glossary name...");
-            }
-            let glossary_value = glossaries_value.get(name).unwrap();
-            let glossary_map = glossary_value.as_table().unwrap();
-            for g in glossary_map {
-                let (from, to) = g;
-                println!("{} -> {}", from, &to.to_string().replace("\"", ""));
-                glossary.push((from.to_string(), to.to_string().replace("\"", "")));
-            }
-            Ok(glossary)
+    let changes = diff_entries(&server_entries, glossaries);
+    if changes.is_empty() && registered.len() == 1 {
+        return Ok(GlossarySync {
+            changes,
+            registered: None,
+            removed_ids: Vec::new(),
+        });
+    }
+
+    // Register new one before removing old ones
+    let entries = glossaries
+        .iter()
+        .map(|(from, to)| (from.as_ref(), to.as_ref()))
+        .collect::<Vec<_>>();
+    let glossary = translator
+        .register_glossaries(name, from_lang, to_lang, &entries)
+        .await?;
+    let mut removed_ids = Vec::new();
+    for old in registered {
+        translator.remove_glossary(&old.glossary_id).await?;
+        removed_ids.push(old.glossary_id);
+    }
+
+    Ok(GlossarySync {
+        changes,
+        registered: Some(glossary),
+        removed_ids,
+    })
+}
+
+/// Compare entries, trimmed and empty entries are ignored same as registration
+fn diff_entries<S1: AsRef<str>, S2: AsRef<str>>(
+    server: &[(S1, S1)],
+    local: &[(S2, S2)],
+) -> Vec<GlossaryChange> {
+    fn entry_map<S: AsRef<str>>(entries: &[(S, S)]) -> BTreeMap<&str, &str> {
+        entries
+            .iter()
+            .map(|(from, to)| (from.as_ref().trim(), to.as_ref().trim()))
+            .filter(|(from, to)| !from.is_empty() && !to.is_empty())
+            .collect()
+    }
+    let server = entry_map(server);
+    let local = entry_map(local);
+
+    let mut changes = Vec::new();
+    for (from, to) in &local {
+        match server.get(from) {
+            None => changes.push(GlossaryChange::Added(from.to_string(), to.to_string())),
+            Some(old) if old != to => changes.push(GlossaryChange::Changed(
+                from.to_string(),
+                old.to_string(),
+                to.to_string(),
+            )),
+            Some(_) => (),
+        }
+    }
+    for (from, to) in &server {
+        if !local.contains_key(from) {
+            changes.push(GlossaryChange::Removed(from.to_string(), to.to_string()));
+        }
+    }
+    changes
+}
+
+/// Check key is `<from>_<to>` language pair
+fn is_glossary_id_key(key: &str) -> bool {
+    key.split_once('_').is_some_and(|(from, to)| {
+        from.parse::<SourceLanguage>().is_ok() && to.parse::<TargetLanguage>().is_ok()
+    })
+}
+
+/// Glossary file format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlossaryFormat {
+    /// `[glossaries.<name>]` table in TOML
+    Toml,
+    Xlsx,
+    Tsv,
+    Csv,
+}
+
+impl GlossaryFormat {
+    /// Detect format from file extension, TOML if unknown
+    pub fn from_path<P: AsRef<std::path::Path>>(path: P) -> Self {
+        path.as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| ext.parse().ok())
+            .unwrap_or(Self::Toml)
+    }
+}
+
+impl std::str::FromStr for GlossaryFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "toml" => Ok(Self::Toml),
+            "xlsx" => Ok(Self::Xlsx),
+            "tsv" | "tab" => Ok(Self::Tsv),
+            "csv" => Ok(Self::Csv),
+            _ => Err(Error::Parse(format!("unknown glossary format \"{}\"", s))),
         }
-        Err(e) => panic!("fail to parse toml: {}", e),
     }
 }
 
+/// Find column of language code in header row
+///
+/// Header without regional variant (`en`) matches regional language (`en-us`)
+/// if there is no exact match.
+fn language_column(headers: &[String], code: &str, source: &str) -> Result<usize> {
+    let headers = headers
+        .iter()
+        .map(|header| header.trim().trim_start_matches('\u{feff}'))
+        .collect::<Vec<_>>();
+    let primary = code.split('-').next().unwrap_or_default();
+    headers
+        .iter()
+        .position(|header| header.eq_ignore_ascii_case(code))
+        .or_else(|| {
+            headers
+                .iter()
+                .position(|header| header.eq_ignore_ascii_case(primary))
+        })
+        .ok_or_else(|| Error::Parse(format!("column \"{}\" NOT found in {}", code, source)))
+}
+
+fn csv_error(e: csv::Error) -> Error {
+    Error::Parse(e.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -103,5 +394,234 @@ numbers = { "one" = "一", "two" = "二" }
             ("red".to_string(), "赤".to_string()),
         ];
         assert_eq!(result.unwrap(), expected_glossary);
+
+        // Missing glossary is an error, not a panic
+        assert!(matches!(
+            read_glossary("missing", &test_file_path),
+            Err(Error::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_glossary_xlsx() {
+        let xlsx_path =
+            std::env::temp_dir().join(format!("cmark-translate-{}.xlsx", std::process::id()));
+
+        // Header row, term pairs and an empty row
+        let mut book = umya_spreadsheet::new_file();
+        let sheet = book.new_sheet("terms").unwrap();
+        for (col, row, value) in [
+            (1, 1, "ja"),
+            (2, 1, "EN"),
+            (3, 1, "note"),
+            (1, 2, "赤"),
+            (2, 2, "red"),
+            (1, 4, "青"),
+            (2, 4, " blue "),
+        ] {
+            // Rows without row dimension are not written
+            sheet.get_row_dimension_mut(&row);
+            sheet.get_cell_mut((col, row)).set_value_string(value);
+        }
+        umya_spreadsheet::writer::xlsx::write(&book, &xlsx_path).unwrap();
+
+        let glossary = read_glossary_xlsx(
+            &xlsx_path,
+            Some("terms"),
+            SourceLanguage::En,
+            TargetLanguage::Ja,
+        )
+        .unwrap();
+        assert_eq!(
+            glossary,
+            vec![
+                ("red".to_string(), "赤".to_string()),
+                (" blue ".to_string(), "青".to_string()),
+            ]
+        );
+
+        // First sheet is empty, no language columns
+        assert!(
+            read_glossary_xlsx(&xlsx_path, None, SourceLanguage::En, TargetLanguage::Ja).is_err()
+        );
+        // Sheet NOT found
+        assert!(read_glossary_xlsx(
+            &xlsx_path,
+            Some("none"),
+            SourceLanguage::En,
+            TargetLanguage::Ja
+        )
+        .is_err());
+
+        std::fs::remove_file(&xlsx_path).unwrap();
+    }
+
+    #[test]
+    fn test_read_glossary_delimited() {
+        let tmp_path = std::env::temp_dir().join(format!("cmark-translate-{}", std::process::id()));
+
+        // Multi-column terminology file, used for several language pairs
+        let tsv_path = tmp_path.with_extension("tsv");
+        std::fs::write(
+            &tsv_path,
+            "en\tja\tde\nred\t赤\trot\n\"blue\"\t青\tblau\n\t\t\n",
+        )
+        .unwrap();
+        assert_eq!(GlossaryFormat::from_path(&tsv_path), GlossaryFormat::Tsv);
+        assert_eq!(
+            read_glossary_delimited(
+                &tsv_path,
+                GlossaryFormat::Tsv,
+                SourceLanguage::En,
+                TargetLanguage::Ja
+            )
+            .unwrap(),
+            vec![
+                ("red".to_string(), "赤".to_string()),
+                ("\"blue\"".to_string(), "青".to_string()),
+            ]
+        );
+        assert_eq!(
+            read_glossary_delimited(
+                &tsv_path,
+                GlossaryFormat::Tsv,
+                SourceLanguage::De,
+                TargetLanguage::En
+            )
+            .unwrap(),
+            vec![
+                ("rot".to_string(), "red".to_string()),
+                ("blau".to_string(), "\"blue\"".to_string()),
+            ]
+        );
+        // Language column NOT found
+        assert!(read_glossary_delimited(
+            &tsv_path,
+            GlossaryFormat::Tsv,
+            SourceLanguage::En,
+            TargetLanguage::Fr
+        )
+        .is_err());
+        std::fs::remove_file(&tsv_path).unwrap();
+
+        // CSV with BOM and quoted field
+        let csv_path = tmp_path.with_extension("csv");
+        std::fs::write(&csv_path, "\u{feff}JA,EN\n赤,red\n青,\"blue, navy\"\n").unwrap();
+        assert_eq!(GlossaryFormat::from_path(&csv_path), GlossaryFormat::Csv);
+        assert_eq!(
+            read_glossary_delimited(
+                &csv_path,
+                GlossaryFormat::Csv,
+                SourceLanguage::En,
+                TargetLanguage::Ja
+            )
+            .unwrap(),
+            vec![
+                ("red".to_string(), "赤".to_string()),
+                ("blue, navy".to_string(), "青".to_string()),
+            ]
+        );
+        std::fs::remove_file(&csv_path).unwrap();
+
+        assert_eq!(
+            GlossaryFormat::from_path("deepl.toml"),
+            GlossaryFormat::Toml
+        );
+        assert_eq!(
+            "XLSX".parse::<GlossaryFormat>().unwrap(),
+            GlossaryFormat::Xlsx
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_glossary() {
+        use crate::mock::{MockStyle, MockTranslator};
+        let mock = MockTranslator::new(MockStyle::default());
+
+        // Not registered yet
+        let synced = sync_glossary(
+            &mock,
+            "test",
+            SourceLanguage::En,
+            TargetLanguage::Ja,
+            &[("red", "赤")],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            synced.changes,
+            vec![GlossaryChange::Added("red".to_string(), "赤".to_string())]
+        );
+        let first_id = synced.registered.unwrap().glossary_id;
+        assert!(synced.removed_ids.is_empty());
+
+        // Up to date, spaces are ignored
+        let synced = sync_glossary(
+            &mock,
+            "test",
+            SourceLanguage::En,
+            TargetLanguage::Ja,
+            &[(" red ", "赤")],
+        )
+        .await
+        .unwrap();
+        assert!(synced.changes.is_empty());
+        assert!(synced.registered.is_none());
+
+        // Changed, re-registered
+        let synced = sync_glossary(
+            &mock,
+            "test",
+            SourceLanguage::En,
+            TargetLanguage::Ja,
+            &[("red", "紅"), ("blue", "青")],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            synced.changes,
+            vec![
+                GlossaryChange::Added("blue".to_string(), "青".to_string()),
+                GlossaryChange::Changed("red".to_string(), "赤".to_string(), "紅".to_string()),
+            ]
+        );
+        assert_eq!(synced.removed_ids, vec![first_id]);
+        let glossaries = mock.list_glossaries().await.unwrap();
+        assert_eq!(glossaries.len(), 1);
+        assert_eq!(
+            glossaries[0].glossary_id,
+            synced.registered.unwrap().glossary_id
+        );
+
+        // Other language pair is a different glossary
+        let synced = sync_glossary(
+            &mock,
+            "test",
+            SourceLanguage::En,
+            TargetLanguage::De,
+            &[("red", "rot")],
+        )
+        .await
+        .unwrap();
+        assert!(synced.removed_ids.is_empty());
+        assert_eq!(mock.list_glossaries().await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_diff_entries() {
+        let changes = diff_entries(
+            &[("red", "赤"), ("green", "緑")],
+            &[("red", "赤"), ("", "")],
+        );
+        assert_eq!(
+            changes,
+            vec![GlossaryChange::Removed(
+                "green".to_string(),
+                "緑".to_string()
+            )]
+        );
+        assert_eq!(changes[0].to_string(), "- green -> 緑");
+        assert!(is_glossary_id_key("en_ja"));
+        assert!(!is_glossary_id_key("query_call"));
     }
 }
@@ -0,0 +1,387 @@
+// SPDX-License-Identifier: MIT
+//!
+//! Source and target languages supported by DeepL
+//!
+//! DeepL accepts different sets of languages for source and target.
+//! Regional variants (`en-GB`, `pt-BR`, ...) are available only for target.
+//!
+
+use crate::error::{Error, Result};
+
+/// Source language of translation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceLanguage {
+    Ar,
+    Bg,
+    Cs,
+    Da,
+    De,
+    El,
+    En,
+    Es,
+    Et,
+    Fi,
+    Fr,
+    Hu,
+    Id,
+    It,
+    Ja,
+    Ko,
+    Lt,
+    Lv,
+    Nb,
+    Nl,
+    Pl,
+    Pt,
+    Ro,
+    Ru,
+    Sk,
+    Sl,
+    Sv,
+    Tr,
+    Uk,
+    Zh,
+}
+
+impl SourceLanguage {
+    /// All source languages
+    pub const ALL: [Self; 30] = [
+        Self::Ar,
+        Self::Bg,
+        Self::Cs,
+        Self::Da,
+        Self::De,
+        Self::El,
+        Self::En,
+        Self::Es,
+        Self::Et,
+        Self::Fi,
+        Self::Fr,
+        Self::Hu,
+        Self::Id,
+        Self::It,
+        Self::Ja,
+        Self::Ko,
+        Self::Lt,
+        Self::Lv,
+        Self::Nb,
+        Self::Nl,
+        Self::Pl,
+        Self::Pt,
+        Self::Ro,
+        Self::Ru,
+        Self::Sk,
+        Self::Sl,
+        Self::Sv,
+        Self::Tr,
+        Self::Uk,
+        Self::Zh,
+    ];
+
+    pub fn as_langcode(&self) -> &'static str {
+        match self {
+            Self::Ar => "ar",
+            Self::Bg => "bg",
+            Self::Cs => "cs",
+            Self::Da => "da",
+            Self::De => "de",
+            Self::El => "el",
+            Self::En => "en",
+            Self::Es => "es",
+            Self::Et => "et",
+            Self::Fi => "fi",
+            Self::Fr => "fr",
+            Self::Hu => "hu",
+            Self::Id => "id",
+            Self::It => "it",
+            Self::Ja => "ja",
+            Self::Ko => "ko",
+            Self::Lt => "lt",
+            Self::Lv => "lv",
+            Self::Nb => "nb",
+            Self::Nl => "nl",
+            Self::Pl => "pl",
+            Self::Pt => "pt",
+            Self::Ro => "ro",
+            Self::Ru => "ru",
+            Self::Sk => "sk",
+            Self::Sl => "sl",
+            Self::Sv => "sv",
+            Self::Tr => "tr",
+            Self::Uk => "uk",
+            Self::Zh => "zh",
+        }
+    }
+
+    /// Language name in English
+    pub fn name(&self) -> &'static str {
+        language_name(self.as_langcode())
+    }
+}
+
+impl std::str::FromStr for SourceLanguage {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|lang| lang.as_langcode().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::Parse(format!("unsupported source language \"{}\"", s)))
+    }
+}
+
+/// Target language of translation, including regional variants
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetLanguage {
+    Ar,
+    Bg,
+    Cs,
+    Da,
+    De,
+    El,
+    /// Unspecified variant, deprecated by DeepL
+    En,
+    EnGb,
+    EnUs,
+    Es,
+    Et,
+    Fi,
+    Fr,
+    Hu,
+    Id,
+    It,
+    Ja,
+    Ko,
+    Lt,
+    Lv,
+    Nb,
+    Nl,
+    Pl,
+    /// Unspecified variant, deprecated by DeepL
+    Pt,
+    PtBr,
+    PtPt,
+    Ro,
+    Ru,
+    Sk,
+    Sl,
+    Sv,
+    Tr,
+    Uk,
+    /// Same as `ZhHans`
+    Zh,
+    ZhHans,
+    ZhHant,
+}
+
+impl TargetLanguage {
+    /// All target languages
+    pub const ALL: [Self; 36] = [
+        Self::Ar,
+        Self::Bg,
+        Self::Cs,
+        Self::Da,
+        Self::De,
+        Self::El,
+        Self::En,
+        Self::EnGb,
+        Self::EnUs,
+        Self::Es,
+        Self::Et,
+        Self::Fi,
+        Self::Fr,
+        Self::Hu,
+        Self::Id,
+        Self::It,
+        Self::Ja,
+        Self::Ko,
+        Self::Lt,
+        Self::Lv,
+        Self::Nb,
+        Self::Nl,
+        Self::Pl,
+        Self::Pt,
+        Self::PtBr,
+        Self::PtPt,
+        Self::Ro,
+        Self::Ru,
+        Self::Sk,
+        Self::Sl,
+        Self::Sv,
+        Self::Tr,
+        Self::Uk,
+        Self::Zh,
+        Self::ZhHans,
+        Self::ZhHant,
+    ];
+
+    pub fn as_langcode(&self) -> &'static str {
+        match self {
+            Self::Ar => "ar",
+            Self::Bg => "bg",
+            Self::Cs => "cs",
+            Self::Da => "da",
+            Self::De => "de",
+            Self::El => "el",
+            Self::En => "en",
+            Self::EnGb => "en-gb",
+            Self::EnUs => "en-us",
+            Self::Es => "es",
+            Self::Et => "et",
+            Self::Fi => "fi",
+            Self::Fr => "fr",
+            Self::Hu => "hu",
+            Self::Id => "id",
+            Self::It => "it",
+            Self::Ja => "ja",
+            Self::Ko => "ko",
+            Self::Lt => "lt",
+            Self::Lv => "lv",
+            Self::Nb => "nb",
+            Self::Nl => "nl",
+            Self::Pl => "pl",
+            Self::Pt => "pt",
+            Self::PtBr => "pt-br",
+            Self::PtPt => "pt-pt",
+            Self::Ro => "ro",
+            Self::Ru => "ru",
+            Self::Sk => "sk",
+            Self::Sl => "sl",
+            Self::Sv => "sv",
+            Self::Tr => "tr",
+            Self::Uk => "uk",
+            Self::Zh => "zh",
+            Self::ZhHans => "zh-hans",
+            Self::ZhHant => "zh-hant",
+        }
+    }
+
+    /// Language code without regional variant, used for glossaries
+    pub fn glossary_langcode(&self) -> &'static str {
+        let code = self.as_langcode();
+        code.split_once('-').map_or(code, |(primary, _)| primary)
+    }
+
+    /// Language name in English
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::EnGb => "English (British)",
+            Self::EnUs => "English (American)",
+            Self::PtBr => "Portuguese (Brazilian)",
+            Self::PtPt => "Portuguese (European)",
+            Self::ZhHans => "Chinese (simplified)",
+            Self::ZhHant => "Chinese (traditional)",
+            _ => language_name(self.as_langcode()),
+        }
+    }
+
+    /// Check DeepL supports formality for this language
+    pub fn supports_formality(&self) -> bool {
+        matches!(
+            self,
+            Self::De
+                | Self::Es
+                | Self::Fr
+                | Self::It
+                | Self::Ja
+                | Self::Nl
+                | Self::Pl
+                | Self::PtBr
+                | Self::PtPt
+                | Self::Ru
+        )
+    }
+}
+
+impl std::str::FromStr for TargetLanguage {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|lang| lang.as_langcode().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::Parse(format!("unsupported target language \"{}\"", s)))
+    }
+}
+
+/// Language information returned from `/v2/languages`
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DeeplLanguage {
+    pub language: String,
+    pub name: String,
+    /// Only for target languages
+    #[serde(default)]
+    pub supports_formality: bool,
+}
+
+// Language name by code without regional variant
+fn language_name(code: &str) -> &'static str {
+    match code {
+        "ar" => "Arabic",
+        "bg" => "Bulgarian",
+        "cs" => "Czech",
+        "da" => "Danish",
+        "de" => "German",
+        "el" => "Greek",
+        "en" => "English",
+        "es" => "Spanish",
+        "et" => "Estonian",
+        "fi" => "Finnish",
+        "fr" => "French",
+        "hu" => "Hungarian",
+        "id" => "Indonesian",
+        "it" => "Italian",
+        "ja" => "Japanese",
+        "ko" => "Korean",
+        "lt" => "Lithuanian",
+        "lv" => "Latvian",
+        "nb" => "Norwegian (Bokmål)",
+        "nl" => "Dutch",
+        "pl" => "Polish",
+        "pt" => "Portuguese",
+        "ro" => "Romanian",
+        "ru" => "Russian",
+        "sk" => "Slovak",
+        "sl" => "Slovenian",
+        "sv" => "Swedish",
+        "tr" => "Turkish",
+        "uk" => "Ukrainian",
+        "zh" => "Chinese",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_from_str() {
+        for lang in SourceLanguage::ALL {
+            assert_eq!(lang.as_langcode().parse::<SourceLanguage>().unwrap(), lang);
+            assert!(!lang.name().is_empty());
+        }
+        for lang in TargetLanguage::ALL {
+            assert_eq!(lang.as_langcode().parse::<TargetLanguage>().unwrap(), lang);
+            assert!(!lang.name().is_empty());
+        }
+
+        assert_eq!(
+            "PT-BR".parse::<TargetLanguage>().unwrap(),
+            TargetLanguage::PtBr
+        );
+        assert_eq!("pt".parse::<TargetLanguage>().unwrap().as_langcode(), "pt");
+        // Regional variants are only for target
+        assert!("en-us".parse::<SourceLanguage>().is_err());
+        assert!("xx".parse::<TargetLanguage>().is_err());
+    }
+
+    #[test]
+    fn test_target_language() {
+        assert_eq!(TargetLanguage::EnGb.glossary_langcode(), "en");
+        assert_eq!(TargetLanguage::Ja.glossary_langcode(), "ja");
+        assert!(TargetLanguage::PtBr.supports_formality());
+        assert!(!TargetLanguage::EnUs.supports_formality());
+    }
+}
@@ -0,0 +1,480 @@
+// SPDX-License-Identifier: MIT
+//!
+//! Directory layouts of static site generators
+//!
+//! Computes output paths of translated files for each target language.
+//!
+
+use crate::deepl::{Formality, SourceLanguage, TargetLanguage};
+use crate::error::{Error, Result};
+use crate::translator::Translator;
+use std::path::{Path, PathBuf};
+
+/// Source file and its output file for each target language
+pub type TranslationFiles = Vec<(PathBuf, Vec<(TargetLanguage, PathBuf)>)>;
+
+/// Directory layout of input and output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Output path is given by `--output`
+    #[default]
+    Plain,
+    /// Docusaurus site, translated files are placed in `i18n/<locale>/`
+    Docusaurus,
+    /// Hugo content, translated by file name (`page.<lang>.md`) or by content directory
+    Hugo,
+    /// Zola content, translated by file name (`page.<lang>.md`)
+    Zola,
+}
+
+impl std::str::FromStr for Layout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "plain" => Ok(Self::Plain),
+            "docusaurus" => Ok(Self::Docusaurus),
+            "hugo" => Ok(Self::Hugo),
+            "zola" => Ok(Self::Zola),
+            _ => Err(Error::Parse(format!("unknown layout \"{}\"", s))),
+        }
+    }
+}
+
+/// Content directories of Docusaurus site, and their directories in `i18n/<locale>/`
+const DOCUSAURUS_CONTENTS: [(&str, &str); 3] = [
+    ("docs", "docusaurus-plugin-content-docs/current"),
+    ("blog", "docusaurus-plugin-content-blog"),
+    ("src/pages", "docusaurus-plugin-content-pages"),
+];
+
+/// Docusaurus locale of target language, e.g. "ja", "pt-BR", "zh-Hans"
+pub fn docusaurus_locale(lang: TargetLanguage) -> String {
+    match lang.as_langcode().split_once('-') {
+        Some((primary, script)) if script.len() == 4 => {
+            // Script subtag is title case
+            let (head, tail) = script.split_at(1);
+            format!("{}-{}{}", primary, head.to_ascii_uppercase(), tail)
+        }
+        Some((primary, region)) => format!("{}-{}", primary, region.to_ascii_uppercase()),
+        None => lang.as_langcode().to_string(),
+    }
+}
+
+/// Files of Docusaurus site to be translated
+///
+/// - `.md`/`.mdx` in `docs`, `blog` and `src/pages` are written to `i18n/<locale>/docusaurus-plugin-content-*/`
+/// - JSON translation files in `i18n/<source locale>/` are written to the same path in `i18n/<locale>/`.
+///   They are skipped if the source language is detected automatically.
+pub fn docusaurus_files<T: Translator>(
+    translator: &T,
+    site_dir: &Path,
+    from_lang: Option<SourceLanguage>,
+    langs_to: &[TargetLanguage],
+    max_depth: usize,
+) -> Result<TranslationFiles> {
+    let i18n_dir = site_dir.join("i18n");
+    let mut files = Vec::new();
+
+    for (content_dir, plugin_dir) in DOCUSAURUS_CONTENTS {
+        let content_dir = site_dir.join(content_dir);
+        if !content_dir.is_dir() {
+            continue;
+        }
+        for path in crate::walkdir::new(translator, content_dir.clone(), max_depth, true)? {
+            if !has_extension(&path, &["md", "mdx"]) {
+                continue;
+            }
+            let Ok(relpath) = path.strip_prefix(&content_dir) else {
+                continue;
+            };
+            let targets = langs_to
+                .iter()
+                .map(|lang| {
+                    let output = i18n_dir
+                        .join(docusaurus_locale(*lang))
+                        .join(plugin_dir)
+                        .join(relpath);
+                    (*lang, output)
+                })
+                .collect();
+            files.push((path.clone(), targets));
+        }
+    }
+
+    let Some(from_lang) = from_lang else {
+        log::warn!("JSON translation files are skipped, source language is not specified");
+        return Ok(files);
+    };
+    let source_dir = i18n_dir.join(from_lang.as_langcode());
+    if !source_dir.is_dir() {
+        log::warn!(
+            "{:?} NOT found, run `docusaurus write-translations --locale {}` to translate JSON files",
+            source_dir,
+            from_lang.as_langcode()
+        );
+        return Ok(files);
+    }
+    for entry in ::walkdir::WalkDir::new(&source_dir)
+        .max_depth(max_depth)
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        let path = entry.path();
+        if !entry.file_type().is_file() || !has_extension(path, &["json"]) {
+            continue;
+        }
+        let Ok(relpath) = path.strip_prefix(&source_dir) else {
+            continue;
+        };
+        let targets = langs_to
+            .iter()
+            .map(|lang| (*lang, i18n_dir.join(docusaurus_locale(*lang)).join(relpath)))
+            .collect();
+        files.push((path.to_path_buf(), targets));
+    }
+    Ok(files)
+}
+
+/// Check file is Hugo/Zola content to be translated, `.md` without language suffix
+pub fn is_content_file(path: &Path) -> bool {
+    has_extension(path, &["md"]) && content_language(path).is_none()
+}
+
+/// Language suffix of Hugo/Zola content file, e.g. `ja` of `page.ja.md`
+fn content_language(path: &Path) -> Option<TargetLanguage> {
+    let stem = path.file_stem()?.to_str()?;
+    let (_, suffix) = stem.rsplit_once('.')?;
+    suffix.parse().ok()
+}
+
+/// Hugo/Zola content file translated by file name, `page.md` -> `page.<lang>.md`
+///
+/// Bundles are translated in the same directory, so that page resources are shared.
+/// `index.md` -> `index.<lang>.md`, `_index.md` -> `_index.<lang>.md`
+pub fn translated_content_path(path: &Path, lang: TargetLanguage) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}.{}.md", stem, lang.as_langcode()))
+}
+
+/// Files of Hugo/Zola content directory translated by file name
+///
+/// Files already translated (`page.<lang>.md`) are not translated again.
+pub fn content_files<T: Translator>(
+    translator: &T,
+    content_dir: &Path,
+    langs_to: &[TargetLanguage],
+    max_depth: usize,
+) -> Result<TranslationFiles> {
+    let files = crate::walkdir::new(translator, content_dir.to_path_buf(), max_depth, true)?
+        .into_iter()
+        .filter(|path| is_content_file(path))
+        .map(|path| {
+            let targets = langs_to
+                .iter()
+                .map(|lang| (*lang, translated_content_path(&path, *lang)))
+                .collect();
+            (path, targets)
+        })
+        .collect();
+    Ok(files)
+}
+
+/// Link destination to Hugo/Zola content translated by file name
+///
+/// Relative links to `.md` files and Zola internal links (`@/path/page.md`) are rewritten to `.<lang>.md`,
+/// only if the linked source file exists. Other links are kept as is, returns None.
+pub fn translated_link(
+    content_dir: &Path,
+    src_path: &Path,
+    lang: TargetLanguage,
+    href: &str,
+) -> Option<String> {
+    // Keep fragment and query, e.g. "page.md#section"
+    let (path, fragment) = href.split_at(href.find(['#', '?']).unwrap_or(href.len()));
+    let linked = if let Some(internal) = path.strip_prefix("@/") {
+        content_dir.join(internal)
+    } else if path.contains(':') || path.starts_with('/') {
+        // URL with scheme, or absolute path
+        return None;
+    } else {
+        src_path.parent()?.join(path)
+    };
+    if !is_content_file(&linked) || !linked.is_file() {
+        return None;
+    }
+    let stem = path.strip_suffix(".md")?;
+    Some(format!("{}.{}.md{}", stem, lang.as_langcode(), fragment))
+}
+
+/// Translate Docusaurus JSON translation file into multiple target languages
+///
+/// Only `message` fields are translated, `description` fields are kept as is.
+/// Returns result of each target in the same order as `targets`.
+pub async fn translate_docusaurus_json_file<T: Translator, P: AsRef<Path>, Q: AsRef<Path>>(
+    translator: &T,
+    from_lang: SourceLanguage,
+    formality: Formality,
+    src_path: P,
+    targets: &[(TargetLanguage, Q)],
+) -> Result<Vec<Result<()>>> {
+    let json = std::fs::read_to_string(&src_path)?;
+    let json = &json;
+    let results = futures::future::join_all(targets.iter().map(|(to_lang, dst_path)| async move {
+        let translated =
+            translate_docusaurus_json(translator, from_lang, *to_lang, formality, json).await?;
+        if let Some(parent) = dst_path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(std::fs::write(dst_path, translated)?)
+    }))
+    .await;
+    Ok(results)
+}
+
+/// Translate `message` fields of Docusaurus JSON translation file
+///
+/// `{"key": {"message": "...", "description": "..."}}`
+pub async fn translate_docusaurus_json<T: Translator>(
+    translator: &T,
+    from_lang: SourceLanguage,
+    to_lang: TargetLanguage,
+    formality: Formality,
+    json: &str,
+) -> Result<String> {
+    let mut root: serde_json::Value = serde_json::from_str(json)?;
+    let serde_json::Value::Object(entries) = &mut root else {
+        return Err(Error::Parse(String::from(
+            "Docusaurus translation file is not an object",
+        )));
+    };
+
+    // Pickup messages for translation
+    let mut messages = entries
+        .values_mut()
+        .filter_map(|entry| match entry.get_mut("message") {
+            Some(serde_json::Value::String(message)) if !message.trim().is_empty() => Some(message),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    if !messages.is_empty() {
+        let src_vec = messages.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        let translated_vec = translator
+            .translate_strings(from_lang, to_lang, formality, &src_vec)
+            .await?;
+        if translated_vec.len() != messages.len() {
+            return Err(Error::Parse(format!(
+                "{} messages are translated into {} messages",
+                messages.len(),
+                translated_vec.len()
+            )));
+        }
+        for (message, translated) in messages.iter_mut().zip(translated_vec) {
+            **message = translated;
+        }
+    }
+
+    // Docusaurus writes JSON with 2 spaces indent
+    let mut translated = serde_json::to_string_pretty(&root)?;
+    translated.push('\n');
+    Ok(translated)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.contains(&ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockStyle, MockTranslator};
+
+    #[test]
+    fn test_docusaurus_locale() {
+        assert_eq!(docusaurus_locale(TargetLanguage::Ja), "ja");
+        assert_eq!(docusaurus_locale(TargetLanguage::PtBr), "pt-BR");
+        assert_eq!(docusaurus_locale(TargetLanguage::ZhHans), "zh-Hans");
+    }
+
+    #[tokio::test]
+    async fn test_translate_docusaurus_json() {
+        let translator = MockTranslator::new(MockStyle::Brackets);
+        let json = r#"{
+  "theme.NotFound.title": {
+    "message": "Page Not Found",
+    "description": "The title of the 404 page"
+  },
+  "sidebar.docs.category.Guides": {
+    "message": "Guides"
+  },
+  "empty": {
+    "message": ""
+  }
+}"#;
+        let translated = translate_docusaurus_json(
+            &translator,
+            SourceLanguage::En,
+            TargetLanguage::Ja,
+            Formality::Default,
+            json,
+        )
+        .await
+        .unwrap();
+        let expected = r#"{
+  "theme.NotFound.title": {
+    "message": "⟦Page Not Found⟧",
+    "description": "The title of the 404 page"
+  },
+  "sidebar.docs.category.Guides": {
+    "message": "⟦Guides⟧"
+  },
+  "empty": {
+    "message": ""
+  }
+}
+"#;
+        assert_eq!(translated, expected);
+    }
+
+    #[test]
+    fn test_docusaurus_files() {
+        let translator = MockTranslator::new(MockStyle::default());
+        let site_dir =
+            std::env::temp_dir().join(format!("cmark-translate-docusaurus-{}", std::process::id()));
+        std::fs::create_dir_all(site_dir.join("docs/guides")).unwrap();
+        std::fs::create_dir_all(site_dir.join("i18n/en/docusaurus-theme-classic")).unwrap();
+        std::fs::write(site_dir.join("docs/intro.md"), "# Intro").unwrap();
+        std::fs::write(site_dir.join("docs/guides/start.mdx"), "# Start").unwrap();
+        std::fs::write(site_dir.join("docs/_category_.json"), "{}").unwrap();
+        std::fs::write(site_dir.join("i18n/en/code.json"), "{}").unwrap();
+        std::fs::write(
+            site_dir.join("i18n/en/docusaurus-theme-classic/navbar.json"),
+            "{}",
+        )
+        .unwrap();
+
+        let mut files = docusaurus_files(
+            &translator,
+            &site_dir,
+            Some(SourceLanguage::En),
+            &[TargetLanguage::Ja, TargetLanguage::PtBr],
+            usize::MAX,
+        )
+        .unwrap();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        let relative = |path: &Path| {
+            path.strip_prefix(&site_dir)
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/")
+        };
+        let files = files
+            .iter()
+            .map(|(src, targets)| {
+                (
+                    relative(src),
+                    targets
+                        .iter()
+                        .map(|(_, dst)| relative(dst))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        std::fs::remove_dir_all(&site_dir).unwrap();
+
+        assert_eq!(
+            files,
+            vec![
+                (
+                    String::from("docs/guides/start.mdx"),
+                    vec![
+                        String::from(
+                            "i18n/ja/docusaurus-plugin-content-docs/current/guides/start.mdx"
+                        ),
+                        String::from(
+                            "i18n/pt-BR/docusaurus-plugin-content-docs/current/guides/start.mdx"
+                        ),
+                    ]
+                ),
+                (
+                    String::from("docs/intro.md"),
+                    vec![
+                        String::from("i18n/ja/docusaurus-plugin-content-docs/current/intro.md"),
+                        String::from("i18n/pt-BR/docusaurus-plugin-content-docs/current/intro.md"),
+                    ]
+                ),
+                (
+                    String::from("i18n/en/code.json"),
+                    vec![
+                        String::from("i18n/ja/code.json"),
+                        String::from("i18n/pt-BR/code.json"),
+                    ]
+                ),
+                (
+                    String::from("i18n/en/docusaurus-theme-classic/navbar.json"),
+                    vec![
+                        String::from("i18n/ja/docusaurus-theme-classic/navbar.json"),
+                        String::from("i18n/pt-BR/docusaurus-theme-classic/navbar.json"),
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_content_files() {
+        let translator = MockTranslator::new(MockStyle::default());
+        let content_dir =
+            std::env::temp_dir().join(format!("cmark-translate-content-{}", std::process::id()));
+        std::fs::create_dir_all(content_dir.join("post")).unwrap();
+        std::fs::write(content_dir.join("_index.md"), "# Home").unwrap();
+        std::fs::write(content_dir.join("about.md"), "[post](post/index.md#intro)").unwrap();
+        std::fs::write(content_dir.join("post/index.md"), "# Post").unwrap();
+        std::fs::write(content_dir.join("post/index.ja.md"), "# 投稿").unwrap();
+        std::fs::write(content_dir.join("post/image.png"), "").unwrap();
+
+        let mut files =
+            content_files(&translator, &content_dir, &[TargetLanguage::Ja], usize::MAX).unwrap();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        let relative = |path: &Path| {
+            path.strip_prefix(&content_dir)
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/")
+        };
+        let relpaths = files
+            .iter()
+            .map(|(src, targets)| (relative(src), relative(&targets[0].1)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            relpaths,
+            vec![
+                (String::from("_index.md"), String::from("_index.ja.md")),
+                (String::from("about.md"), String::from("about.ja.md")),
+                (
+                    String::from("post/index.md"),
+                    String::from("post/index.ja.md")
+                ),
+            ]
+        );
+
+        // Links to existing contents are rewritten
+        let src_path = content_dir.join("about.md");
+        let link = |href: &str| translated_link(&content_dir, &src_path, TargetLanguage::Ja, href);
+        assert_eq!(
+            link("post/index.md#intro").as_deref(),
+            Some("post/index.ja.md#intro")
+        );
+        assert_eq!(link("@/_index.md").as_deref(), Some("@/_index.ja.md"));
+        assert_eq!(link("missing.md"), None);
+        assert_eq!(link("post/image.png"), None);
+        assert_eq!(link("post/index.ja.md"), None);
+        assert_eq!(link("https://example.com/index.md"), None);
+        assert_eq!(link("#top"), None);
+
+        std::fs::remove_dir_all(&content_dir).unwrap();
+    }
+}
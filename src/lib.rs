@@ -1,15 +1,49 @@
+mod cmark_fmt;
 mod cmark_xml;
 mod deepl;
+mod error;
+mod frontmatter;
 mod glossary;
+mod language;
+mod layout;
+mod manifest;
+mod memory;
+mod mock;
+mod ratelimit;
+mod segment;
+mod toml_text;
 mod trans;
+mod translator;
 mod walkdir;
+mod yaml;
 
 // re-export
 pub use crate::walkdir::new;
 pub use cmark_xml::{
-    cmark_from_xml, cmark_from_xmldom, read_cmark_with_frontmatter, xml_from_cmark,
-    xmldom_from_cmark,
+    cmark_from_xml, cmark_from_xmldom, cmark_from_xmldom_with_extensions,
+    read_cmark_with_frontmatter, rewrite_links, xml_from_cmark, xmldom_from_cmark,
+    xmldom_from_cmark_with_extensions, CmarkExtensions,
 };
-pub use deepl::{Deepl, DeeplGlossary, Formality, Language};
-pub use glossary::read_glossary;
-pub use trans::{translate_cmark, translate_cmark_file, translate_toml};
+pub use deepl::{
+    find_glossary, glossary_source_languages, Deepl, DeeplConfig, DeeplGlossary, Formality,
+};
+pub use error::{Error, Result};
+pub use frontmatter::{FrontmatterKeys, KeyPattern, PathSegment, DEFAULT_FRONTMATTER_KEYS};
+pub use glossary::{
+    read_glossary, read_glossary_delimited, read_glossary_file, read_glossary_xlsx, sync_glossary,
+    GlossaryChange, GlossaryFormat, GlossarySync,
+};
+pub use language::{DeeplLanguage, SourceLanguage, TargetLanguage};
+pub use layout::{
+    content_files, docusaurus_files, docusaurus_locale, is_content_file, translate_docusaurus_json,
+    translate_docusaurus_json_file, translated_content_path, translated_link, Layout,
+    TranslationFiles,
+};
+pub use manifest::{file_hash, Manifest, ManifestEntry, MANIFEST_FILE_NAME};
+pub use memory::TranslationMemory;
+pub use mock::{MockStyle, MockTranslator};
+pub use trans::{
+    detect_cmark_file_language, translate_cmark, translate_cmark_file, translate_cmark_file_multi,
+    translate_cmark_file_with_links, translate_json, translate_toml, translate_yaml, LinkRewriter,
+};
+pub use translator::Translator;
@@ -1,13 +1,13 @@
-mod cmark_xml;
-mod deepl;
-mod glossary;
-mod trans;
-mod walkdir;
-
 use std::path::PathBuf;
 
 use clap::{CommandFactory, Parser};
-// use walkdir::WalkDir;
+use cmark_translate::{
+    content_files, detect_cmark_file_language, docusaurus_files, file_hash,
+    glossary_source_languages, is_content_file, read_glossary_file, sync_glossary,
+    translate_cmark_file_with_links, translate_docusaurus_json_file, translated_link, Deepl, Error,
+    Formality, GlossaryFormat, Layout, Manifest, MockStyle, MockTranslator, Result, SourceLanguage,
+    TargetLanguage, TranslationFiles, Translator, MANIFEST_FILE_NAME,
+};
 
 #[derive(clap::Parser)]
 #[command(author, version, about, long_about = None)]
@@ -16,6 +16,27 @@ struct Cli {
     #[arg(short, long, value_name = "FILE")]
     config: Option<std::path::PathBuf>,
 
+    /// DeepL API base URL, overrides `endpoint` in config file
+    #[arg(long, value_name = "URL")]
+    endpoint: Option<String>,
+
+    /// Glossary name or ID used for translation, overrides `glossary` in config file
+    #[arg(short, long, value_name = "NAME_OR_ID")]
+    glossary: Option<String>,
+
+    /// Do not use translation memory, translate all texts
+    #[arg(long)]
+    no_memory: bool,
+
+    /// Rate limit of DeepL API requests, overrides `requests_per_second` in config file
+    #[arg(long, value_name = "N")]
+    requests_per_second: Option<f64>,
+
+    /// Use offline pseudo translation instead of DeepL API (for testing)
+    /// STYLE: upper, brackets or any prefix text
+    #[arg(long, value_name = "STYLE")]
+    mock: Option<String>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -24,12 +45,13 @@ struct Cli {
 enum Commands {
     /// Translate a CommonMark file
     Translate {
-        /// Source language (ISO639-1 2 letter code)
+        /// Source language (ISO639-1 2 letter code), or "auto" to detect for each file
         #[arg(short, long)]
         from: String,
-        /// Target language (ISO639-1 2 letter code)
-        #[arg(short, long)]
-        to: String,
+        /// Target languages (ISO639-1 2 letter code, or regional variant like en-us),
+        /// comma separated for multiple languages
+        #[arg(short, long, value_delimiter = ',', required = true)]
+        to: Vec<String>,
         /// Formality - formal or informal
         #[arg(long)]
         formality: Option<String>,
@@ -40,9 +62,23 @@ enum Commands {
         /// Default: max
         #[arg(short, long)]
         max_depth: Option<usize>,
-        /// Output translated CommonMark file
+        /// Output translated CommonMark file or directory.
+        /// `{lang}` is replaced with target language, `{relpath}` with path relative to input,
+        /// e.g. i18n/{lang}/{relpath}
         #[arg(short, long)]
         output: Option<String>,
+        /// Translate all files, even if source is not changed since last translation
+        #[arg(long)]
+        force: bool,
+        /// Number of files translated concurrently
+        #[arg(short, long, default_value = "4")]
+        jobs: std::num::NonZeroUsize,
+        /// Directory layout - plain, docusaurus, hugo or zola.
+        /// For docusaurus, input is the site directory and output paths are computed for each locale.
+        /// For hugo and zola, input is the content directory and `page.<lang>.md` is written next to the source,
+        /// or into output directory like content/{lang} for hugo
+        #[arg(long, default_value = "plain")]
+        layout: String,
     },
     /// Manage glossaries
     Glossary {
@@ -51,11 +87,13 @@ enum Commands {
     },
     /// Show DeepL usage
     Usage,
+    /// List languages supported by DeepL
+    Languages,
 }
 
 #[derive(clap::Subcommand)]
 enum GlossaryCommands {
-    /// Register glossary TSV file
+    /// Register glossary file
     Register {
         /// Glossary name
         #[arg(short, long)]
@@ -63,10 +101,37 @@ enum GlossaryCommands {
         /// Source language (ISO639-1 2 letter code)
         #[arg(short, long)]
         from: String,
-        /// Target language (ISO639-1 2 letter code)
+        /// Target language (ISO639-1 2 letter code, or regional variant like en-us)
+        #[arg(short, long)]
+        to: String,
+        /// Glossary file format - toml, xlsx, tsv or csv. Detected by extension if not specified
+        #[arg(long)]
+        format: Option<String>,
+        /// Sheet name of .xlsx file, the first sheet is used if not specified
+        #[arg(long)]
+        sheet: Option<String>,
+        /// Input glossary file - TOML with [glossaries.<name>] table,
+        /// or xlsx/TSV/CSV whose first row should contain language codes
+        input: std::path::PathBuf,
+    },
+    /// Register glossary file, replacing registered glossary of the same name if entries differ
+    Sync {
+        /// Glossary name
+        #[arg(short, long)]
+        name: String,
+        /// Source language (ISO639-1 2 letter code)
+        #[arg(short, long)]
+        from: String,
+        /// Target language (ISO639-1 2 letter code, or regional variant like en-us)
         #[arg(short, long)]
         to: String,
-        /// Input glossary TSV file - First row should contain language codes
+        /// Glossary file format - toml, xlsx, tsv or csv. Detected by extension if not specified
+        #[arg(long)]
+        format: Option<String>,
+        /// Sheet name of .xlsx file, the first sheet is used if not specified
+        #[arg(long)]
+        sheet: Option<String>,
+        /// Input glossary file, same as register
         input: std::path::PathBuf,
     },
     /// List registered glossaries
@@ -79,164 +144,660 @@ enum GlossaryCommands {
 }
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> std::io::Result<()> {
+async fn main() -> Result<()> {
     use std::str::FromStr;
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
 
-    async fn deepl_with_config() -> Result<deepl::Deepl, std::io::Error> {
-        // parse commandline
-        let cli = Cli::parse();
-        if let Some(cfg_file) = cli.config {
-            deepl::Deepl::with_config(&cfg_file)
-        } else {
-            deepl::Deepl::new()
+    // parse commandline
+    let cli = Cli::parse();
+
+    let Some(command) = cli.command else {
+        // Print help
+        Cli::command().print_help()?;
+        return Ok(());
+    };
+
+    // Load DeepL config
+    let mut deepl = if let Some(cfg_file) = cli.config {
+        Deepl::with_config(&cfg_file)?
+    } else {
+        Deepl::new()?
+    };
+    if cli.endpoint.is_some() {
+        deepl.config.endpoint = cli.endpoint;
+    }
+    if cli.glossary.is_some() {
+        deepl.config.glossary = cli.glossary;
+    }
+    if cli.no_memory {
+        deepl = deepl.without_memory();
+    }
+    if let Some(requests_per_second) = cli.requests_per_second {
+        deepl = deepl.with_requests_per_second(requests_per_second);
+    }
+
+    if let Some(style) = cli.mock {
+        let style = MockStyle::from_str(&style)?;
+        let mock = MockTranslator::with_config(deepl.config, style);
+        run(&mock, command).await
+    } else {
+        run(&deepl, command).await
+    }
+}
+
+/// Output path of translated file, `{lang}` and `{relpath}` in output are replaced
+fn output_path(
+    output: &str,
+    lang_to: TargetLanguage,
+    relpath: &str,
+    is_dir_output: bool,
+) -> PathBuf {
+    let path = output.replace("{lang}", lang_to.as_langcode());
+    if path.contains("{relpath}") {
+        PathBuf::from(path.replace("{relpath}", relpath))
+    } else if is_dir_output {
+        PathBuf::from(path).join(relpath)
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+/// Files in input and their output paths for each target language, and manifest directory
+fn plain_files<T: Translator>(
+    translator: &T,
+    input: &str,
+    output: Option<String>,
+    langs_to: &[TargetLanguage],
+    max_depth: usize,
+) -> Result<(TranslationFiles, PathBuf)> {
+    let output = output.unwrap_or(input.to_string());
+    let sep = std::path::MAIN_SEPARATOR.to_string();
+    if 1 < langs_to.len() && !output.contains("{lang}") {
+        return Err(Error::Config(String::from(
+            "Output should contain {lang} for multiple target languages",
+        )));
+    }
+
+    let input_path = PathBuf::from(input);
+    let is_dir_input = input_path.is_dir();
+    // `{relpath}` is replaced with path of each file relative to input
+    let has_relpath = output.contains("{relpath}");
+    let is_dir_output = !has_relpath && PathBuf::from(&output).extension().is_none();
+    if !has_relpath && is_dir_input != is_dir_output {
+        return Err(Error::Config(String::from(
+            "Input and output should be both directory or file",
+        )));
+    }
+    let files = if is_dir_input {
+        // TODO: コマンドライン引数で拡張子と隠しファイルの指定を可能にする
+        // let ext = Some(vec!["md"]);
+        let hidden = true;
+
+        // inputディレクトリを再帰処理して (翻訳対象ファイルPath, inputからの相対パス) の Vecを生成する。
+        cmark_translate::new(translator, PathBuf::from(input), max_depth, hidden)?
+            .iter()
+            .filter(|file_path| file_path.file_name() != Some(MANIFEST_FILE_NAME.as_ref()))
+            .map(|file_path| {
+                let file_path_string = file_path.to_string_lossy().to_string();
+
+                // file_path から output 用の相対パスを生成する。
+                // path_join_string の先頭文字列がOSの separator文字列だと、
+                // 後続の Path の join で path_join_string だけが有効になってしまうので
+                // 先頭の separator文字列は削除する。
+                let mut path_join_string = file_path_string.replacen(input, "", 1);
+                path_join_string = if path_join_string.starts_with(&sep) {
+                    path_join_string.replacen(&sep, "", 1)
+                } else {
+                    path_join_string
+                };
+
+                (PathBuf::from(&file_path_string), path_join_string)
+            })
+            .collect::<Vec<_>>()
+    } else {
+        let file_name = input_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        vec![(input_path, file_name)]
+    };
+    let files = files
+        .into_iter()
+        .map(|(file_path, relpath)| {
+            let targets = langs_to
+                .iter()
+                .map(|lang_to| {
+                    let path = output_path(&output, *lang_to, &relpath, is_dir_output);
+                    (*lang_to, path)
+                })
+                .collect();
+            (file_path, targets)
+        })
+        .collect();
+
+    // Manifest is placed in output directory, or the fixed part of output template.
+    let manifest_dir = match output.find('{') {
+        Some(pos) if output[..pos].ends_with(['/', std::path::MAIN_SEPARATOR]) => {
+            PathBuf::from(&output[..pos])
+        }
+        Some(pos) => PathBuf::from(&output[..pos])
+            .parent()
+            .unwrap_or(std::path::Path::new(""))
+            .to_path_buf(),
+        None if is_dir_output => PathBuf::from(&output),
+        None => PathBuf::from(&output)
+            .parent()
+            .unwrap_or(std::path::Path::new(""))
+            .to_path_buf(),
+    };
+    Ok((files, manifest_dir))
+}
+
+/// Status of translated file in run report
+#[derive(Clone)]
+enum FileStatus {
+    Translated,
+    /// Skipped with reason
+    Skipped(String),
+    /// Failed with error
+    Failed(String),
+}
+
+/// Report of a source file, printed after translating all files
+struct FileReport {
+    input: PathBuf,
+    /// Source language given by `--from`, or detected
+    source_lang: Option<SourceLanguage>,
+    /// Status of each output file
+    outputs: Vec<(PathBuf, FileStatus)>,
+    /// Glossaries not applied to the detected language
+    warnings: Vec<String>,
+}
+
+impl FileReport {
+    fn new(input: &std::path::Path, source_lang: Option<SourceLanguage>) -> Self {
+        Self {
+            input: input.to_path_buf(),
+            source_lang,
+            outputs: Vec::new(),
+            warnings: Vec::new(),
         }
     }
 
-    // Load DeepL config
-    let deepl = deepl_with_config().await;
-    // parse commandline
-    let cli = Cli::parse();
+    fn push(&mut self, output: &std::path::Path, status: FileStatus) {
+        self.outputs.push((output.to_path_buf(), status));
+    }
+
+    fn push_all(&mut self, targets: &[(TargetLanguage, PathBuf)], status: FileStatus) {
+        for (_, output) in targets {
+            self.push(output, status.clone());
+        }
+    }
+}
+
+/// Print table of status, source language and output of each file, and glossary warnings
+fn print_report(reports: &[FileReport]) {
+    let count = |f: fn(&FileStatus) -> bool| {
+        reports
+            .iter()
+            .flat_map(|report| &report.outputs)
+            .filter(|(_, status)| f(status))
+            .count()
+    };
+    println!();
+    println!("{:<10} {:<6} FILE", "STATUS", "LANG");
+    for report in reports {
+        let lang = report.source_lang.map_or("-", |lang| lang.as_langcode());
+        for (output, status) in &report.outputs {
+            let (label, detail) = match status {
+                FileStatus::Translated => ("translated", String::new()),
+                FileStatus::Skipped(reason) => ("skipped", format!(" ({})", reason)),
+                FileStatus::Failed(e) => ("failed", format!(" ({})", e)),
+            };
+            println!(
+                "{:<10} {:<6} {:?} -> {:?}{}",
+                label, lang, report.input, output, detail
+            );
+        }
+        for warning in &report.warnings {
+            println!(
+                "{:<10} {:<6} {:?} : {}",
+                "warning", lang, report.input, warning
+            );
+        }
+    }
+    println!(
+        "{} translated, {} skipped, {} failed",
+        count(|status| matches!(status, FileStatus::Translated)),
+        count(|status| matches!(status, FileStatus::Skipped(_))),
+        count(|status| matches!(status, FileStatus::Failed(_))),
+    );
+}
+
+/// Run subcommand with given translation backend
+async fn run<T: Translator>(translator: &T, command: Commands) -> Result<()> {
+    use std::str::FromStr;
 
-    match cli.command {
-        Some(Commands::Translate {
+    match command {
+        Commands::Translate {
             from,
             to,
             formality,
             input,
             max_depth,
             output,
-        }) => {
+            force,
+            jobs,
+            layout,
+        } => {
+            use futures::StreamExt;
+
             // Translate CommonMark file
-            let lang_from = deepl::Language::from_str(&from)?;
-            let lang_to = deepl::Language::from_str(&to)?;
-            let formality = formality.map_or(Ok(deepl::Formality::Default), |f| {
-                deepl::Formality::from_str(&f)
-            })?;
-            let max_depth = max_depth.unwrap_or(usize::MAX);
-            let output = output.unwrap_or(input.clone());
-            let sep = std::path::MAIN_SEPARATOR.to_string();
-
-            let input_path = PathBuf::from(&input);
-            let input_output = PathBuf::from(&output);
-            let is_dir_input = input_path.is_dir();
-            let is_dir_output = input_output.extension().is_none();
-            if is_dir_input != is_dir_output {
-                panic!("Input and output should be both directory or file");
-            }
-            let files = if is_dir_input {
-                // TODO: コマンドライン引数で拡張子と隠しファイルの指定を可能にする
-                // let ext = Some(vec!["md"]);
-                let hidden = true;
-                // return 用 (翻訳対象ファイルPath, 出力ファイルPath) の Vecを生成
-                let mut files = Vec::new();
-
-                // inputディレクトリを再帰処理して翻訳対象ファイルPath, 出力ファイルPathを生成する。
-                let deepl = deepl_with_config().await;
-                let _paths =
-                    walkdir::new(&deepl.unwrap(), PathBuf::from(&input), max_depth, hidden)
-                        .iter()
-                        .map(|e| {
-                            let file_path = e.as_path();
-                            let file_path_string = file_path.to_str().unwrap().to_string();
-
-                            // file_path を取得し output 用の file_path を生成する。
-                            // path_join_string の先頭文字列がOSの separator文字列だと、
-                            // 後続の Path の join で path_join_string だけが有効になってしまうので
-                            // 先頭の separator文字列は削除する。
-                            let mut path_join_string = file_path_string.replacen(&input, "", 1);
-                            path_join_string =
-                                if path_join_string.chars().nth(0).unwrap().to_string() == sep {
-                                    path_join_string.replacen(&sep, "", 1)
-                                } else {
-                                    path_join_string
-                                };
-
-                            files.push((
-                                PathBuf::from(&file_path_string),
-                                PathBuf::from(&output).join(path_join_string),
-                            ));
-
-                            Some(())
-                        })
-                        .collect::<Vec<_>>();
-                files
+            // "auto" detects source language of each file
+            let lang_from = if from.eq_ignore_ascii_case("auto") {
+                None
             } else {
-                vec![(input_path, input_output.clone())]
+                Some(SourceLanguage::from_str(&from)?)
             };
-
-            let res = files
+            let langs_to = to
                 .iter()
-                .map(|i| async move {
-                    let (input, output) = i;
-                    // Reload DeepL config
-                    let deepl = deepl_with_config().await;
-
-                    // run translation
-                    let res = trans::translate_cmark_file(
-                        &deepl.unwrap(),
+                .map(|to| TargetLanguage::from_str(to))
+                .collect::<Result<Vec<_>>>()?;
+            let formality =
+                formality.map_or(Ok(Formality::Default), |f| Formality::from_str(&f))?;
+            for lang_to in &langs_to {
+                translator
+                    .check_target_language(*lang_to, formality)
+                    .await?;
+            }
+
+            // Resolve glossary once before translating files, fails if not ready.
+            // For detected languages, glossary is resolved for each detected language.
+            let glossaries = match lang_from {
+                Some(_) => Vec::new(),
+                None => translator.list_glossaries().await?,
+            };
+            let mut glossary_sources = Vec::new();
+            for lang_to in &langs_to {
+                match lang_from {
+                    Some(lang_from) => {
+                        if let Some(glossary_id) =
+                            translator.glossary_id(lang_from, *lang_to).await?
+                        {
+                            println!("Use glossary {} for {}", glossary_id, lang_to.as_langcode());
+                        }
+                    }
+                    None => glossary_sources.push((
+                        *lang_to,
+                        glossary_source_languages(
+                            &glossaries,
+                            translator.config().glossary_name(),
+                            *lang_to,
+                        ),
+                    )),
+                }
+            }
+            let glossary_sources = &glossary_sources;
+
+            let max_depth = max_depth.unwrap_or(usize::MAX);
+            let layout = Layout::from_str(&layout)?;
+            // Links to other contents are rewritten when translated by file name
+            let rewrite_links = match layout {
+                Layout::Hugo => output.is_none(),
+                Layout::Zola => true,
+                _ => false,
+            };
+            let (files, manifest_dir) = match layout {
+                Layout::Plain => plain_files(translator, &input, output, &langs_to, max_depth)?,
+                Layout::Docusaurus => {
+                    if output.is_some() {
+                        return Err(Error::Config(String::from(
+                            "Output can not be specified for docusaurus layout",
+                        )));
+                    }
+                    let site_dir = PathBuf::from(&input);
+                    if !site_dir.is_dir() {
+                        return Err(Error::Config(String::from(
+                            "Input should be Docusaurus site directory",
+                        )));
+                    }
+                    let files =
+                        docusaurus_files(translator, &site_dir, lang_from, &langs_to, max_depth)?;
+                    (files, site_dir.join("i18n"))
+                }
+                Layout::Hugo | Layout::Zola => {
+                    let content_dir = PathBuf::from(&input);
+                    if !content_dir.is_dir() {
+                        return Err(Error::Config(String::from(
+                            "Input should be content directory",
+                        )));
+                    }
+                    match output {
+                        // Translation by content directory, e.g. content/{lang}
+                        Some(output) if layout == Layout::Hugo => {
+                            let (mut files, manifest_dir) = plain_files(
+                                translator,
+                                &input,
+                                Some(output),
+                                &langs_to,
+                                max_depth,
+                            )?;
+                            files.retain(|(file_path, _)| is_content_file(file_path));
+                            (files, manifest_dir)
+                        }
+                        Some(_) => {
+                            return Err(Error::Config(String::from(
+                                "Output can not be specified for zola layout",
+                            )));
+                        }
+                        // Translation by file name, page.<lang>.md
+                        None => {
+                            let files =
+                                content_files(translator, &content_dir, &langs_to, max_depth)?;
+                            (files, content_dir)
+                        }
+                    }
+                }
+            };
+            let content_dir = &PathBuf::from(&input);
+
+            // Translated files are recorded in manifest to skip unchanged files next time.
+            let manifest_path = manifest_dir.join(MANIFEST_FILE_NAME);
+            let manifest = std::sync::Mutex::new(Manifest::open(&manifest_path)?);
+            let (manifest, manifest_path) = (&manifest, &manifest_path);
+
+            let res = files.iter().map(|(input, targets)| async move {
+                let mut report = FileReport::new(input, lang_from);
+                let mut targets = targets.clone();
+                if !force {
+                    targets.retain(|(lang_to, output)| {
+                        let up_to_date = manifest
+                            .lock()
+                            .unwrap()
+                            .is_up_to_date(input, output, lang_from, *lang_to, formality);
+                        match up_to_date {
+                            Ok(true) => {
+                                println!("Skipped (unchanged): {:?}", output);
+                                report.push(output, FileStatus::Skipped(String::from("unchanged")));
+                                false
+                            }
+                            Ok(false) => true,
+                            Err(e) => {
+                                log::warn!("Can not check {:?} : {:?}", input, e);
+                                true
+                            }
+                        }
+                    });
+                }
+                // Language is not detected for unchanged files
+                let Some((first_lang_to, _)) = targets.first() else {
+                    return report;
+                };
+
+                let lang_from = match lang_from {
+                    Some(lang_from) => lang_from,
+                    None => {
+                        match detect_cmark_file_language(translator, *first_lang_to, input).await {
+                            Ok(Some(detected)) => {
+                                println!("Detected {}: {:?}", detected.as_langcode(), input);
+                                report.source_lang = Some(detected);
+                                targets.retain(|(lang_to, output)| {
+                                    if detected.as_langcode() == lang_to.glossary_langcode() {
+                                        println!(
+                                            "Skipped (already {}): {:?}",
+                                            detected.as_langcode(),
+                                            input
+                                        );
+                                        report.push(
+                                            output,
+                                            FileStatus::Skipped(format!(
+                                                "already {}",
+                                                detected.as_langcode()
+                                            )),
+                                        );
+                                        return false;
+                                    }
+                                    true
+                                });
+                                for (lang_to, sources) in glossary_sources {
+                                    if targets.iter().any(|(l, _)| l == lang_to)
+                                        && !sources.is_empty()
+                                        && !sources.iter().any(|s| s == detected.as_langcode())
+                                    {
+                                        let warning = format!(
+                                            "Glossary {} -> {} is NOT applied, detected as {}",
+                                            sources.join(", "),
+                                            lang_to.as_langcode(),
+                                            detected.as_langcode()
+                                        );
+                                        log::warn!("{} : {:?}", warning, input);
+                                        report.warnings.push(warning);
+                                    }
+                                }
+                                detected
+                            }
+                            Ok(None) => {
+                                println!("Skipped (unknown language): {:?}", input);
+                                report.push_all(
+                                    &targets,
+                                    FileStatus::Skipped(String::from("unknown language")),
+                                );
+                                return report;
+                            }
+                            Err(e) => {
+                                println!("Error: {:?}", e);
+                                report.push_all(&targets, FileStatus::Failed(e.to_string()));
+                                return report;
+                            }
+                        }
+                    }
+                };
+                if targets.is_empty() {
+                    return report;
+                }
+
+                // Hash source before translation, in case it is edited during translation
+                let source_hash = match file_hash(input) {
+                    Ok(source_hash) => source_hash,
+                    Err(e) => {
+                        println!("Error: {:?}", e);
+                        report.push_all(&targets, FileStatus::Failed(e.to_string()));
+                        return report;
+                    }
+                };
+
+                // run translation, source file is parsed once for all target languages
+                let is_json =
+                    layout == Layout::Docusaurus && input.extension() == Some("json".as_ref());
+                let results = if is_json {
+                    translate_docusaurus_json_file(
+                        translator, lang_from, formality, input, &targets,
+                    )
+                    .await
+                } else {
+                    let rewrite_link = |lang_to, href: &str| {
+                        if rewrite_links {
+                            translated_link(content_dir, input, lang_to, href)
+                        } else {
+                            None
+                        }
+                    };
+                    translate_cmark_file_with_links(
+                        translator,
                         lang_from,
-                        lang_to,
                         formality,
-                        &input,
-                        &output,
+                        input,
+                        &targets,
+                        &rewrite_link,
                     )
-                    .await;
+                    .await
+                };
+                let results = match results {
+                    Ok(results) => results,
+                    Err(e) => {
+                        println!("Error: {:?}", e);
+                        report.push_all(&targets, FileStatus::Failed(e.to_string()));
+                        return report;
+                    }
+                };
 
+                for ((lang_to, output), res) in targets.iter().zip(results) {
                     match res {
-                        Ok(_) => println!("Translated: {:?}", output),
-                        Err(e) => println!("Error: {:?}", e),
+                        Ok(_) => {
+                            println!("Translated: {:?}", output);
+                            report.push(output, FileStatus::Translated);
+                            // In case of overwriting source, the hash of translated file is recorded
+                            let source_hash = if input == output {
+                                file_hash(output)
+                            } else {
+                                Ok(source_hash.clone())
+                            };
+                            let mut manifest = manifest.lock().unwrap();
+                            if let Err(e) = source_hash
+                                .and_then(|source_hash| {
+                                    manifest.record(
+                                        input,
+                                        output,
+                                        &source_hash,
+                                        lang_from,
+                                        *lang_to,
+                                        formality,
+                                    )
+                                })
+                                .and_then(|_| manifest.save())
+                            {
+                                log::error!(
+                                    "Can not update manifest {:?} : {:?}",
+                                    manifest_path,
+                                    e
+                                );
+                            }
+                        }
+                        Err(e) => {
+                            println!("Error: {:?} : {:?}", output, e);
+                            report.push(output, FileStatus::Failed(e.to_string()));
+                        }
                     }
-                    // println!("input  : {:?}", input);
-                    // println!("output : {:?}", output);
-                })
-                .collect::<Vec<_>>();
-            // Wait for all translation tasks
-            futures::future::join_all(res).await;
+                }
+                report
+            });
+            // Wait for all translation tasks, up to `jobs` files at once
+            let mut reports = futures::stream::iter(res)
+                .buffer_unordered(jobs.get())
+                .collect::<Vec<_>>()
+                .await;
+            reports.sort_by(|a, b| a.input.cmp(&b.input));
+            print_report(&reports);
         }
-        Some(Commands::Glossary { command }) => {
+        Commands::Glossary { command } => {
             // Glossary management
             match command {
                 GlossaryCommands::Register {
                     name,
                     from,
                     to,
+                    format,
+                    sheet,
                     input,
                 } => {
-                    let from_lang = deepl::Language::from_str(&from)?;
-                    let to_lang = deepl::Language::from_str(&to)?;
+                    let from_lang = SourceLanguage::from_str(&from)?;
+                    let to_lang = TargetLanguage::from_str(&to)?;
+                    let format = format.map(|f| GlossaryFormat::from_str(&f)).transpose()?;
+                    let glossaries = read_glossary_file(
+                        &name,
+                        &input,
+                        format,
+                        sheet.as_deref(),
+                        from_lang,
+                        to_lang,
+                    )?;
 
-                    let glossaries = glossary::read_glossary(&name, input).unwrap();
+                    let entries = glossaries
+                        .iter()
+                        .map(|(from, to)| (from.as_str(), to.as_str()))
+                        .collect::<Vec<_>>();
+                    let glossary = translator
+                        .register_glossaries(&name, from_lang, to_lang, &entries)
+                        .await?;
+                    println!(
+                        "Total {} entries are registered as ID = {}",
+                        glossary.entry_count, glossary.glossary_id
+                    );
+                }
+                GlossaryCommands::Sync {
+                    name,
+                    from,
+                    to,
+                    format,
+                    sheet,
+                    input,
+                } => {
+                    let from_lang = SourceLanguage::from_str(&from)?;
+                    let to_lang = TargetLanguage::from_str(&to)?;
+                    let format = format.map(|f| GlossaryFormat::from_str(&f)).transpose()?;
+                    let glossaries = read_glossary_file(
+                        &name,
+                        &input,
+                        format,
+                        sheet.as_deref(),
+                        from_lang,
+                        to_lang,
+                    )?;
 
-                    let glossary = deepl
-                        .unwrap()
-                        .register_glossaries(&name, from_lang, to_lang, &glossaries)
-                        .await
-                        .unwrap();
+                    let synced =
+                        sync_glossary(translator, &name, from_lang, to_lang, &glossaries).await?;
+                    for change in &synced.changes {
+                        println!("{}", change);
+                    }
+                    let Some(glossary) = synced.registered else {
+                        println!("Glossary {} is up to date", name);
+                        return Ok(());
+                    };
                     println!(
                         "Total {} entries are registered as ID = {}",
                         glossary.entry_count, glossary.glossary_id
                     );
+                    for old_id in &synced.removed_ids {
+                        println!("Removed old glossary ID = {}", old_id);
+                        if translator
+                            .config()
+                            .update_glossary_id(old_id, &glossary.glossary_id)?
+                        {
+                            println!("Updated glossary ID in config file");
+                        }
+                    }
                 }
                 GlossaryCommands::List => {
                     // List glossaries
-                    let glossaries = deepl.unwrap().list_glossaries().await.unwrap();
+                    let glossaries = translator.list_glossaries().await?;
                     for glossary in glossaries {
                         println!("{:?}\n", glossary);
                     }
                 }
                 GlossaryCommands::Delete { id } => {
-                    deepl.unwrap().remove_glossary(&id).await.unwrap();
+                    translator.remove_glossary(&id).await?;
                 }
             }
         }
-        Some(Commands::Usage) => {
-            let used_chars = deepl.unwrap().get_usage().await.unwrap();
+        Commands::Usage => {
+            let used_chars = translator.get_usage().await?;
             println!("{} characters used.", used_chars);
         }
-        _ => {
-            // Print help
-            Cli::command().print_help()?;
+        Commands::Languages => {
+            println!("Source languages:");
+            for lang in translator.source_languages().await? {
+                println!("  {:<8}{}", lang.language, lang.name);
+            }
+            println!("Target languages:");
+            for lang in translator.target_languages().await? {
+                let formality = if lang.supports_formality {
+                    " (formality)"
+                } else {
+                    ""
+                };
+                println!("  {:<8}{}{}", lang.language, lang.name, formality);
+            }
         }
     }
 
@@ -0,0 +1,283 @@
+// SPDX-License-Identifier: MIT
+//!
+//! Manifest of translated files, used to skip unchanged files
+//!
+
+use crate::deepl::{Formality, SourceLanguage, TargetLanguage};
+use crate::error::Result;
+use sha2::{Digest, Sha256};
+
+/// Default manifest file name, placed in output directory
+pub const MANIFEST_FILE_NAME: &str = ".cmark-translate.json";
+
+/// One translated file
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ManifestEntry {
+    pub source_path: String,
+    /// SHA-256 of source file
+    pub source_hash: String,
+    pub output_path: String,
+    pub from: String,
+    pub to: String,
+    /// Formality of translation, "default" for manifests without formality
+    #[serde(default = "default_formality")]
+    pub formality: String,
+    /// Translated time, UNIX epoch seconds
+    pub timestamp: u64,
+}
+
+/// Manifest file
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct Manifest {
+    #[serde(skip)]
+    path: std::path::PathBuf,
+    files: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Read manifest file, returns empty manifest if not exists
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        let mut manifest = match std::fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str::<Manifest>(&s)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::debug!("Manifest {:?} NOT found.", path.as_ref());
+                Manifest::default()
+            }
+            Err(e) => return Err(e.into()),
+        };
+        manifest.path = path.as_ref().to_path_buf();
+        Ok(manifest)
+    }
+
+    /// Write manifest file
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        Ok(std::fs::write(&self.path, json)?)
+    }
+
+    /// Check source file is not changed since last translation
+    ///
+    /// If `from_lang` is None (detected automatically), any source language matches.
+    pub fn is_up_to_date<P: AsRef<std::path::Path>>(
+        &self,
+        src_path: P,
+        dst_path: P,
+        from_lang: Option<SourceLanguage>,
+        to_lang: TargetLanguage,
+        formality: Formality,
+    ) -> Result<bool> {
+        let Some(entry) = self.find(&src_path, &dst_path, from_lang, to_lang, formality) else {
+            return Ok(false);
+        };
+        if !dst_path.as_ref().exists() {
+            // Output was removed
+            return Ok(false);
+        }
+        Ok(entry.source_hash == file_hash(&src_path)?)
+    }
+
+    /// Record translated file
+    ///
+    /// `source_hash` is the hash of source file read before translation,
+    /// so that the source edited during translation is translated again next time.
+    pub fn record<P: AsRef<std::path::Path>>(
+        &mut self,
+        src_path: P,
+        dst_path: P,
+        source_hash: &str,
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
+        formality: Formality,
+    ) -> Result<()> {
+        let entry = ManifestEntry {
+            source_path: path_string(&src_path),
+            source_hash: source_hash.to_string(),
+            output_path: path_string(&dst_path),
+            from: from_lang.as_langcode().to_string(),
+            to: to_lang.as_langcode().to_string(),
+            formality: formality.to_str().to_string(),
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        };
+
+        match self.files.iter_mut().find(|e| {
+            e.source_path == entry.source_path
+                && e.output_path == entry.output_path
+                && e.from == entry.from
+                && e.to == entry.to
+                && e.formality == entry.formality
+        }) {
+            Some(e) => *e = entry,
+            None => self.files.push(entry),
+        }
+        Ok(())
+    }
+
+    fn find<P: AsRef<std::path::Path>>(
+        &self,
+        src_path: P,
+        dst_path: P,
+        from_lang: Option<SourceLanguage>,
+        to_lang: TargetLanguage,
+        formality: Formality,
+    ) -> Option<&ManifestEntry> {
+        let src = path_string(&src_path);
+        let dst = path_string(&dst_path);
+        self.files.iter().find(|e| {
+            e.source_path == src
+                && e.output_path == dst
+                && from_lang.is_none_or(|from| e.from == from.as_langcode())
+                && e.to == to_lang.as_langcode()
+                && e.formality == formality.to_str()
+        })
+    }
+}
+
+fn default_formality() -> String {
+    Formality::Default.to_str().to_string()
+}
+
+/// SHA-256 of file, hex string
+pub fn file_hash<P: AsRef<std::path::Path>>(path: P) -> Result<String> {
+    let body = std::fs::read(path)?;
+    Ok(format!("{:x}", Sha256::digest(body)))
+}
+
+fn path_string<P: AsRef<std::path::Path>>(path: P) -> String {
+    path.as_ref().to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let tmp_dir =
+            std::env::temp_dir().join(format!("cmark-translate-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+        let src_path = tmp_dir.join("src.md");
+        let dst_path = tmp_dir.join("dst.md");
+        let manifest_path = tmp_dir.join(MANIFEST_FILE_NAME);
+        std::fs::write(&src_path, "Hello").unwrap();
+        std::fs::write(&dst_path, "こんにちは").unwrap();
+
+        let mut manifest = Manifest::open(&manifest_path).unwrap();
+        assert!(!manifest
+            .is_up_to_date(
+                &src_path,
+                &dst_path,
+                Some(SourceLanguage::En),
+                TargetLanguage::Ja,
+                Formality::Default
+            )
+            .unwrap());
+
+        // Source is edited during translation
+        let source_hash = file_hash(&src_path).unwrap();
+        std::fs::write(&src_path, "Hello World").unwrap();
+        manifest
+            .record(
+                &src_path,
+                &dst_path,
+                &source_hash,
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                Formality::Default,
+            )
+            .unwrap();
+        manifest.save().unwrap();
+        assert!(!manifest
+            .is_up_to_date(
+                &src_path,
+                &dst_path,
+                Some(SourceLanguage::En),
+                TargetLanguage::Ja,
+                Formality::Default
+            )
+            .unwrap());
+        std::fs::write(&src_path, "Hello").unwrap();
+
+        // Reopen saved manifest
+        let manifest = Manifest::open(&manifest_path).unwrap();
+        assert!(manifest
+            .is_up_to_date(
+                &src_path,
+                &dst_path,
+                Some(SourceLanguage::En),
+                TargetLanguage::Ja,
+                Formality::Default
+            )
+            .unwrap());
+        // Other language pair
+        assert!(!manifest
+            .is_up_to_date(
+                &src_path,
+                &dst_path,
+                Some(SourceLanguage::En),
+                TargetLanguage::De,
+                Formality::Default
+            )
+            .unwrap());
+        assert!(!manifest
+            .is_up_to_date(
+                &src_path,
+                &dst_path,
+                Some(SourceLanguage::Fr),
+                TargetLanguage::Ja,
+                Formality::Default
+            )
+            .unwrap());
+        // Detected source language
+        assert!(manifest
+            .is_up_to_date(
+                &src_path,
+                &dst_path,
+                None,
+                TargetLanguage::Ja,
+                Formality::Default
+            )
+            .unwrap());
+        // Other formality
+        assert!(!manifest
+            .is_up_to_date(
+                &src_path,
+                &dst_path,
+                Some(SourceLanguage::En),
+                TargetLanguage::Ja,
+                Formality::Formal
+            )
+            .unwrap());
+
+        // Source is changed
+        std::fs::write(&src_path, "Hello World").unwrap();
+        assert!(!manifest
+            .is_up_to_date(
+                &src_path,
+                &dst_path,
+                Some(SourceLanguage::En),
+                TargetLanguage::Ja,
+                Formality::Default
+            )
+            .unwrap());
+
+        // Output is removed
+        std::fs::write(&src_path, "Hello").unwrap();
+        std::fs::remove_file(&dst_path).unwrap();
+        assert!(!manifest
+            .is_up_to_date(
+                &src_path,
+                &dst_path,
+                Some(SourceLanguage::En),
+                TargetLanguage::Ja,
+                Formality::Default
+            )
+            .unwrap());
+
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+}
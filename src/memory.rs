@@ -0,0 +1,318 @@
+// SPDX-License-Identifier: MIT
+//!
+//! Translation memory, cache of translated segments
+//!
+//! Stored as JSON lines, one translated segment per line.
+//! Segments are looked up by source text, language pair, formality, glossary ID
+//! and words not to translate (`[ignores]`).
+//!
+
+use crate::deepl::{Formality, SourceLanguage, TargetLanguage};
+use crate::error::Result;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::sync::Mutex;
+
+/// Lookup key of translated segment
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+struct MemoryKey {
+    source: String,
+    from: String,
+    to: String,
+    formality: String,
+    glossary_id: Option<String>,
+    /// Words not to translate, sorted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    ignores: Vec<String>,
+}
+
+/// One translated segment, a line of JSON lines file
+#[derive(serde::Deserialize, serde::Serialize)]
+struct MemoryEntry {
+    #[serde(flatten)]
+    key: MemoryKey,
+    target: String,
+}
+
+/// Persistent translation memory
+#[derive(Debug)]
+pub struct TranslationMemory {
+    path: Option<std::path::PathBuf>,
+    entries: Mutex<HashMap<MemoryKey, String>>,
+    /// File opened on first insert, kept open to append segments
+    file: Mutex<Option<std::fs::File>>,
+}
+
+impl TranslationMemory {
+    /// In-memory translation memory, not persisted
+    pub fn new() -> Self {
+        Self {
+            path: None,
+            entries: Mutex::new(HashMap::new()),
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Open JSON lines file, created on first insert if not exists
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        let mut entries = HashMap::new();
+        match std::fs::File::open(&path) {
+            Ok(f) => {
+                for (n, line) in std::io::BufReader::new(f).lines().enumerate() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str::<MemoryEntry>(&line) {
+                        Ok(entry) => {
+                            // later line wins
+                            entries.insert(entry.key, entry.target);
+                        }
+                        Err(e) => {
+                            log::warn!("{:?}:{} is skipped : {}", path.as_ref(), n + 1, e);
+                        }
+                    }
+                }
+                log::debug!("Read {} segments from {:?}", entries.len(), path.as_ref());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::debug!("Translation memory {:?} NOT found.", path.as_ref());
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(Self {
+            path: Some(path.as_ref().to_path_buf()),
+            entries: Mutex::new(entries),
+            file: Mutex::new(None),
+        })
+    }
+
+    /// Find translated segment
+    pub fn lookup(
+        &self,
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
+        formality: Formality,
+        glossary_id: Option<&str>,
+        ignores: &[String],
+        source: &str,
+    ) -> Option<String> {
+        let key = MemoryKey::new(from_lang, to_lang, formality, glossary_id, ignores, source);
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&key)
+            .cloned()
+    }
+
+    /// Add translated segment and append it to the file
+    ///
+    /// Segment already stored with the same translation is not appended again.
+    #[allow(clippy::too_many_arguments)]
+    pub fn insert(
+        &self,
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
+        formality: Formality,
+        glossary_id: Option<&str>,
+        ignores: &[String],
+        source: &str,
+        target: &str,
+    ) -> Result<()> {
+        let entry = MemoryEntry {
+            key: MemoryKey::new(from_lang, to_lang, formality, glossary_id, ignores, source),
+            target: target.to_string(),
+        };
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.get(&entry.key) == Some(&entry.target) {
+            return Ok(());
+        }
+        if let Some(path) = &self.path {
+            let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+            let f = match file.as_mut() {
+                Some(f) => f,
+                None => file.insert(
+                    std::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(path)?,
+                ),
+            };
+            let line = serde_json::to_string(&entry)?;
+            writeln!(f, "{}", line)?;
+        }
+        entries.insert(entry.key, entry.target);
+        Ok(())
+    }
+
+    /// Number of stored segments
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for TranslationMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryKey {
+    fn new(
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
+        formality: Formality,
+        glossary_id: Option<&str>,
+        ignores: &[String],
+        source: &str,
+    ) -> Self {
+        let mut ignores = ignores.to_vec();
+        ignores.sort();
+        ignores.dedup();
+        Self {
+            source: source.to_string(),
+            from: from_lang.as_langcode().to_string(),
+            to: to_lang.as_langcode().to_string(),
+            formality: formality.to_str().to_string(),
+            glossary_id: glossary_id.map(|id| id.to_string()),
+            ignores,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_lookup() {
+        let memory = TranslationMemory::new();
+        memory
+            .insert(
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                Formality::Default,
+                None,
+                &[],
+                "<p>Hello</p>",
+                "<p>こんにちは</p>",
+            )
+            .unwrap();
+
+        assert_eq!(
+            memory.lookup(
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                Formality::Default,
+                None,
+                &[],
+                "<p>Hello</p>"
+            ),
+            Some(String::from("<p>こんにちは</p>"))
+        );
+        // Different formality, glossary or language pair are not matched
+        assert_eq!(
+            memory.lookup(
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                Formality::Formal,
+                None,
+                &[],
+                "<p>Hello</p>"
+            ),
+            None
+        );
+        assert_eq!(
+            memory.lookup(
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                Formality::Default,
+                Some("glossary"),
+                &[],
+                "<p>Hello</p>"
+            ),
+            None
+        );
+        assert_eq!(
+            memory.lookup(
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                Formality::Default,
+                None,
+                &[String::from("Hello")],
+                "<p>Hello</p>"
+            ),
+            None
+        );
+        assert_eq!(
+            memory.lookup(
+                SourceLanguage::En,
+                TargetLanguage::De,
+                Formality::Default,
+                None,
+                &[],
+                "<p>Hello</p>"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_memory_open() {
+        let path = std::env::temp_dir().join(format!(
+            "cmark-translate-memory-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let memory = TranslationMemory::open(&path).unwrap();
+        assert!(memory.is_empty());
+        memory
+            .insert(
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                Formality::Default,
+                Some("glossary"),
+                &[],
+                "Hello",
+                "こんにちは",
+            )
+            .unwrap();
+        // Same segment is not appended again
+        memory
+            .insert(
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                Formality::Default,
+                Some("glossary"),
+                &[],
+                "Hello",
+                "こんにちは",
+            )
+            .unwrap();
+        drop(memory);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+        // Reopen persisted memory
+        let memory = TranslationMemory::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(memory.len(), 1);
+        assert_eq!(
+            memory.lookup(
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                Formality::Default,
+                Some("glossary"),
+                &[],
+                "Hello"
+            ),
+            Some(String::from("こんにちは"))
+        );
+    }
+}
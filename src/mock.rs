@@ -0,0 +1,361 @@
+// SPDX-License-Identifier: MIT
+//!
+//! Offline pseudo translation backend
+//!
+//! Deterministic stand-in for DeepL used in tests and dry runs.
+//! No network access, no API key is required.
+//!
+
+use crate::deepl::{
+    DeeplConfig, DeeplGlossary, Formality, SourceLanguage, TargetLanguage, IGNORE_TAGS,
+    SPLITTING_TAGS,
+};
+use crate::error::{Error, Result};
+use crate::memory::TranslationMemory;
+use crate::translator::Translator;
+use minidom::{Element, Node};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Registered glossary and its entries
+type MockGlossary = (DeeplGlossary, Vec<(String, String)>);
+
+/// How the mock backend "translates" texts
+#[derive(Clone, Debug, Default)]
+pub enum MockStyle {
+    /// Convert to upper case, "Hello" -> "HELLO"
+    Upper,
+    /// Wrap each sentence unit in brackets, "Hello" -> "⟦Hello⟧"
+    #[default]
+    Brackets,
+    /// Prepend prefix to each sentence unit, "Hello" -> "ja: Hello"
+    Prefix(String),
+}
+
+impl std::str::FromStr for MockStyle {
+    type Err = Error;
+
+    /// "upper", "brackets", or any other text used as prefix
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "upper" => Ok(Self::Upper),
+            "brackets" => Ok(Self::Brackets),
+            "" => Err(Error::Parse(String::from("empty mock style"))),
+            _ => Ok(Self::Prefix(s.to_string())),
+        }
+    }
+}
+
+/// Pseudo translation backend
+#[derive(Debug)]
+pub struct MockTranslator {
+    pub config: DeeplConfig,
+    pub style: MockStyle,
+    pub memory: Option<TranslationMemory>,
+    used_chars: AtomicUsize,
+    /// Number of glossaries ever registered, used for unique IDs
+    glossary_count: AtomicUsize,
+    /// Registered glossaries and their entries
+    glossaries: Mutex<Vec<MockGlossary>>,
+}
+
+impl MockTranslator {
+    /// New mock backend with empty config
+    pub fn new(style: MockStyle) -> Self {
+        Self::with_config(DeeplConfig::default(), style)
+    }
+
+    /// New mock backend with specific config
+    pub fn with_config(config: DeeplConfig, style: MockStyle) -> Self {
+        Self {
+            config,
+            style,
+            memory: None,
+            used_chars: AtomicUsize::new(0),
+            glossary_count: AtomicUsize::new(0),
+            glossaries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Pseudo translate one sentence unit made of several text nodes
+    fn translate_unit(&self, texts: Vec<&mut String>) {
+        let mut texts = texts
+            .into_iter()
+            .filter(|t| !t.trim().is_empty())
+            .collect::<Vec<_>>();
+        for t in texts.iter_mut() {
+            self.used_chars
+                .fetch_add(t.chars().count(), Ordering::Relaxed);
+            if let MockStyle::Upper = self.style {
+                **t = t.to_uppercase();
+            }
+        }
+
+        let marker = match &self.style {
+            MockStyle::Upper => None,
+            MockStyle::Brackets => Some(("⟦".to_string(), "⟧")),
+            MockStyle::Prefix(prefix) => Some((format!("{} ", prefix), "")),
+        };
+        if let Some((head, tail)) = marker {
+            if let Some(first) = texts.first_mut() {
+                let pos = first.len() - first.trim_start().len();
+                first.insert_str(pos, &head);
+            }
+            if let Some(last) = texts.last_mut() {
+                let pos = last.trim_end().len();
+                last.insert_str(pos, tail);
+            }
+        }
+    }
+
+    /// Pseudo translate XML element, honoring ignore tags and splitting tags
+    fn translate_element(&self, elm: &mut Element) {
+        let mut texts = Vec::new();
+        let mut units = Vec::new();
+        collect_unit(elm, &mut texts, &mut units);
+        self.translate_unit(texts);
+        for unit in units {
+            self.translate_element(unit);
+        }
+    }
+}
+
+/// Collect text nodes of a sentence unit.
+/// Nested splitting tags are collected as separated units.
+fn collect_unit<'a>(
+    elm: &'a mut Element,
+    texts: &mut Vec<&'a mut String>,
+    units: &mut Vec<&'a mut Element>,
+) {
+    for node in elm.nodes_mut() {
+        match node {
+            Node::Text(t) => texts.push(t),
+            Node::Element(e) => {
+                if has_tag(IGNORE_TAGS, e.name()) {
+                    // keep as is
+                } else if has_tag(SPLITTING_TAGS, e.name()) {
+                    units.push(e);
+                } else {
+                    collect_unit(e, texts, units);
+                }
+            }
+        }
+    }
+}
+
+/// Check tag name is in comma separated tag list
+fn has_tag(tags: &str, name: &str) -> bool {
+    tags.split(',').any(|t| t == name)
+}
+
+#[async_trait::async_trait]
+impl Translator for MockTranslator {
+    fn config(&self) -> &DeeplConfig {
+        &self.config
+    }
+
+    fn memory(&self) -> Option<&TranslationMemory> {
+        self.memory.as_ref()
+    }
+
+    async fn translate_strings(
+        &self,
+        _from_lang: SourceLanguage,
+        _to_lang: TargetLanguage,
+        _formality: Formality,
+        body: &[&str],
+    ) -> Result<Vec<String>> {
+        Ok(body
+            .iter()
+            .map(|s| {
+                let mut s = s.to_string();
+                self.translate_unit(vec![&mut s]);
+                s
+            })
+            .collect())
+    }
+
+    /// Japanese if text contains kana or kanji, otherwise English
+    async fn detect_language(
+        &self,
+        _to_lang: TargetLanguage,
+        text: &str,
+    ) -> Result<Option<SourceLanguage>> {
+        let is_japanese = |c: char| matches!(c, '\u{3040}'..='\u{30ff}' | '\u{4e00}'..='\u{9fff}');
+        if text.trim().is_empty() {
+            Ok(None)
+        } else if text.chars().any(is_japanese) {
+            Ok(Some(SourceLanguage::Ja))
+        } else {
+            Ok(Some(SourceLanguage::En))
+        }
+    }
+
+    async fn translate_xml(
+        &self,
+        _from_lang: SourceLanguage,
+        _to_lang: TargetLanguage,
+        _formality: Formality,
+        xml_body: &str,
+    ) -> Result<String> {
+        let mut xml_root: Element = xml_body.parse()?;
+        self.translate_element(&mut xml_root);
+
+        let mut buf = Vec::<u8>::new();
+        xml_root.write_to(&mut buf)?;
+        String::from_utf8(buf).map_err(|e| Error::Xml(e.to_string()))
+    }
+
+    async fn get_usage(&self) -> Result<i32> {
+        Ok(self.used_chars.load(Ordering::Relaxed) as i32)
+    }
+
+    async fn register_glossaries(
+        &self,
+        name: &str,
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
+        glossaries: &[(&str, &str)],
+    ) -> Result<DeeplGlossary> {
+        let entries = glossaries
+            .iter()
+            .map(|(from, to)| (from.trim(), to.trim()))
+            .filter(|(from, to)| !from.is_empty() && !to.is_empty())
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect::<Vec<_>>();
+        let mut registered = self.glossaries.lock().unwrap_or_else(|e| e.into_inner());
+        self.glossary_count.fetch_add(1, Ordering::Relaxed);
+        let glossary = DeeplGlossary {
+            glossary_id: format!(
+                "mock-glossary-{}",
+                self.glossary_count.load(Ordering::Relaxed)
+            ),
+            name: name.to_string(),
+            ready: true,
+            source_lang: from_lang.as_langcode().to_string(),
+            target_lang: to_lang.glossary_langcode().to_string(),
+            creation_time: String::new(),
+            entry_count: entries.len() as i32,
+        };
+        registered.push((glossary.clone(), entries));
+        Ok(glossary)
+    }
+
+    async fn list_glossaries(&self) -> Result<Vec<DeeplGlossary>> {
+        Ok(self
+            .glossaries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(glossary, _)| glossary.clone())
+            .collect())
+    }
+
+    async fn glossary_entries(&self, id: &str) -> Result<Vec<(String, String)>> {
+        self.glossaries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|(glossary, _)| glossary.glossary_id == id)
+            .map(|(_, entries)| entries.clone())
+            .ok_or_else(|| Error::ApiStatus {
+                status: 404,
+                message: format!("glossary {} NOT found", id),
+            })
+    }
+
+    async fn remove_glossary(&self, id: &str) -> Result<()> {
+        let mut registered = self.glossaries.lock().unwrap_or_else(|e| e.into_inner());
+        let len = registered.len();
+        registered.retain(|(g, _)| g.glossary_id != id);
+        if registered.len() == len {
+            Err(Error::ApiStatus {
+                status: 404,
+                message: format!("glossary {} NOT found", id),
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_translate_strings() {
+        let mock = MockTranslator::new(MockStyle::Brackets);
+        let translated = mock
+            .translate_strings(
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                Formality::Default,
+                &["Hello", " World "],
+            )
+            .await
+            .unwrap();
+        assert_eq!(translated, vec!["⟦Hello⟧", " ⟦World⟧ "]);
+        assert_eq!(mock.get_usage().await.unwrap(), 12);
+    }
+
+    #[tokio::test]
+    async fn test_mock_translate_xml_splitting_tags() {
+        let mock = MockTranslator::new(MockStyle::Brackets);
+        let xml = "<body xmlns='markdown'><p>This is <strong>bold</strong></p><ul><li><p>item</p></li></ul></body>";
+        let translated = mock
+            .translate_xml(
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                Formality::Default,
+                xml,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            translated,
+            "<body xmlns='markdown'><p>⟦This is <strong>bold⟧</strong></p><ul><li><p>⟦item⟧</p></li></ul></body>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mock_translate_xml_ignore_tags() {
+        let mock = MockTranslator::new(MockStyle::Upper);
+        let xml = "<body xmlns='markdown'><p>Use <code literal=\"foo\"/> on <ignore-tag>Motoko</ignore-tag> canister</p><pre info=\"\">let a = 1;</pre></body>";
+        let translated = mock
+            .translate_xml(
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                Formality::Default,
+                xml,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            translated,
+            "<body xmlns='markdown'><p>USE <code literal=\"foo\"/> ON <ignore-tag>Motoko</ignore-tag> CANISTER</p><pre info=\"\">let a = 1;</pre></body>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mock_glossaries() {
+        // Used as trait object
+        let mock = MockTranslator::new(MockStyle::default());
+        let mock: &dyn Translator = &mock;
+        let glossary = mock
+            .register_glossaries(
+                "test",
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                &[("red", "赤"), ("", "")],
+            )
+            .await
+            .unwrap();
+        assert_eq!(glossary.entry_count, 1);
+        assert_eq!(mock.list_glossaries().await.unwrap().len(), 1);
+
+        mock.remove_glossary(&glossary.glossary_id).await.unwrap();
+        assert!(mock.list_glossaries().await.unwrap().is_empty());
+        assert!(mock.remove_glossary(&glossary.glossary_id).await.is_err());
+    }
+}
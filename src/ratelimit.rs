@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: MIT
+//!
+//! Rate limiter shared by all DeepL API requests
+//!
+//! Requests are spaced at fixed intervals, and all requests are paused
+//! while the server asks to wait with `Retry-After` (HTTP 429, 503).
+//!
+
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{Middleware, Next};
+use std::sync::Mutex;
+use std::time::Duration;
+use task_local_extensions::Extensions;
+use tokio::time::Instant;
+
+/// Requests per second limiter
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Minimum interval between requests, no limit if zero
+    interval: Duration,
+    /// Time when next request is allowed
+    next_slot: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// New rate limiter, no limit if `requests_per_second` is zero or negative
+    pub fn new(requests_per_second: f64) -> Self {
+        let interval = if 0.0 < requests_per_second {
+            Duration::from_secs_f64(1.0 / requests_per_second)
+        } else {
+            Duration::ZERO
+        };
+        Self {
+            interval,
+            next_slot: Mutex::new(None),
+        }
+    }
+
+    /// Wait until next request is allowed
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let slot = next_slot.map_or(now, |next| next.max(now));
+            *next_slot = Some(slot + self.interval);
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+
+    /// Pause all requests for given duration
+    pub fn pause(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut next_slot = self.next_slot.lock().unwrap_or_else(|e| e.into_inner());
+        if next_slot.is_none_or(|next| next < until) {
+            *next_slot = Some(until);
+        }
+    }
+}
+
+/// Middleware applying rate limiter to each request, including retries
+pub struct RateLimitMiddleware(pub std::sync::Arc<RateLimiter>);
+
+#[async_trait::async_trait]
+impl Middleware for RateLimitMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        self.0.acquire().await;
+        let resp = next.run(req, extensions).await?;
+
+        if resp.status() == StatusCode::TOO_MANY_REQUESTS
+            || resp.status() == StatusCode::SERVICE_UNAVAILABLE
+        {
+            if let Some(retry_after) = retry_after(&resp) {
+                log::warn!(
+                    "HTTP {}, wait {} seconds before next request",
+                    resp.status(),
+                    retry_after.as_secs()
+                );
+                self.0.pause(retry_after);
+            }
+        }
+        Ok(resp)
+    }
+}
+
+/// Parse `Retry-After` header in seconds
+fn retry_after(resp: &Response) -> Option<Duration> {
+    resp.headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new(20.0);
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        // 1st request is immediate, then 50ms intervals
+        assert!(Duration::from_millis(100) <= start.elapsed());
+
+        let start = Instant::now();
+        limiter.pause(Duration::from_millis(200));
+        limiter.acquire().await;
+        assert!(Duration::from_millis(200) <= start.elapsed());
+    }
+}
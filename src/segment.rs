@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: MIT
+//!
+//! Split XML body into block segments, and merge them back
+//!
+//! Large documents are translated with multiple API requests.
+//! Body is split at block level elements, so each request keeps tag balance.
+//!
+
+use minidom::{Element, Node};
+
+/// Elements which contain only block elements, split into children if too large
+const CONTAINER_TAGS: &str = "body,blockquote,ul,ol,li,table,th,tr,dl,di,footer";
+
+/// Split XML into block segments, each segment size is up to limit if possible
+///
+/// Containers (lists, tables, block quotes, ...) larger than limit are split into child blocks.
+/// Paragraphs, headings and table cells are never split even if larger than limit.
+pub(crate) fn split_blocks<'a, F: Fn(&Element) -> usize>(
+    root: &'a Element,
+    limit: usize,
+    size: &F,
+) -> Vec<&'a Element> {
+    let mut blocks = Vec::new();
+    for child in root.children() {
+        if is_splittable(child, limit, size) {
+            blocks.append(&mut split_blocks(child, limit, size));
+        } else {
+            if limit < size(child) {
+                log::warn!(
+                    "<{}> block exceeds request size limit {} bytes",
+                    child.name(),
+                    limit
+                );
+            }
+            blocks.push(child);
+        }
+    }
+    blocks
+}
+
+/// Rebuild XML from translated block segments
+///
+/// `root`, `limit` and `size` should be the same as `split_blocks`.
+/// Texts out of block segments are kept as is.
+pub(crate) fn merge_blocks<F: Fn(&Element) -> usize, I: Iterator<Item = Element>>(
+    root: &Element,
+    limit: usize,
+    size: &F,
+    translated: &mut I,
+) -> Element {
+    // Copy element without children
+    let mut merged = Element::builder(root.name(), root.ns()).build();
+    for (name, value) in root.attrs() {
+        merged.set_attr(name, value);
+    }
+
+    for node in root.nodes() {
+        match node {
+            Node::Element(child) if is_splittable(child, limit, size) => {
+                merged.append_child(merge_blocks(child, limit, size, translated));
+            }
+            Node::Element(child) => {
+                merged.append_child(translated.next().unwrap_or_else(|| child.clone()));
+            }
+            Node::Text(text) => {
+                merged.append_text_node(text.as_str());
+            }
+        }
+    }
+    merged
+}
+
+/// Group consecutive segments into chunks, total size of each chunk is up to limit
+///
+/// Returns number of segments in each chunk.
+pub(crate) fn chunk_sizes(sizes: &[usize], limit: usize) -> Vec<usize> {
+    let mut chunks = Vec::new();
+    let (mut count, mut total) = (0, 0);
+    for size in sizes {
+        if 0 < count && limit < total + size {
+            chunks.push(count);
+            (count, total) = (0, 0);
+        }
+        count += 1;
+        total += size;
+    }
+    if 0 < count {
+        chunks.push(count);
+    }
+    chunks
+}
+
+/// Length of application/x-www-form-urlencoded string
+pub(crate) fn form_encoded_len(s: &str) -> usize {
+    s.bytes()
+        .map(|b| match b {
+            b'0'..=b'9' | b'a'..=b'z' | b'A'..=b'Z' | b'-' | b'_' | b'.' | b'*' | b' ' => 1,
+            _ => 3,
+        })
+        .sum()
+}
+
+fn is_splittable<F: Fn(&Element) -> usize>(elm: &Element, limit: usize, size: &F) -> bool {
+    CONTAINER_TAGS.split(',').any(|t| t == elm.name())
+        && elm.children().next().is_some()
+        && limit < size(elm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xml_len(elm: &Element) -> usize {
+        let mut buf = Vec::<u8>::new();
+        elm.write_to(&mut buf).unwrap();
+        buf.len()
+    }
+
+    const XML: &str = "<body xmlns='markdown'><h1>Title</h1><ul><li><p>one</p></li><li><p>two</p></li></ul><p>end</p></body>";
+
+    #[test]
+    fn test_split_blocks() {
+        let root: Element = XML.parse().unwrap();
+
+        // Small enough, top level blocks
+        let blocks = split_blocks(&root, 1000, &xml_len);
+        assert_eq!(
+            blocks.iter().map(|e| e.name()).collect::<Vec<_>>(),
+            vec!["h1", "ul", "p"]
+        );
+
+        // List is split into items
+        let blocks = split_blocks(&root, 40, &xml_len);
+        assert_eq!(
+            blocks.iter().map(|e| e.name()).collect::<Vec<_>>(),
+            vec!["h1", "li", "li", "p"]
+        );
+
+        // Items are split into paragraphs
+        let blocks = split_blocks(&root, 10, &xml_len);
+        assert_eq!(
+            blocks.iter().map(|e| e.name()).collect::<Vec<_>>(),
+            vec!["h1", "p", "p", "p"]
+        );
+    }
+
+    #[test]
+    fn test_merge_blocks() {
+        let root: Element = XML.parse().unwrap();
+        for limit in [10, 40, 1000] {
+            let blocks = split_blocks(&root, limit, &xml_len);
+            let mut translated = blocks.into_iter().cloned().map(|mut e| {
+                if let Some(Node::Text(t)) = e.nodes_mut().next() {
+                    *t = t.to_uppercase();
+                }
+                e
+            });
+            let merged = merge_blocks(&root, limit, &xml_len, &mut translated);
+
+            let mut buf = Vec::<u8>::new();
+            merged.write_to(&mut buf).unwrap();
+            let expected = if limit == 10 {
+                "<body xmlns='markdown'><h1>TITLE</h1><ul><li><p>ONE</p></li><li><p>TWO</p></li></ul><p>END</p></body>"
+            } else {
+                // <ul> or <li> is a block, its first node is not a text
+                "<body xmlns='markdown'><h1>TITLE</h1><ul><li><p>one</p></li><li><p>two</p></li></ul><p>END</p></body>"
+            };
+            assert_eq!(String::from_utf8(buf).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_chunk_sizes() {
+        assert_eq!(chunk_sizes(&[], 10), Vec::<usize>::new());
+        assert_eq!(chunk_sizes(&[3, 3, 3, 3], 10), vec![3, 1]);
+        assert_eq!(chunk_sizes(&[3, 20, 3], 10), vec![1, 1, 1]);
+        assert_eq!(chunk_sizes(&[5, 5, 5], 10), vec![2, 1]);
+    }
+
+    #[test]
+    fn test_form_encoded_len() {
+        assert_eq!(form_encoded_len("Hello World"), 11);
+        assert_eq!(form_encoded_len("<p>"), 7);
+        assert_eq!(form_encoded_len("あ"), 9);
+    }
+}
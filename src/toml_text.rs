@@ -0,0 +1,490 @@
+// SPDX-License-Identifier: MIT
+//!
+//! Minimal TOML editor for frontmatter
+//!
+//! String values are located by their paths and replaced in place,
+//! so that key order, comments and quoting style of the other parts are kept as is.
+//! Tables, arrays of tables, dotted keys, arrays and inline tables are understood.
+//! Text is expected to be valid TOML, checked by `toml` crate beforehand.
+//!
+
+use crate::frontmatter::PathSegment;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Quoting style of string
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StringStyle {
+    /// `"..."`
+    Basic,
+    /// `'...'`
+    Literal,
+    /// `"""..."""`
+    MultiLineBasic,
+    /// `'''...'''`
+    MultiLineLiteral,
+}
+
+/// String value found in TOML text
+#[derive(Clone, Debug)]
+pub struct TomlString {
+    pub path: Vec<PathSegment>,
+    pub value: String,
+    pub style: StringStyle,
+    /// Byte range of the value in TOML text, including quotes
+    span: Range<usize>,
+}
+
+/// Find string values in TOML text
+pub fn strings(toml: &str) -> Vec<TomlString> {
+    let mut scanner = Scanner {
+        text: toml,
+        pos: 0,
+        found: Vec::new(),
+    };
+    let mut table = Vec::new();
+    // Number of elements of each array of tables, keyed by its path
+    let mut arrays = HashMap::<Vec<PathSegment>, usize>::new();
+
+    loop {
+        scanner.skip_blank(true);
+        let Some(c) = scanner.peek() else {
+            break;
+        };
+        if c == '[' {
+            let is_array = scanner.rest().starts_with("[[");
+            scanner.pos += if is_array { 2 } else { 1 };
+            let Some(keys) = scanner.keys() else {
+                break;
+            };
+            // Keys through array of tables refer to its last element
+            let mut path = Vec::new();
+            for key in keys {
+                path.push(PathSegment::Key(key));
+                if let Some(len) = arrays.get(&path) {
+                    path.push(PathSegment::Index(len - 1));
+                }
+            }
+            if is_array {
+                if let Some(PathSegment::Index(_)) = path.last() {
+                    path.pop();
+                }
+                let len = arrays.entry(path.clone()).or_default();
+                *len += 1;
+                path.push(PathSegment::Index(*len - 1));
+            }
+            table = path;
+            let close = if is_array { "]]" } else { "]" };
+            scanner.skip_blank(false);
+            if !scanner.rest().starts_with(close) {
+                break;
+            }
+            scanner.pos += close.len();
+        } else if !scanner.key_value(&table) {
+            break;
+        }
+    }
+    scanner.found
+}
+
+/// Replace string values, each value is written in its original quoting style if possible
+pub fn replace_strings(toml: &str, replacements: &[(&TomlString, String)]) -> String {
+    let mut sorted = replacements.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|(string, _)| std::cmp::Reverse(string.span.start));
+
+    let mut replaced = toml.to_string();
+    for (string, value) in sorted {
+        replaced.replace_range(string.span.clone(), &encode(string.style, value));
+    }
+    replaced
+}
+
+struct Scanner<'t> {
+    text: &'t str,
+    pos: usize,
+    found: Vec<TomlString>,
+}
+
+impl<'t> Scanner<'t> {
+    fn rest(&self) -> &'t str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    /// Skip whitespaces and comments, and also newlines if `newline` is true
+    fn skip_blank(&mut self, newline: bool) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t') => self.pos += 1,
+                Some('\r' | '\n') if newline => self.pos += 1,
+                Some('#') => {
+                    self.pos += self.rest().find('\n').unwrap_or(self.rest().len());
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Parse dotted key, `a."b".'c'`
+    fn keys(&mut self) -> Option<Vec<String>> {
+        let mut keys = Vec::new();
+        loop {
+            self.skip_blank(false);
+            let rest = self.rest();
+            let (key, len) = match rest.chars().next()? {
+                '"' => basic_string(rest)?,
+                '\'' => literal_string(rest)?,
+                _ => {
+                    let len = rest
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                        .unwrap_or(rest.len());
+                    if len == 0 {
+                        return None;
+                    }
+                    (rest[..len].to_string(), len)
+                }
+            };
+            keys.push(key);
+            self.pos += len;
+            self.skip_blank(false);
+            if self.peek() != Some('.') {
+                return Some(keys);
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Parse `key = value` in table, returns false if unknown syntax is found
+    fn key_value(&mut self, table: &[PathSegment]) -> bool {
+        let Some(keys) = self.keys() else {
+            return false;
+        };
+        if self.peek() != Some('=') {
+            return false;
+        }
+        self.pos += 1;
+        self.skip_blank(false);
+        let path = [
+            table,
+            &keys.into_iter().map(PathSegment::Key).collect::<Vec<_>>(),
+        ]
+        .concat();
+        self.value(path)
+    }
+
+    /// Parse value, returns false if unknown syntax is found
+    fn value(&mut self, path: Vec<PathSegment>) -> bool {
+        let rest = self.rest();
+        let parsed = match rest.chars().next() {
+            Some('"') if rest.starts_with("\"\"\"") => {
+                multi_line_string(rest, '"').map(|(v, len)| (v, len, StringStyle::MultiLineBasic))
+            }
+            Some('\'') if rest.starts_with("'''") => multi_line_string(rest, '\'')
+                .map(|(v, len)| (v, len, StringStyle::MultiLineLiteral)),
+            Some('"') => basic_string(rest).map(|(v, len)| (v, len, StringStyle::Basic)),
+            Some('\'') => literal_string(rest).map(|(v, len)| (v, len, StringStyle::Literal)),
+            Some('[') => return self.array(path),
+            Some('{') => return self.inline_table(path),
+            Some(_) => {
+                // Number, boolean or date
+                let len = rest
+                    .find([',', ']', '}', '#', '\r', '\n'])
+                    .unwrap_or(rest.len());
+                self.pos += len;
+                return len > 0;
+            }
+            None => return false,
+        };
+        let Some((value, len, style)) = parsed else {
+            return false;
+        };
+        self.found.push(TomlString {
+            path,
+            value,
+            style,
+            span: self.pos..self.pos + len,
+        });
+        self.pos += len;
+        true
+    }
+
+    /// Parse array `[a, b, ...]`, may span multiple lines
+    fn array(&mut self, path: Vec<PathSegment>) -> bool {
+        self.pos += 1;
+        let mut index = 0;
+        loop {
+            self.skip_blank(true);
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return true;
+            }
+            let item_path = [path.as_slice(), &[PathSegment::Index(index)]].concat();
+            if !self.value(item_path) {
+                return false;
+            }
+            index += 1;
+            self.skip_blank(true);
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                _ => return false,
+            }
+        }
+    }
+
+    /// Parse inline table `{ a = 1, b = 2 }`
+    fn inline_table(&mut self, path: Vec<PathSegment>) -> bool {
+        self.pos += 1;
+        loop {
+            self.skip_blank(false);
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                return true;
+            }
+            if !self.key_value(&path) {
+                return false;
+            }
+            self.skip_blank(false);
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {}
+                _ => return false,
+            }
+        }
+    }
+}
+
+/// Parse basic string `"..."`, returns value and length including quotes
+fn basic_string(text: &str) -> Option<(String, usize)> {
+    let mut chars = text.char_indices().skip(1);
+    let mut value = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, i + 1)),
+            '\\' => value.push(unescape(&mut chars)?),
+            '\n' => return None,
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Parse literal string `'...'`, returns value and length including quotes
+fn literal_string(text: &str) -> Option<(String, usize)> {
+    let len = text[1..].find(['\'', '\n'])?;
+    text[1 + len..]
+        .starts_with('\'')
+        .then(|| (text[1..1 + len].to_string(), len + 2))
+}
+
+/// Parse multi-line string `"""..."""` or `'''...'''`
+fn multi_line_string(text: &str, quote: char) -> Option<(String, usize)> {
+    let delimiter = quote.to_string().repeat(3);
+    let body_start = 3;
+    let mut chars = text.char_indices().skip(body_start).peekable();
+    let mut value = String::new();
+    // Newline immediately following the opening delimiter is trimmed
+    if text[body_start..].starts_with('\n') {
+        chars.next();
+    } else if text[body_start..].starts_with("\r\n") {
+        chars.next();
+        chars.next();
+    }
+    while let Some((i, c)) = chars.next() {
+        if c == quote && text[i..].starts_with(&delimiter) {
+            // Up to 2 quotes are allowed just before the closing delimiter
+            let extra = text[i + 3..]
+                .chars()
+                .take_while(|&d| d == quote)
+                .count()
+                .min(2);
+            (0..extra).for_each(|_| value.push(quote));
+            return Some((value, i + 3 + extra));
+        }
+        if quote == '"' && c == '\\' {
+            let rest = &text[i + 1..];
+            let trimmed = rest.trim_start_matches([' ', '\t']);
+            if trimmed.starts_with(['\n', '\r']) {
+                // Line ending backslash, trim whitespaces and newlines
+                while chars.peek().is_some_and(|(_, d)| d.is_whitespace()) {
+                    chars.next();
+                }
+                continue;
+            }
+            value.push(unescape(&mut chars)?);
+            continue;
+        }
+        value.push(c);
+    }
+    None
+}
+
+/// Unescape the character following backslash
+fn unescape<I: Iterator<Item = (usize, char)>>(chars: &mut I) -> Option<char> {
+    let (_, escaped) = chars.next()?;
+    let c = match escaped {
+        'b' => '\u{8}',
+        't' => '\t',
+        'n' => '\n',
+        'f' => '\u{c}',
+        'r' => '\r',
+        'u' | 'U' => {
+            let len = if escaped == 'u' { 4 } else { 8 };
+            let hex = (0..len)
+                .map(|_| chars.next().map(|(_, c)| c))
+                .collect::<Option<String>>()?;
+            char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+        }
+        c => c,
+    };
+    Some(c)
+}
+
+/// Encode value in quoting style, switching to basic string if the style can not hold it
+fn encode(style: StringStyle, value: &str) -> String {
+    match style {
+        StringStyle::Literal if !value.contains(['\'', '\n', '\r']) => format!("'{}'", value),
+        StringStyle::MultiLineLiteral if !value.contains("'''") && !value.ends_with('\'') => {
+            format!("'''{}'''", value)
+        }
+        StringStyle::MultiLineBasic | StringStyle::MultiLineLiteral => {
+            format!("\"\"\"{}\"\"\"", escape(value, false))
+        }
+        _ => format!("\"{}\"", escape(value, true)),
+    }
+}
+
+fn escape(value: &str, escape_newline: bool) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' if escape_newline => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() && c != '\n' => {
+                escaped.push_str(&format!("\\u{:04X}", c as u32));
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_path(keys: &[&str]) -> Vec<PathSegment> {
+        keys.iter()
+            .map(|k| match k.parse::<usize>() {
+                Ok(i) => PathSegment::Index(i),
+                Err(_) => PathSegment::Key(k.to_string()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_strings() {
+        let toml = r#"
+# comment
+title = "Hello \"World\"" # trailing comment
+slug = 'a\b'
+weight = 10
+keywords = [
+  "docs", # comment
+  'guide',
+]
+extra.summary = """
+Multi
+line"""
+seo = { og = { text = "Open Graph" }, url = "/" }
+
+[taxonomies]
+"tags" = ["a"]
+
+[[authors]]
+name = "Alice"
+
+[[authors]]
+name = "Bob"
+"#;
+        let found = strings(toml)
+            .into_iter()
+            .map(|s| (s.path, s.value, s.style))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    key_path(&["title"]),
+                    String::from("Hello \"World\""),
+                    StringStyle::Basic
+                ),
+                (
+                    key_path(&["slug"]),
+                    String::from("a\\b"),
+                    StringStyle::Literal
+                ),
+                (
+                    key_path(&["keywords", "0"]),
+                    String::from("docs"),
+                    StringStyle::Basic
+                ),
+                (
+                    key_path(&["keywords", "1"]),
+                    String::from("guide"),
+                    StringStyle::Literal
+                ),
+                (
+                    key_path(&["extra", "summary"]),
+                    String::from("Multi\nline"),
+                    StringStyle::MultiLineBasic
+                ),
+                (
+                    key_path(&["seo", "og", "text"]),
+                    String::from("Open Graph"),
+                    StringStyle::Basic
+                ),
+                (
+                    key_path(&["seo", "url"]),
+                    String::from("/"),
+                    StringStyle::Basic
+                ),
+                (
+                    key_path(&["taxonomies", "tags", "0"]),
+                    String::from("a"),
+                    StringStyle::Basic
+                ),
+                (
+                    key_path(&["authors", "0", "name"]),
+                    String::from("Alice"),
+                    StringStyle::Basic
+                ),
+                (
+                    key_path(&["authors", "1", "name"]),
+                    String::from("Bob"),
+                    StringStyle::Basic
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replace_strings() {
+        let toml = "title = 'Hello' # comment\nslug = 'a'\nbody = '''\nline'''\n";
+        let found = strings(toml);
+        let replacements = vec![
+            (&found[0], String::from("It's")),
+            (&found[2], String::from("行 1\n行 2")),
+        ];
+        assert_eq!(
+            replace_strings(toml, &replacements),
+            "title = \"It's\" # comment\nslug = 'a'\nbody = '''行 1\n行 2'''\n"
+        );
+    }
+}
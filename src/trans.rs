@@ -1,16 +1,73 @@
 // SPDX-License-Identifier: MIT
-use crate::{cmark_xml, deepl};
+use crate::error::{Error, Result};
+use crate::frontmatter::{FrontmatterKeys, PathSegment};
+use crate::{cmark_xml, deepl, segment, toml_text, translator::Translator, yaml};
 
 /// Translate CommonMark .md file
-pub async fn translate_cmark_file<P: AsRef<std::path::Path>>(
-    deepl: &deepl::Deepl,
-    from_lang: deepl::Language,
-    to_lang: deepl::Language,
+pub async fn translate_cmark_file<T: Translator, P: AsRef<std::path::Path>>(
+    translator: &T,
+    from_lang: deepl::SourceLanguage,
+    to_lang: deepl::TargetLanguage,
     formality: deepl::Formality,
     src_path: P,
     dst_path: P,
-) -> std::io::Result<()> {
-    use std::io::Write;
+) -> Result<()> {
+    translate_cmark_file_multi(
+        translator,
+        from_lang,
+        formality,
+        src_path,
+        &[(to_lang, dst_path)],
+    )
+    .await?
+    .pop()
+    .unwrap_or(Ok(()))
+}
+
+/// Translate CommonMark .md file into multiple target languages
+///
+/// Source file is read and parsed once, then translated into each target language concurrently.
+/// Returns result of each target in the same order as `targets`.
+pub async fn translate_cmark_file_multi<
+    T: Translator,
+    P: AsRef<std::path::Path>,
+    Q: AsRef<std::path::Path>,
+>(
+    translator: &T,
+    from_lang: deepl::SourceLanguage,
+    formality: deepl::Formality,
+    src_path: P,
+    targets: &[(deepl::TargetLanguage, Q)],
+) -> Result<Vec<Result<()>>> {
+    translate_cmark_file_with_links(
+        translator,
+        from_lang,
+        formality,
+        src_path,
+        targets,
+        &|_, _| None,
+    )
+    .await
+}
+
+/// Rewrites link destination for target language, returns None to keep it as is
+pub type LinkRewriter<'a> = dyn Fn(deepl::TargetLanguage, &str) -> Option<String> + Sync + 'a;
+
+/// Translate CommonMark .md file into multiple target languages, rewriting link destinations
+///
+/// Same as `translate_cmark_file_multi`, links are rewritten for each target language before translation.
+pub async fn translate_cmark_file_with_links<
+    T: Translator,
+    P: AsRef<std::path::Path>,
+    Q: AsRef<std::path::Path>,
+>(
+    translator: &T,
+    from_lang: deepl::SourceLanguage,
+    formality: deepl::Formality,
+    src_path: P,
+    targets: &[(deepl::TargetLanguage, Q)],
+    rewrite_link: &LinkRewriter<'_>,
+) -> Result<Vec<Result<()>>> {
     log::debug!("start translate. input: {}", &src_path.as_ref().display());
 
     // Read .md file
@@ -25,28 +82,111 @@ pub async fn translate_cmark_file<P: AsRef<std::path::Path>>(
         cmark_text
     );
 
-    let is_md_file = src_path.as_ref().extension().is_some()
-        && (src_path.as_ref().extension().unwrap() == "md"
-            || src_path.as_ref().extension().unwrap() == "mdx");
+    // Frontmatter keys are configured for each file type
+    let extension = src_path
+        .as_ref()
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let frontmatter_keys = translator.config().frontmatter_keys(&extension)?;
 
     // If Deepl API KEY is a free version, get the number of characters remaining to be translated.
-    if deepl.config.is_free_api_key() {
-        api_availability_check(&deepl, &cmark_text).await?;
+    if translator.config().is_free_api_key() {
+        api_availability_check(translator, &cmark_text).await?;
     }
 
-    // Parse frontmatter. For Markdown files, do not translate front matter.
-    let translated_frontmatter = match frontmatter {
-        Some(frontmatter) if !is_md_file => {
-            // translate TOML frontmatter
-            Some(translate_toml(&deepl, from_lang, to_lang, formality, &frontmatter).await?)
-        }
-        Some(frontmatter) => Some(frontmatter),
-        _ => None,
-    };
+    // Parse once, shared by all target languages
+    let xml_root = cmark_xml::xmldom_from_cmark_with_extensions(
+        &cmark_text,
+        true,
+        &translator.config().cmark_extensions()?,
+    );
+    let (cmark_text, delimiter, frontmatter, xml_root, frontmatter_keys) = (
+        &cmark_text,
+        &delimiter,
+        &frontmatter,
+        &xml_root,
+        &frontmatter_keys,
+    );
+
+    let results = futures::future::join_all(targets.iter().map(|(to_lang, dst_path)| async move {
+        // Parse frontmatter, and translate configured keys
+        let translated_frontmatter = match frontmatter {
+            Some(frontmatter) if frontmatter_keys.is_empty() => Some(frontmatter.clone()),
+            Some(frontmatter) if delimiter == "---" => {
+                // translate YAML frontmatter
+                Some(
+                    translate_yaml(
+                        translator,
+                        from_lang,
+                        *to_lang,
+                        formality,
+                        frontmatter,
+                        frontmatter_keys,
+                    )
+                    .await?,
+                )
+            }
+            Some(frontmatter) if delimiter == ";;;" || delimiter.is_empty() => {
+                // translate JSON frontmatter
+                Some(
+                    translate_json(
+                        translator,
+                        from_lang,
+                        *to_lang,
+                        formality,
+                        frontmatter,
+                        frontmatter_keys,
+                    )
+                    .await?,
+                )
+            }
+            Some(frontmatter) => {
+                // translate TOML frontmatter
+                Some(
+                    translate_toml(
+                        translator,
+                        from_lang,
+                        *to_lang,
+                        formality,
+                        frontmatter,
+                        frontmatter_keys,
+                    )
+                    .await?,
+                )
+            }
+            _ => None,
+        };
+
+        // Translate CommonMark body
+        let mut xml_root = xml_root.clone();
+        cmark_xml::rewrite_links(&mut xml_root, &|href: &str| rewrite_link(*to_lang, href));
+        let translated_cmark =
+            translate_xmldom(translator, from_lang, *to_lang, formality, &xml_root).await?;
 
-    // Translate CommonMark body
-    let translated_cmark =
-        translate_cmark(&deepl, from_lang, to_lang, formality, &cmark_text).await?;
+        write_translated_file(
+            translator,
+            dst_path,
+            delimiter,
+            translated_frontmatter,
+            &translated_cmark,
+            cmark_text,
+        )
+    }))
+    .await;
+    Ok(results)
+}
+
+/// Write translated frontmatter and body, followed by original text if configured
+fn write_translated_file<T: Translator, P: AsRef<std::path::Path>>(
+    translator: &T,
+    dst_path: P,
+    delimiter: &str,
+    translated_frontmatter: Option<String>,
+    translated_cmark: &str,
+    cmark_text: &str,
+) -> Result<()> {
+    use std::io::Write;
 
     // create output directory
     if let Some(parent) = dst_path.as_ref().parent() {
@@ -54,20 +194,34 @@ pub async fn translate_cmark_file<P: AsRef<std::path::Path>>(
     }
 
     // Print result
-    // let mut f = std::fs::File::create(&dst_path)?;
     let mut write_string = String::new();
-    if let Some(translated_frontmatter) = translated_frontmatter {
-        write_string.push_str(format!("{}{}", delimiter, "\n").as_str());
-        write_string.push_str(translated_frontmatter.as_str());
-        write_string.push_str(format!("{}{}", delimiter, "\n").as_str());
+    match translated_frontmatter {
+        // JSON frontmatter without delimiter
+        Some(translated_frontmatter) if delimiter.is_empty() => {
+            write_string.push_str(translated_frontmatter.as_str());
+            write_string.push('\n');
+        }
+        Some(translated_frontmatter) => {
+            // Frontmatter kept in place has newlines after and before delimiters
+            write_string.push_str(delimiter);
+            if !translated_frontmatter.starts_with('\n') {
+                write_string.push('\n');
+            }
+            write_string.push_str(translated_frontmatter.as_str());
+            if !translated_frontmatter.ends_with('\n') {
+                write_string.push('\n');
+            }
+            write_string.push_str(format!("{}{}", delimiter, "\n").as_str());
+        }
+        None => {}
     }
-    write_string.push_str(translated_cmark.as_str());
+    write_string.push_str(translated_cmark);
 
     // deepl.config.backup_original_text が true の場合は原文をコメントアウトで残す。
     // 原文に"-->"が含まれていると原文全体のコメントが失敗するため"-!->"に置換する。
-    if deepl.config.backup_original_text {
+    if translator.config().backup_original_text {
         write_string.push_str("\n<!---\n");
-        write_string.push_str(&cmark_text.as_str().replace("-->", "-!->"));
+        write_string.push_str(&cmark_text.replace("-->", "-!->"));
         write_string.push_str("\n-->\n");
     }
     let mut f = std::fs::File::create(&dst_path)?;
@@ -75,117 +229,554 @@ pub async fn translate_cmark_file<P: AsRef<std::path::Path>>(
     Ok(())
 }
 
+/// Characters of body text used to detect source language
+const DETECT_SAMPLE_CHARS: usize = 500;
+
+/// Detect source language of CommonMark file from the beginning of body text
+///
+/// Code blocks and inline codes are excluded from the sample.
+/// Returns None if the file has no text or its language is not supported.
+/// Detection sends up to `DETECT_SAMPLE_CHARS` characters to the translator,
+/// which are counted in the DeepL character usage.
+pub async fn detect_cmark_file_language<T: Translator, P: AsRef<std::path::Path>>(
+    translator: &T,
+    to_lang: deepl::TargetLanguage,
+    src_path: P,
+) -> Result<Option<deepl::SourceLanguage>> {
+    let mut f = std::fs::File::open(&src_path)?;
+    let (cmark_text, _, _) = cmark_xml::read_cmark_with_frontmatter(&mut f)?;
+    drop(f);
+
+    let xml_root = cmark_xml::xmldom_from_cmark_with_extensions(
+        &cmark_text,
+        true,
+        &translator.config().cmark_extensions()?,
+    );
+    let mut sample = String::new();
+    collect_text(&xml_root, &mut sample);
+    let sample = sample
+        .trim()
+        .chars()
+        .take(DETECT_SAMPLE_CHARS)
+        .collect::<String>();
+    if sample.is_empty() {
+        return Ok(None);
+    }
+    translator.detect_language(to_lang, &sample).await
+}
+
+// Collect text nodes out of ignore tags, up to DETECT_SAMPLE_CHARS
+fn collect_text(elm: &minidom::Element, text: &mut String) {
+    for node in elm.nodes() {
+        if DETECT_SAMPLE_CHARS <= text.chars().count() {
+            return;
+        }
+        match node {
+            minidom::Node::Text(t) => {
+                text.push_str(t);
+                text.push(' ');
+            }
+            minidom::Node::Element(e) => {
+                if !deepl::IGNORE_TAGS.split(',').any(|tag| tag == e.name()) {
+                    collect_text(e, text);
+                }
+            }
+        }
+    }
+}
+
 /// Translate TOML frontmatter
-pub async fn translate_toml(
-    deepl: &deepl::Deepl,
-    from_lang: deepl::Language,
-    to_lang: deepl::Language,
+///
+/// Values of `frontmatter_keys` are translated.
+/// Only the values are replaced in place, so that key order, comments and quoting style are kept.
+pub async fn translate_toml<T: Translator>(
+    translator: &T,
+    from_lang: deepl::SourceLanguage,
+    to_lang: deepl::TargetLanguage,
     formality: deepl::Formality,
     toml_frontmatter: &str,
-) -> Result<String, std::io::Error> {
-    if let toml::Value::Table(mut root) = toml_frontmatter.parse::<toml::Value>()? {
-        // Pickup TOML key for translation
-        let mut should_be_translate: Vec<&mut String> = vec![];
-        for (key, val) in &mut root {
-            match key.as_str() {
-                "title" | "description" => {
-                    if let toml::Value::String(val) = val {
-                        should_be_translate.push(val);
-                    }
-                }
-                "extra" => {
-                    if let toml::Value::Table(extra) = val {
-                        for (extra_key, extra_val) in extra {
-                            match extra_key.as_str() {
-                                "time" => {
-                                    if let toml::Value::String(extra_val) = extra_val {
-                                        should_be_translate.push(extra_val);
-                                    }
-                                }
-                                _ => (),
-                            }
-                        }
-                    }
-                }
-                _ => {}
-            }
+    frontmatter_keys: &FrontmatterKeys,
+) -> Result<String> {
+    let mut root = toml_frontmatter.parse::<toml::Value>()?;
+    if !root.is_table() {
+        // TOML parse failed
+        return Err(Error::Parse(String::from(
+            "TOML frontmatter is not a table",
+        )));
+    }
+
+    // Pickup TOML values for translation
+    let mut values: Vec<&mut String> = vec![];
+    collect_toml_strings(&mut root, &mut Vec::new(), frontmatter_keys, &mut values);
+    let num_values = values.len();
+    if num_values == 0 {
+        return Ok(toml_frontmatter.to_string());
+    }
+    let should_be_translate = toml_text::strings(toml_frontmatter)
+        .into_iter()
+        .filter(|s| !s.value.trim().is_empty() && frontmatter_keys.matches(&s.path))
+        .collect::<Vec<_>>();
+    if should_be_translate.len() != num_values {
+        // Unsupported syntax, fallback to reformat whole TOML
+        log::warn!("TOML frontmatter can not be edited in place, reformatted.");
+        return translate_toml_values(translator, from_lang, to_lang, formality, values)
+            .await
+            .and_then(|_| toml_string(&root));
+    }
+
+    // Prepare input Vec
+    let src_vec = should_be_translate
+        .iter()
+        .map(|s| s.value.as_str())
+        .collect::<Vec<&str>>();
+
+    // If Deepl API KEY is a free version, get the number of characters remaining to be translated.
+    if translator.config().is_free_api_key() {
+        api_availability_check(translator, &src_vec.join("")).await?;
+    }
+
+    // Translate texts
+    let translated_vec = translator
+        .translate_strings(from_lang, to_lang, formality, &src_vec)
+        .await?;
+
+    // Replace TOML value with translated text
+    let replacements = should_be_translate
+        .iter()
+        .zip(translated_vec)
+        .collect::<Vec<_>>();
+    let translated_frontmatter = toml_text::replace_strings(toml_frontmatter, &replacements);
+    log::trace!("Translated TOML :\n{}\n", translated_frontmatter);
+
+    // Edited text should have the same values as edited toml::Value
+    for (string, translated_str) in &replacements {
+        if let Some(toml::Value::String(val)) = toml_value_mut(&mut root, &string.path) {
+            val.clone_from(translated_str);
         }
+    }
+    if translated_frontmatter.parse::<toml::Value>().ok().as_ref() == Some(&root) {
+        Ok(translated_frontmatter)
+    } else {
+        log::warn!("TOML frontmatter can not be edited in place, reformatted.");
+        toml_string(&root)
+    }
+}
 
-        // Prepare input Vec
-        let src_vec = should_be_translate
-            .iter()
-            .map(|s| s.as_str())
-            .collect::<Vec<&str>>();
+/// Translate TOML values, and replace them with translated text
+async fn translate_toml_values<T: Translator>(
+    translator: &T,
+    from_lang: deepl::SourceLanguage,
+    to_lang: deepl::TargetLanguage,
+    formality: deepl::Formality,
+    values: Vec<&mut String>,
+) -> Result<()> {
+    let src_vec = values.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+    if translator.config().is_free_api_key() {
+        api_availability_check(translator, &src_vec.join("")).await?;
+    }
+    let translated_vec = translator
+        .translate_strings(from_lang, to_lang, formality, &src_vec)
+        .await?;
+    values
+        .into_iter()
+        .zip(translated_vec)
+        .for_each(|(toml_val, translated_str)| *toml_val = translated_str);
+    Ok(())
+}
 
-        // If Deepl API KEY is a free version, get the number of characters remaining to be translated.
-        if deepl.config.is_free_api_key() {
-            api_availability_check(&deepl, &src_vec.join("")).await?;
+/// Serialize whole TOML
+fn toml_string(root: &toml::Value) -> Result<String> {
+    toml::to_string_pretty(root).map_err(|e| Error::Parse(e.to_string()))
+}
+
+/// TOML value at the path
+fn toml_value_mut<'a>(
+    val: &'a mut toml::Value,
+    path: &[PathSegment],
+) -> Option<&'a mut toml::Value> {
+    match path.split_first() {
+        None => Some(val),
+        Some((PathSegment::Key(key), rest)) => toml_value_mut(val.get_mut(key.as_str())?, rest),
+        Some((PathSegment::Index(i), rest)) => toml_value_mut(val.get_mut(*i)?, rest),
+    }
+}
+
+// Collect TOML strings whose path matches frontmatter keys
+fn collect_toml_strings<'a>(
+    val: &'a mut toml::Value,
+    path: &mut Vec<PathSegment>,
+    frontmatter_keys: &FrontmatterKeys,
+    strings: &mut Vec<&'a mut String>,
+) {
+    match val {
+        toml::Value::String(s) if !s.trim().is_empty() && frontmatter_keys.matches(path) => {
+            strings.push(s);
+        }
+        toml::Value::Table(table) => {
+            for (key, val) in table {
+                path.push(PathSegment::Key(key.clone()));
+                collect_toml_strings(val, path, frontmatter_keys, strings);
+                path.pop();
+            }
+        }
+        toml::Value::Array(array) => {
+            for (i, val) in array.iter_mut().enumerate() {
+                path.push(PathSegment::Index(i));
+                collect_toml_strings(val, path, frontmatter_keys, strings);
+                path.pop();
+            }
         }
+        _ => {}
+    }
+}
 
-        // Translate texts
-        let translated_vec = deepl
-            .translate_strings(from_lang, to_lang, formality, &src_vec)
-            .await
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+/// Translate JSON frontmatter
+///
+/// Values of `frontmatter_keys` are translated.
+/// Key order, indentation and surrounding whitespaces of the original are kept.
+pub async fn translate_json<T: Translator>(
+    translator: &T,
+    from_lang: deepl::SourceLanguage,
+    to_lang: deepl::TargetLanguage,
+    formality: deepl::Formality,
+    json_frontmatter: &str,
+    frontmatter_keys: &FrontmatterKeys,
+) -> Result<String> {
+    let mut root = serde_json::from_str::<serde_json::Value>(json_frontmatter)?;
+    if !root.is_object() {
+        return Err(Error::Parse(String::from(
+            "JSON frontmatter is not an object",
+        )));
+    }
 
-        // Replace TOML value with translated text
-        should_be_translate
-            .into_iter()
-            .zip(translated_vec.iter())
-            .for_each(|(toml_val, translated_str)| {
-                toml_val.clear();
-                *toml_val += translated_str.as_str();
-            });
+    // Pickup JSON values for translation
+    let mut should_be_translate: Vec<&mut String> = vec![];
+    collect_json_strings(
+        &mut root,
+        &mut Vec::new(),
+        frontmatter_keys,
+        &mut should_be_translate,
+    );
+    if should_be_translate.is_empty() {
+        return Ok(json_frontmatter.to_string());
+    }
 
-        // Serialize toml::Value should not fail
-        let translated_frontmatter = toml::to_string_pretty(&toml::Value::Table(root)).unwrap();
-        // Show translated frontmatter
-        log::trace!("Translated TOML :\n{}\n", translated_frontmatter);
+    // Prepare input Vec
+    let src_vec = should_be_translate
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<&str>>();
 
-        Ok(translated_frontmatter)
+    // If Deepl API KEY is a free version, get the number of characters remaining to be translated.
+    if translator.config().is_free_api_key() {
+        api_availability_check(translator, &src_vec.join("")).await?;
+    }
+
+    // Translate texts
+    let translated_vec = translator
+        .translate_strings(from_lang, to_lang, formality, &src_vec)
+        .await?;
+
+    // Replace JSON value with translated text
+    should_be_translate
+        .into_iter()
+        .zip(translated_vec)
+        .for_each(|(json_val, translated_str)| *json_val = translated_str);
+
+    // Serialize with the same indent as the original
+    let body = json_frontmatter.trim();
+    let indent = body
+        .lines()
+        .nth(1)
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .unwrap_or_default();
+    let serialized = if body.contains('\n') {
+        let mut buf = Vec::new();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+        let mut serializer = serde_json::Serializer::with_formatter(&mut buf, formatter);
+        serde::Serialize::serialize(&root, &mut serializer)?;
+        String::from_utf8(buf).map_err(|e| Error::Parse(e.to_string()))?
     } else {
-        // TOML parse failed
-        Err(std::io::Error::from(std::io::ErrorKind::InvalidData))
+        serde_json::to_string(&root)?
+    };
+    let leading = &json_frontmatter[..json_frontmatter.len() - json_frontmatter.trim_start().len()];
+    let trailing = &json_frontmatter[json_frontmatter.trim_end().len()..];
+    let translated_frontmatter = format!("{}{}{}", leading, serialized, trailing);
+    log::trace!("Translated JSON :\n{}\n", translated_frontmatter);
+
+    Ok(translated_frontmatter)
+}
+
+// Collect JSON strings whose path matches frontmatter keys
+fn collect_json_strings<'a>(
+    val: &'a mut serde_json::Value,
+    path: &mut Vec<PathSegment>,
+    frontmatter_keys: &FrontmatterKeys,
+    strings: &mut Vec<&'a mut String>,
+) {
+    match val {
+        serde_json::Value::String(s) if !s.trim().is_empty() && frontmatter_keys.matches(path) => {
+            strings.push(s);
+        }
+        serde_json::Value::Object(object) => {
+            for (key, val) in object {
+                path.push(PathSegment::Key(key.clone()));
+                collect_json_strings(val, path, frontmatter_keys, strings);
+                path.pop();
+            }
+        }
+        serde_json::Value::Array(array) => {
+            for (i, val) in array.iter_mut().enumerate() {
+                path.push(PathSegment::Index(i));
+                collect_json_strings(val, path, frontmatter_keys, strings);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Translate YAML frontmatter
+///
+/// Only values of `frontmatter_keys` are replaced in place,
+/// so that key order, comments and quoting style are kept.
+pub async fn translate_yaml<T: Translator>(
+    translator: &T,
+    from_lang: deepl::SourceLanguage,
+    to_lang: deepl::TargetLanguage,
+    formality: deepl::Formality,
+    yaml_frontmatter: &str,
+    frontmatter_keys: &FrontmatterKeys,
+) -> Result<String> {
+    // Pickup YAML values for translation
+    let should_be_translate = yaml::scalars(yaml_frontmatter)
+        .into_iter()
+        .filter(|scalar| !scalar.value.trim().is_empty() && frontmatter_keys.matches(&scalar.path))
+        .collect::<Vec<_>>();
+    if should_be_translate.is_empty() {
+        return Ok(yaml_frontmatter.to_string());
+    }
+
+    // Prepare input Vec
+    let src_vec = should_be_translate
+        .iter()
+        .map(|s| s.value.as_str())
+        .collect::<Vec<&str>>();
+
+    // If Deepl API KEY is a free version, get the number of characters remaining to be translated.
+    if translator.config().is_free_api_key() {
+        api_availability_check(translator, &src_vec.join("")).await?;
     }
+
+    // Translate texts
+    let translated_vec = translator
+        .translate_strings(from_lang, to_lang, formality, &src_vec)
+        .await?;
+
+    // Replace YAML value with translated text
+    let replacements = should_be_translate
+        .iter()
+        .zip(translated_vec)
+        .collect::<Vec<_>>();
+    let translated_frontmatter = yaml::replace_scalars(yaml_frontmatter, &replacements);
+    log::trace!("Translated YAML :\n{}\n", translated_frontmatter);
+
+    Ok(translated_frontmatter)
 }
 
 /// Translate CommonMark
-pub async fn translate_cmark(
-    deepl: &deepl::Deepl,
-    from_lang: deepl::Language,
-    to_lang: deepl::Language,
+///
+/// Block elements found in the translation memory are reused,
+/// only new or edited blocks are sent to the translator.
+/// Large documents are split into multiple requests at block elements.
+pub async fn translate_cmark<T: Translator>(
+    translator: &T,
+    from_lang: deepl::SourceLanguage,
+    to_lang: deepl::TargetLanguage,
     formality: deepl::Formality,
     cmark_text: &str,
-) -> Result<String, std::io::Error> {
-    let xml = cmark_xml::xml_from_cmark(&cmark_text, true);
-    log::trace!("XML: {}\n", xml);
+) -> Result<String> {
+    let xml_root = cmark_xml::xmldom_from_cmark_with_extensions(
+        cmark_text,
+        true,
+        &translator.config().cmark_extensions()?,
+    );
+    translate_xmldom(translator, from_lang, to_lang, formality, &xml_root).await
+}
 
-    let target_name = deepl.config.project_name.as_str();
-    let xml = deepl::Deepl::add_ignore_tags(deepl, target_name, &xml).await;
+/// Translate CommonMark parsed into XML DOM
+async fn translate_xmldom<T: Translator>(
+    translator: &T,
+    from_lang: deepl::SourceLanguage,
+    to_lang: deepl::TargetLanguage,
+    formality: deepl::Formality,
+    xml_root: &minidom::Element,
+) -> Result<String> {
+    log::trace!("XML: {}\n", xml_string(xml_root)?);
 
-    log::trace!("added ignore tags. XML: {}\n", xml);
+    let target_name = translator.config().project_name.as_str();
+    let memory = translator.memory();
+    let glossary_id = if memory.is_some() {
+        translator.glossary_id(from_lang, to_lang).await?
+    } else {
+        None
+    };
+    let ignores = translator
+        .config()
+        .ignores
+        .as_ref()
+        .and_then(|ignores| ignores.get(target_name))
+        .map_or(&[][..], Vec::as_slice);
 
-    // If Deepl API KEY is a free version, get the number of characters remaining to be translated.
-    if deepl.config.is_free_api_key() {
-        api_availability_check(&deepl, &xml).await?;
-    }
+    // Split into block elements, each block is up to request size limit
+    let limit = translator.config().max_request_bytes();
+    let request_size = |elm: &minidom::Element| {
+        xml_string(elm)
+            .and_then(|xml| translator.add_ignore_tags(target_name, &xml))
+            .map_or(0, |xml| segment::form_encoded_len(&xml))
+    };
+    let blocks = segment::split_blocks(xml_root, limit, &request_size);
+
+    // Lookup translation memory for each block element
+    let sources = blocks
+        .iter()
+        .map(|elm| xml_string(elm))
+        .collect::<Result<Vec<_>>>()?;
+    let mut translated = sources
+        .iter()
+        .map(|src| {
+            memory
+                .and_then(|m| {
+                    m.lookup(
+                        from_lang,
+                        to_lang,
+                        formality,
+                        glossary_id.as_deref(),
+                        ignores,
+                        src,
+                    )
+                })
+                .map(|xml| parse_xml(&xml))
+                .transpose()
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let missing = translated
+        .iter()
+        .enumerate()
+        .filter_map(|(i, t)| if t.is_none() { Some(i) } else { None })
+        .collect::<Vec<_>>();
+    log::debug!(
+        "{} of {} blocks found in translation memory",
+        blocks.len() - missing.len(),
+        blocks.len()
+    );
+
+    if !missing.is_empty() {
+        // Group blocks to be translated into chunks, each chunk is sent as one request
+        let sizes = missing
+            .iter()
+            .map(|i| request_size(blocks[*i]))
+            .collect::<Vec<_>>();
+        let chunk_sizes = segment::chunk_sizes(&sizes, limit);
+        let mut missing_iter = missing.iter();
+        let chunks = chunk_sizes
+            .into_iter()
+            .map(|n| {
+                let mut xml_body = minidom::Element::bare("body", cmark_xml::NS);
+                let indices = missing_iter.by_ref().take(n).copied().collect::<Vec<_>>();
+                for i in &indices {
+                    xml_body.append_child(blocks[*i].clone());
+                }
+                let xml = translator.add_ignore_tags(target_name, &xml_string(&xml_body)?)?;
+                log::trace!("added ignore tags. XML: {}\n", xml);
+                Ok((indices, xml))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        log::debug!(
+            "{} blocks are translated in {} requests",
+            missing.len(),
+            chunks.len()
+        );
+
+        // If Deepl API KEY is a free version, get the number of characters remaining to be translated.
+        if translator.config().is_free_api_key() {
+            let text = chunks
+                .iter()
+                .map(|(_, xml)| xml.as_str())
+                .collect::<String>();
+            api_availability_check(translator, &text).await?;
+        }
 
-    // translate
-    let xml_translated = deepl
-        .translate_xml(from_lang, to_lang, formality, target_name, &xml)
-        .await
-        .unwrap();
+        // translate
+        let translated_chunks =
+            futures::future::try_join_all(chunks.iter().map(|(indices, xml)| async move {
+                let xml_translated = translator
+                    .translate_xml(from_lang, to_lang, formality, xml)
+                    .await?;
+                let xml_translated = translator.remove_ignore_tags(&xml_translated);
+                let xml_translated = parse_xml(&xml_translated)?;
 
-    let xml_translated = deepl::Deepl::remove_ignore_tags(deepl, &xml_translated).await;
+                let translated_blocks = xml_translated.children().cloned().collect::<Vec<_>>();
+                if translated_blocks.len() != indices.len() {
+                    return Err(Error::Xml(format!(
+                        "{} blocks are translated into {} blocks",
+                        indices.len(),
+                        translated_blocks.len()
+                    )));
+                }
+                Ok(translated_blocks)
+            }))
+            .await?;
 
-    let cmark_translated = cmark_xml::cmark_from_xml(&xml_translated, true).unwrap();
+        // Put translated blocks back, and save them in translation memory
+        for ((indices, _), translated_blocks) in chunks.iter().zip(translated_chunks) {
+            for (i, elm) in indices.iter().zip(translated_blocks) {
+                if let Some(memory) = memory {
+                    memory.insert(
+                        from_lang,
+                        to_lang,
+                        formality,
+                        glossary_id.as_deref(),
+                        ignores,
+                        &sources[*i],
+                        &xml_string(&elm)?,
+                    )?;
+                }
+                translated[*i] = Some(elm);
+            }
+        }
+    }
+
+    let xml_translated = segment::merge_blocks(
+        xml_root,
+        limit,
+        &request_size,
+        &mut translated.into_iter().flatten(),
+    );
+    let cmark_translated = cmark_xml::cmark_from_xmldom_with_extensions(
+        &xml_translated,
+        true,
+        &translator.config().cmark_extensions()?,
+    )?;
 
     Ok(cmark_translated)
 }
 
-async fn api_availability_check(deepl: &deepl::Deepl, text: &str) -> Result<bool, std::io::Error> {
-    let used_chars = deepl.get_usage().await.unwrap() as usize;
-    let remaining_chars = deepl::MAX_TRANSLATE_LENGTH - used_chars;
+/// Serialize XML element
+fn xml_string(elm: &minidom::Element) -> Result<String> {
+    let mut buf = Vec::<u8>::new();
+    elm.write_to(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| Error::Xml(e.to_string()))
+}
+
+/// Parse XML element
+fn parse_xml(xml: &str) -> Result<minidom::Element> {
+    Ok(xml.parse()?)
+}
+
+async fn api_availability_check<T: Translator>(translator: &T, text: &str) -> Result<bool> {
+    let used_chars = translator.get_usage().await? as usize;
+    let remaining_chars = deepl::MAX_TRANSLATE_LENGTH.saturating_sub(used_chars);
     log::info!("Remaining characters: {}", remaining_chars);
     if remaining_chars < text.len() {
         let error_message = format!(
@@ -194,10 +785,7 @@ async fn api_availability_check(deepl: &deepl::Deepl, text: &str) -> Result<bool
             deepl::MAX_TRANSLATE_LENGTH
         );
         log::error!("{}", error_message);
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            error_message,
-        ));
+        return Err(Error::QuotaExceeded(error_message));
     }
     Ok(true)
 }
@@ -205,15 +793,53 @@ async fn api_availability_check(deepl: &deepl::Deepl, text: &str) -> Result<bool
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mock::{MockStyle, MockTranslator};
     use std::path::PathBuf;
 
+    // Offline backend, wraps each sentence in ⟦⟧
+    fn mock_translator() -> MockTranslator {
+        let mut config = deepl::DeeplConfig::default();
+        config.project_name = String::from("test");
+        config.backup_original_text = true;
+        MockTranslator::with_config(config, MockStyle::Brackets)
+    }
+
     #[tokio::test]
-    async fn test_translate_cmark_file() -> Result<(), Box<dyn std::error::Error>> {
-        // Load Deepl configuration from "deepl.toml"
-        let deepl = deepl::Deepl::with_config("deepl.toml").unwrap();
+    async fn test_detect_cmark_file_language() -> Result<()> {
+        let translator = mock_translator();
+        let tmp_dir =
+            std::env::temp_dir().join(format!("cmark-translate-detect-{}", std::process::id()));
+        std::fs::create_dir_all(&tmp_dir)?;
 
-        let from_lang = deepl::Language::En;
-        let to_lang = deepl::Language::Ja;
+        // Code block is not a sample
+        let path = tmp_dir.join("en.md");
+        std::fs::write(&path, "```\nこんにちは\n```\n\nHello `世界`\n")?;
+        let detected =
+            detect_cmark_file_language(&translator, deepl::TargetLanguage::Ja, &path).await?;
+        assert_eq!(detected, Some(deepl::SourceLanguage::En));
+
+        let path = tmp_dir.join("ja.md");
+        std::fs::write(&path, "# こんにちは\n\nHello\n")?;
+        let detected =
+            detect_cmark_file_language(&translator, deepl::TargetLanguage::Ja, &path).await?;
+        assert_eq!(detected, Some(deepl::SourceLanguage::Ja));
+
+        let path = tmp_dir.join("empty.md");
+        std::fs::write(&path, "```\ncode\n```\n")?;
+        let detected =
+            detect_cmark_file_language(&translator, deepl::TargetLanguage::Ja, &path).await?;
+        assert_eq!(detected, None);
+
+        std::fs::remove_dir_all(&tmp_dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_translate_cmark_file() -> Result<()> {
+        let translator = mock_translator();
+
+        let from_lang = deepl::SourceLanguage::En;
+        let to_lang = deepl::TargetLanguage::Ja;
         let formality = deepl::Formality::Formal;
 
         // Prepare temporary directory for testing
@@ -226,26 +852,79 @@ mod tests {
         )?;
 
         // Call the function to be tested
-        // APIの使用上限に達するとエラーになる。
-        translate_cmark_file(&deepl, from_lang, to_lang, formality, &src_path, &dst_path)
-            .await
-            .unwrap();
+        translate_cmark_file(
+            &translator,
+            from_lang,
+            to_lang,
+            formality,
+            &src_path,
+            &dst_path,
+        )
+        .await?;
 
         // Check if the translated content is as expected
+        // Frontmatter of .md file is translated with default keys.
         let translated_content = std::fs::read_to_string(&dst_path)?;
-        let expected_content = "+++\ntitle = \"こんにちは世界\"\n+++\nこれはテストです。\n<!---\nThis is a test.\n-->\n";
+        std::fs::remove_file(&dst_path)?;
+        let expected_content = "+++\ntitle = \"Hello World\"\n+++\n⟦This is a test.⟧\n\n<!---\n\nThis is a test.\n-->\n";
         assert_eq!(translated_content, expected_content);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_translate_toml() -> Result<(), Box<dyn std::error::Error>> {
-        // Load Deepl configuration from "deepl.toml"
-        let deepl = deepl::Deepl::with_config("deepl.toml").unwrap();
+    async fn test_translate_cmark_file_multi() -> Result<()> {
+        let translator = mock_translator();
+        let tmp_dir =
+            std::env::temp_dir().join(format!("cmark-translate-multi-{}", std::process::id()));
+        std::fs::create_dir_all(&tmp_dir)?;
+        let src_path = tmp_dir.join("test.md");
+        std::fs::write(&src_path, "# Title\n\nThis is a test.\n")?;
+
+        let targets = [
+            (
+                deepl::TargetLanguage::Ja,
+                tmp_dir.join("ja").join("test.md"),
+            ),
+            (
+                deepl::TargetLanguage::Ko,
+                tmp_dir.join("ko").join("test.md"),
+            ),
+        ];
+        let results = translate_cmark_file_multi(
+            &translator,
+            deepl::SourceLanguage::En,
+            deepl::Formality::Default,
+            &src_path,
+            &targets,
+        )
+        .await?;
+        assert_eq!(results.len(), 2);
 
-        let from_lang = deepl::Language::En;
-        let to_lang = deepl::Language::Ja;
+        // Each target is same as single translation
+        let expected = translate_cmark(
+            &translator,
+            deepl::SourceLanguage::En,
+            deepl::TargetLanguage::Ja,
+            deepl::Formality::Default,
+            "# Title\n\nThis is a test.\n",
+        )
+        .await?;
+        for ((_, dst_path), result) in targets.iter().zip(results) {
+            result?;
+            assert!(std::fs::read_to_string(dst_path)?.starts_with(&expected));
+        }
+
+        std::fs::remove_dir_all(&tmp_dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_translate_toml() -> Result<()> {
+        let translator = mock_translator();
+
+        let from_lang = deepl::SourceLanguage::En;
+        let to_lang = deepl::TargetLanguage::Ja;
         let formality = deepl::Formality::Formal;
 
         let toml_frontmatter = r#"title = "Hello World"
@@ -253,32 +932,314 @@ mod tests {
                                         [extra]
                                         time = "2023-03-10""#;
 
-        let translated_frontmatter =
-            translate_toml(&deepl, from_lang, to_lang, formality, toml_frontmatter).await?;
-        let expected_translated = r#"title = "こんにちは世界"
-                                            description = "説明"
-                                            [extra]
-                                            time = "2023年3月10日""#;
+        let translated_frontmatter = translate_toml(
+            &translator,
+            from_lang,
+            to_lang,
+            formality,
+            toml_frontmatter,
+            &FrontmatterKeys::new(&crate::frontmatter::DEFAULT_FRONTMATTER_KEYS)?,
+        )
+        .await?;
+        let expected_translated = r#"title = "⟦Hello World⟧"
+                                        description = "⟦Description⟧"
+                                        [extra]
+                                        time = "⟦2023-03-10⟧""#;
+        assert_eq!(translated_frontmatter, expected_translated);
+
+        // Not translated TOML is kept as is, without calling translator
+        let toml_frontmatter = "slug = \"a\"\n# comment\nweight = 1\n";
+        let translated_frontmatter = translate_toml(
+            &translator,
+            from_lang,
+            to_lang,
+            formality,
+            toml_frontmatter,
+            &FrontmatterKeys::new(&crate::frontmatter::DEFAULT_FRONTMATTER_KEYS)?,
+        )
+        .await?;
+        assert_eq!(translated_frontmatter, toml_frontmatter);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_translate_json() -> Result<()> {
+        let translator = mock_translator();
+
+        let from_lang = deepl::SourceLanguage::En;
+        let to_lang = deepl::TargetLanguage::Ja;
+        let formality = deepl::Formality::Formal;
+        let frontmatter_keys = FrontmatterKeys::new(&["title", "tags[*]", "seo.*.text"])?;
+
+        let json_frontmatter = "\n{\n    \"title\": \"Hello World\",\n    \"date\": \"2023-03-10\",\n    \"tags\": [\"docs\"],\n    \"seo\": {\n        \"og\": {\"text\": \"Open Graph\", \"url\": \"/hello\"}\n    }\n}\n";
+        let translated_frontmatter = translate_json(
+            &translator,
+            from_lang,
+            to_lang,
+            formality,
+            json_frontmatter,
+            &frontmatter_keys,
+        )
+        .await?;
+        let expected_translated = r#"
+{
+    "title": "⟦Hello World⟧",
+    "date": "2023-03-10",
+    "tags": [
+        "⟦docs⟧"
+    ],
+    "seo": {
+        "og": {
+            "text": "⟦Open Graph⟧",
+            "url": "/hello"
+        }
+    }
+}
+"#;
+        assert_eq!(translated_frontmatter, expected_translated);
+
+        // Compact JSON is kept compact
+        let translated_frontmatter = translate_json(
+            &translator,
+            from_lang,
+            to_lang,
+            formality,
+            r#"{"title": "Hello", "draft": true}"#,
+            &frontmatter_keys,
+        )
+        .await?;
+        assert_eq!(
+            translated_frontmatter,
+            r#"{"title":"⟦Hello⟧","draft":true}"#
+        );
+
+        // Not translated JSON is kept as is, without calling translator
+        let json_frontmatter = "{ \"slug\": \"a\",  \"weight\": 1 }\n";
+        let translated_frontmatter = translate_json(
+            &translator,
+            from_lang,
+            to_lang,
+            formality,
+            json_frontmatter,
+            &frontmatter_keys,
+        )
+        .await?;
+        assert_eq!(translated_frontmatter, json_frontmatter);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_translate_yaml() -> Result<()> {
+        let translator = mock_translator();
+
+        let from_lang = deepl::SourceLanguage::En;
+        let to_lang = deepl::TargetLanguage::Ja;
+        let formality = deepl::Formality::Formal;
+
+        let yaml_frontmatter = r#"
+# Page settings
+title: Hello World
+sidebar_position: 2
+sidebar_label: 'Hello'
+description: "Description" # shown in search results
+keywords:
+  - docs
+  - guide
+slug: /hello
+"#;
+
+        let translated_frontmatter = translate_yaml(
+            &translator,
+            from_lang,
+            to_lang,
+            formality,
+            yaml_frontmatter,
+            &FrontmatterKeys::new(&crate::frontmatter::DEFAULT_FRONTMATTER_KEYS)?,
+        )
+        .await?;
+        let expected_translated = r#"
+# Page settings
+title: ⟦Hello World⟧
+sidebar_position: 2
+sidebar_label: '⟦Hello⟧'
+description: "⟦Description⟧" # shown in search results
+keywords:
+  - ⟦docs⟧
+  - ⟦guide⟧
+slug: /hello
+"#;
         assert_eq!(translated_frontmatter, expected_translated);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_translate_cmark() -> Result<(), Box<dyn std::error::Error>> {
-        // Load Deepl configuration from "deepl.toml"
-        let deepl = deepl::Deepl::with_config("deepl.toml").unwrap();
+    async fn test_translate_cmark() -> Result<()> {
+        let translator = mock_translator();
 
-        let from_lang = deepl::Language::En;
-        let to_lang = deepl::Language::Ja;
+        let from_lang = deepl::SourceLanguage::En;
+        let to_lang = deepl::TargetLanguage::Ja;
         let formality = deepl::Formality::Formal;
 
         let cmark_text = "This is a test.";
         let translated_cmark =
-            translate_cmark(&deepl, from_lang, to_lang, formality, cmark_text).await?;
-        let expected_translated = "これはテストです。";
+            translate_cmark(&translator, from_lang, to_lang, formality, cmark_text).await?;
+        let expected_translated = "⟦This is a test.⟧\n";
         assert_eq!(translated_cmark, expected_translated);
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_translate_cmark_round_trip() -> Result<()> {
+        let mut translator = mock_translator();
+        translator.config.ignores = Some(
+            [(
+                String::from("test"),
+                vec![String::from("Motoko"), String::from("C++")],
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        let cmark_text = "# Title\n\nWrite *Motoko* or C++ with `moc` and [link](https://example.com).\n\n- one\n- two\n\n```rust\nlet a = 1;\n```\n";
+        let translated_cmark = translate_cmark(
+            &translator,
+            deepl::SourceLanguage::En,
+            deepl::TargetLanguage::Ja,
+            deepl::Formality::Default,
+            cmark_text,
+        )
+        .await?;
+        let expected_translated = "# ⟦Title⟧\n\n⟦Write *Motoko* or C++ with `moc` and [link](https://example.com).⟧\n\n- ⟦one⟧\n- ⟦two⟧\n\n```rust\nlet a = 1;\n```\n";
+        assert_eq!(translated_cmark, expected_translated);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_translate_cmark_footnotes() -> Result<()> {
+        let translator = mock_translator();
+
+        let cmark_text = "See the note[^note].\n\n- [ ] todo\n\n[^note]: Footnote text.\n";
+        let translated_cmark = translate_cmark(
+            &translator,
+            deepl::SourceLanguage::En,
+            deepl::TargetLanguage::Ja,
+            deepl::Formality::Default,
+            cmark_text,
+        )
+        .await?;
+        let expected_translated =
+            "⟦See the note[^note].⟧\n\n- [ ] ⟦todo⟧\n\n[^note]: ⟦Footnote text.⟧\n";
+        assert_eq!(translated_cmark, expected_translated);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_translate_cmark_link_references() -> Result<()> {
+        let translator = mock_translator();
+
+        let cmark_text = "Read [the guide][guide] and [FAQ].\n\n[guide]: https://example.com/guide\n[faq]: https://example.com/faq \"FAQ\"\n";
+        let translated_cmark = translate_cmark(
+            &translator,
+            deepl::SourceLanguage::En,
+            deepl::TargetLanguage::Ja,
+            deepl::Formality::Default,
+            cmark_text,
+        )
+        .await?;
+        let expected_translated = "⟦Read [the guide][guide] and [FAQ].⟧\n\n[guide]: https://example.com/guide\n[faq]: https://example.com/faq \"FAQ\"\n";
+        assert_eq!(translated_cmark, expected_translated);
+
+        // Text in containers with definitions, and text following definitions are translated
+        let cmark_text = "> [x]: /y\n>\n> Quote [x].\n\n- Item\n\n  [z]: /z\n\n- Other [z].\n\n[a]: https://a.com\nText after def [a].\n";
+        let translated_cmark = translate_cmark(
+            &translator,
+            deepl::SourceLanguage::En,
+            deepl::TargetLanguage::Ja,
+            deepl::Formality::Default,
+            cmark_text,
+        )
+        .await?;
+        let expected_translated = "> [x]: /y\n>\n> ⟦Quote [x].⟧\n\n- ⟦Item⟧\n\n  [z]: /z\n\n- ⟦Other [z].⟧\n\n[a]: https://a.com\n⟦Text after def [a].⟧\n";
+        assert_eq!(translated_cmark, expected_translated);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_translate_cmark_with_memory() -> Result<()> {
+        let mut translator = mock_translator();
+        translator.memory = Some(crate::memory::TranslationMemory::new());
+
+        let from_lang = deepl::SourceLanguage::En;
+        let to_lang = deepl::TargetLanguage::Ja;
+        let formality = deepl::Formality::Default;
+
+        let translated_cmark =
+            translate_cmark(&translator, from_lang, to_lang, formality, "# One\n\nTwo\n").await?;
+        assert_eq!(translated_cmark, "# ⟦One⟧\n\n⟦Two⟧\n");
+        assert_eq!(translator.get_usage().await?, 6);
+
+        // Only edited paragraph is translated
+        let translated_cmark = translate_cmark(
+            &translator,
+            from_lang,
+            to_lang,
+            formality,
+            "# One\n\nThree\n",
+        )
+        .await?;
+        assert_eq!(translated_cmark, "# ⟦One⟧\n\n⟦Three⟧\n");
+        assert_eq!(translator.get_usage().await?, 11);
+
+        // Nothing is translated
+        let translated_cmark = translate_cmark(
+            &translator,
+            from_lang,
+            to_lang,
+            formality,
+            "# One\n\nThree\n",
+        )
+        .await?;
+        assert_eq!(translated_cmark, "# ⟦One⟧\n\n⟦Three⟧\n");
+        assert_eq!(translator.get_usage().await?, 11);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_translate_cmark_split_requests() -> Result<()> {
+        let cmark = "# Title\n\n- one\n- two\n  - nested\n\n> quoted\n> text\n\nEnd of document.\n";
+
+        let translator = mock_translator();
+        let expected = translate_cmark(
+            &translator,
+            deepl::SourceLanguage::En,
+            deepl::TargetLanguage::Ja,
+            deepl::Formality::Default,
+            cmark,
+        )
+        .await?;
+
+        // Each block is sent as separated request, the result should be the same
+        let mut translator = mock_translator();
+        translator.config.max_request_bytes = Some(10);
+        let translated = translate_cmark(
+            &translator,
+            deepl::SourceLanguage::En,
+            deepl::TargetLanguage::Ja,
+            deepl::Formality::Default,
+            cmark,
+        )
+        .await?;
+        assert_eq!(translated, expected);
+        assert!(translated.contains("⟦nested⟧"));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,243 @@
+// SPDX-License-Identifier: MIT
+//!
+//! Translation backend abstraction
+//!
+
+use crate::deepl::{
+    find_glossary, DeeplConfig, DeeplGlossary, DeeplLanguage, Formality, SourceLanguage,
+    TargetLanguage,
+};
+use crate::error::{Error, Result};
+use crate::memory::TranslationMemory;
+use regex::Regex;
+
+/// Translation engine used by the CommonMark pipeline
+///
+/// `Deepl` is the default implementation. Other engines or test doubles
+/// can be plugged into `translate_cmark` and friends by implementing this trait.
+/// The trait is modeled on DeepL API, settings are read from `DeeplConfig`
+/// and languages, formality and glossaries are those of DeepL.
+/// It is object safe, and can be used as `dyn Translator`.
+#[async_trait::async_trait]
+pub trait Translator: Send + Sync {
+    /// Config file (project name, ignore words, ...) shared with the pipeline
+    fn config(&self) -> &DeeplConfig;
+
+    /// Translation memory consulted before translation, None to always translate
+    fn memory(&self) -> Option<&TranslationMemory> {
+        None
+    }
+
+    /// Glossary ID used for the language pair, part of the translation memory key
+    async fn glossary_id(
+        &self,
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
+    ) -> Result<Option<String>> {
+        resolve_glossary_id(self, from_lang, to_lang).await
+    }
+
+    /// Translate multiple plain text strings
+    async fn translate_strings(
+        &self,
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
+        formality: Formality,
+        body: &[&str],
+    ) -> Result<Vec<String>>;
+
+    /// Detect language of text, None if it is not a supported source language
+    async fn detect_language(
+        &self,
+        to_lang: TargetLanguage,
+        text: &str,
+    ) -> Result<Option<SourceLanguage>>;
+
+    /// Translate XML string, text in ignored tags are kept as is
+    async fn translate_xml(
+        &self,
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
+        formality: Formality,
+        xml_body: &str,
+    ) -> Result<String>;
+
+    /// Get usage, returns translated characters
+    async fn get_usage(&self) -> Result<i32>;
+
+    /// Register new glossary, pairs of (source, target)
+    async fn register_glossaries(
+        &self,
+        name: &str,
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
+        glossaries: &[(&str, &str)],
+    ) -> Result<DeeplGlossary>;
+
+    /// List registered glossaries
+    async fn list_glossaries(&self) -> Result<Vec<DeeplGlossary>>;
+
+    /// Get entries of registered glossary, pairs of (source, target)
+    async fn glossary_entries(&self, id: &str) -> Result<Vec<(String, String)>>;
+
+    /// Remove registered glossaries
+    async fn remove_glossary(&self, id: &str) -> Result<()>;
+
+    /// Supported source languages
+    async fn source_languages(&self) -> Result<Vec<DeeplLanguage>> {
+        Ok(SourceLanguage::ALL
+            .iter()
+            .map(|lang| DeeplLanguage {
+                language: lang.as_langcode().to_ascii_uppercase(),
+                name: lang.name().to_string(),
+                supports_formality: false,
+            })
+            .collect())
+    }
+
+    /// Supported target languages
+    async fn target_languages(&self) -> Result<Vec<DeeplLanguage>> {
+        Ok(TargetLanguage::ALL
+            .iter()
+            .map(|lang| DeeplLanguage {
+                language: lang.as_langcode().to_ascii_uppercase(),
+                name: lang.name().to_string(),
+                supports_formality: lang.supports_formality(),
+            })
+            .collect())
+    }
+
+    /// Check target language and formality are supported before translation
+    async fn check_target_language(
+        &self,
+        to_lang: TargetLanguage,
+        formality: Formality,
+    ) -> Result<()> {
+        let languages = self.target_languages().await?;
+        // Deprecated codes without regional variant ("en", "pt") may not be listed
+        let Some(language) = languages
+            .iter()
+            .find(|l| l.language.eq_ignore_ascii_case(to_lang.as_langcode()))
+            .or_else(|| {
+                languages.iter().find(|l| {
+                    l.language
+                        .split('-')
+                        .next()
+                        .unwrap_or_default()
+                        .eq_ignore_ascii_case(to_lang.glossary_langcode())
+                })
+            })
+        else {
+            return Err(Error::Config(format!(
+                "target language \"{}\" is not supported",
+                to_lang.as_langcode()
+            )));
+        };
+        // Formality is sent as "prefer_more" or "prefer_less",
+        // which DeepL ignores for languages without formality support
+        if formality != Formality::Default && !language.supports_formality {
+            log::warn!(
+                "formality is not supported for target language \"{}\", translated with default formality",
+                to_lang.as_langcode()
+            );
+        }
+        Ok(())
+    }
+
+    /// Translate single text string
+    #[allow(dead_code)]
+    async fn translate(
+        &self,
+        from_lang: SourceLanguage,
+        to_lang: TargetLanguage,
+        formality: Formality,
+        body: &str,
+    ) -> Result<String> {
+        let mut result = self
+            .translate_strings(from_lang, to_lang, formality, &[body])
+            .await?;
+        if result.is_empty() {
+            // Empty response
+            Ok(String::new())
+        } else {
+            Ok(result.swap_remove(0))
+        }
+    }
+
+    /// Wrap words listed in `[ignores]` with <ignore-tag>
+    fn add_ignore_tags(&self, target_name: &str, xml_body: &str) -> Result<String> {
+        match self
+            .config()
+            .ignores
+            .as_ref()
+            .and_then(|ignores| ignores.get(target_name))
+        {
+            Some(ignore_trans_words) => {
+                let mut ignore_trans_words = ignore_trans_words.clone();
+                ignore_trans_words.sort_by_key(|w| std::cmp::Reverse(w.len()));
+                ignore_trans_words
+                    .iter()
+                    .try_fold(xml_body.to_owned(), |acc, w| {
+                        let re = Regex::new(&format!("(?i){}", regex::escape(w))).map_err(|e| {
+                            Error::Config(format!("invalid ignore word \"{}\" : {}", w, e))
+                        })?;
+                        Ok(re
+                            .replace_all(&acc, |caps: &regex::Captures| {
+                                format!("<ignore-tag>{}</ignore-tag>", &caps[0])
+                            })
+                            .to_string())
+                    })
+            }
+            None => Ok(xml_body.to_string()),
+        }
+    }
+
+    /// Remove <ignore-tag> added by `add_ignore_tags`
+    fn remove_ignore_tags(&self, body: &str) -> String {
+        body.replace("<ignore-tag>", "")
+            .replace("</ignore-tag>", "")
+    }
+}
+
+/// Find glossary ID for the language pair, and check it is ready to use
+///
+/// Glossary is selected by `DeeplConfig::glossary_name`, either name or ID.
+/// ID stored as `"<from>_<to>" = "<ID>"` in config file takes precedence.
+pub(crate) async fn resolve_glossary_id<T: Translator + ?Sized>(
+    translator: &T,
+    from_lang: SourceLanguage,
+    to_lang: TargetLanguage,
+) -> Result<Option<String>> {
+    let config = translator.config();
+    let glossaries = translator.list_glossaries().await?;
+    let glossary = match config.stored_glossary_id(from_lang, to_lang) {
+        Some(id) => Some(
+            glossaries
+                .iter()
+                .find(|g| g.glossary_id == id)
+                .ok_or_else(|| {
+                    Error::Config(format!("glossary ID {} in config file NOT found", id))
+                })?,
+        ),
+        None => find_glossary(&glossaries, config.glossary_name(), from_lang, to_lang),
+    };
+
+    match glossary {
+        Some(glossary) if !glossary.ready => Err(Error::Config(format!(
+            "glossary \"{}\" ({}) is not ready yet, try again later",
+            glossary.name, glossary.glossary_id
+        ))),
+        Some(glossary) => Ok(Some(glossary.glossary_id.clone())),
+        None => {
+            if config.glossary.is_some() {
+                log::warn!(
+                    "Glossary \"{}\" for {} -> {} NOT found",
+                    config.glossary_name(),
+                    from_lang.as_langcode(),
+                    to_lang.as_langcode()
+                );
+            }
+            Ok(None)
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
-use crate::deepl;
+use crate::error::{Error, Result};
+use crate::translator::Translator;
 use regex::Regex;
 use walkdir::{DirEntry, WalkDir};
 
@@ -8,34 +9,45 @@ fn is_hidden(entry: &DirEntry) -> bool {
     entry
         .file_name()
         .to_str()
-        .map(|s| s.starts_with("."))
+        .map(|s| s.starts_with('.'))
         .unwrap_or(false)
 }
 
-fn check_extension(ext: Option<&Vec<String>>, entry: &DirEntry) -> bool {
+fn extension_regex(ext: &[String]) -> Result<Regex> {
+    let ext = ext
+        .iter()
+        .map(|e| regex::escape(e))
+        .collect::<Vec<_>>()
+        .join("|");
+    Regex::new(&format!(".*\\.({})$", ext)).map_err(|e| Error::Config(e.to_string()))
+}
+
+fn check_extension(ext: Option<&Regex>, entry: &DirEntry) -> bool {
     log::trace!("ext : {:?}, entry : {:?}", ext, entry);
-    if ext.is_none() {
-        true
-    } else {
-        let ext = ext.unwrap().join("|");
-        let ext = format!(".*\\.({})$", ext);
-        let ext = Regex::new(&ext).unwrap();
+    if let Some(ext) = ext {
         entry
             .file_name()
             .to_str()
             .map(|s| ext.is_match(s))
             .unwrap_or(false)
+    } else {
+        true
     }
 }
 
-pub fn new(deepl: &deepl::Deepl, path: PathBuf, max_depth: usize, hidden: bool) -> Vec<PathBuf> {
+pub fn new<T: Translator>(
+    translator: &T,
+    path: PathBuf,
+    max_depth: usize,
+    hidden: bool,
+) -> Result<Vec<PathBuf>> {
     log::trace!("start walkdir!!! path : {:?}", path);
     let walkdir = WalkDir::new(path).max_depth(max_depth).into_iter();
     log::trace!("walkdir : {:?}", walkdir);
 
     let walkdir = walkdir.filter_map(|e| e.ok());
-    let target_name = deepl.config.project_name.as_str();
-    let target_extensions = match &deepl.config.target_extensions {
+    let target_name = translator.config().project_name.as_str();
+    let target_extensions = match &translator.config().target_extensions {
         Some(ext_map) => {
             if ext_map.contains_key(target_name) {
                 ext_map.get(target_name)
@@ -45,16 +57,19 @@ pub fn new(deepl: &deepl::Deepl, path: PathBuf, max_depth: usize, hidden: bool)
         }
         None => None,
     };
+    let target_extensions = target_extensions
+        .map(|ext| extension_regex(ext))
+        .transpose()?;
 
     log::trace!("walkdir.size_hint().0 : {:?}", walkdir.size_hint().0);
     let mut walkdir_res = Vec::new();
     for e in walkdir {
-        if (!hidden && is_hidden(&e)) || !check_extension(target_extensions, &e) {
+        if (!hidden && is_hidden(&e)) || !check_extension(target_extensions.as_ref(), &e) {
             continue;
         }
         walkdir_res.push(e.into_path())
     }
-    walkdir_res
+    Ok(walkdir_res)
 }
 
 // mod test {
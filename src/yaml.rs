@@ -0,0 +1,674 @@
+// SPDX-License-Identifier: MIT
+//!
+//! Minimal YAML editor for frontmatter
+//!
+//! Scalar values are located by their paths and replaced in place,
+//! so that key order, comments and quoting style of the other parts are kept as is.
+//! Block mappings, block sequences, flow sequences and scalars are understood,
+//! other lines (flow mappings, aliases, multi-line plain scalars, ...) are left untouched.
+//!
+
+use crate::frontmatter::PathSegment;
+use std::ops::Range;
+
+/// Quoting style of scalar
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalarStyle {
+    Plain,
+    SingleQuoted,
+    DoubleQuoted,
+    /// Block scalar `|`
+    Literal,
+    /// Block scalar `>`
+    Folded,
+}
+
+/// Scalar value found in YAML text
+#[derive(Clone, Debug)]
+pub struct YamlScalar {
+    pub path: Vec<PathSegment>,
+    pub value: String,
+    pub style: ScalarStyle,
+    /// Byte range of the value in YAML text, including quotes
+    span: Range<usize>,
+    /// Indent of block scalar lines
+    indent: usize,
+    /// In flow sequence `[...]`
+    in_flow: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FrameKind {
+    Mapping,
+    Sequence,
+}
+
+/// Block collection whose entries start at `indent`
+#[derive(Debug)]
+struct Frame {
+    indent: usize,
+    kind: FrameKind,
+    path: Vec<PathSegment>,
+    next_index: usize,
+}
+
+/// Find scalar values in YAML text
+pub fn scalars(yaml: &str) -> Vec<YamlScalar> {
+    // (byte offset, line without newline)
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in yaml.split_inclusive('\n') {
+        lines.push((offset, line.trim_end_matches(['\n', '\r'])));
+        offset += line.len();
+    }
+
+    let mut found = Vec::new();
+    let mut frames = vec![Frame {
+        indent: 0,
+        kind: FrameKind::Mapping,
+        path: Vec::new(),
+        next_index: 0,
+    }];
+    // Key without value, and indent of its line. Value may follow in nested lines.
+    let mut pending: Option<(usize, Vec<PathSegment>)> = None;
+
+    let mut pos = 0;
+    while pos < lines.len() {
+        let (offset, line) = lines[pos];
+        pos += 1;
+        let body = line.trim_start_matches(' ');
+        let indent = line.len() - body.len();
+        if body.is_empty()
+            || body.starts_with('#')
+            || line.starts_with("---")
+            || line.starts_with("...")
+        {
+            continue;
+        }
+        let is_item = body == "-" || body.starts_with("- ");
+
+        if let Some((key_indent, path)) = pending.take() {
+            // Sequence may be placed at the same indent as its key
+            if key_indent < indent || (key_indent == indent && is_item) {
+                let kind = if is_item {
+                    FrameKind::Sequence
+                } else {
+                    FrameKind::Mapping
+                };
+                frames.push(Frame {
+                    indent,
+                    kind,
+                    path,
+                    next_index: 0,
+                });
+            }
+        }
+        while let Some(top) = frames.last() {
+            if indent < top.indent
+                || (indent == top.indent && top.kind == FrameKind::Sequence && !is_item)
+            {
+                frames.pop();
+            } else {
+                break;
+            }
+        }
+        let Some(top) = frames.last_mut() else {
+            break;
+        };
+        if top.indent != indent {
+            // Continuation of multi-line scalar, or unsupported syntax
+            continue;
+        }
+
+        let mut path = top.path.clone();
+        let mut col = indent;
+        let mut owner_indent = indent;
+        if top.kind == FrameKind::Sequence {
+            if !is_item {
+                continue;
+            }
+            path.push(PathSegment::Index(top.next_index));
+            top.next_index += 1;
+            let rest = &line[indent + 1..];
+            col = line.len() - rest.trim_start_matches(' ').len();
+            if mapping_key(&line[col..]).is_some() {
+                // Mapping in sequence item, "- key: value"
+                frames.push(Frame {
+                    indent: col,
+                    kind: FrameKind::Mapping,
+                    path: path.clone(),
+                    next_index: 0,
+                });
+                owner_indent = col;
+            } else {
+                match value_kind(&line[col..]) {
+                    ValueKind::Empty => pending = Some((indent, path)),
+                    ValueKind::Block(style) => {
+                        pos = block_scalar(&lines, pos, indent, style, path, &mut found);
+                    }
+                    ValueKind::Inline => {
+                        inline_value(line, offset, col, path, &mut found);
+                        drop_multi_line_plain(&lines, pos, indent, &mut found);
+                    }
+                }
+                continue;
+            }
+        }
+
+        // Mapping entry, "key: value"
+        let Some((key, key_len)) = mapping_key(&line[col..]) else {
+            continue;
+        };
+        path.push(PathSegment::Key(key));
+        let rest = &line[col + key_len..];
+        let col = line.len() - rest.trim_start_matches(' ').len();
+        match value_kind(&line[col..]) {
+            ValueKind::Empty => pending = Some((owner_indent, path)),
+            ValueKind::Block(style) => {
+                pos = block_scalar(&lines, pos, owner_indent, style, path, &mut found);
+            }
+            ValueKind::Inline => {
+                inline_value(line, offset, col, path, &mut found);
+                drop_multi_line_plain(&lines, pos, owner_indent, &mut found);
+            }
+        }
+    }
+    found
+}
+
+/// Replace scalar values, each value is written in its original quoting style if possible
+pub fn replace_scalars(yaml: &str, replacements: &[(&YamlScalar, String)]) -> String {
+    let mut sorted = replacements.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|(scalar, _)| std::cmp::Reverse(scalar.span.start));
+
+    let mut replaced = yaml.to_string();
+    for (scalar, value) in sorted {
+        replaced.replace_range(scalar.span.clone(), &encode(scalar, value));
+    }
+    replaced
+}
+
+enum ValueKind {
+    /// No value on this line
+    Empty,
+    /// Block scalar `|` or `>`
+    Block(ScalarStyle),
+    Inline,
+}
+
+fn value_kind(text: &str) -> ValueKind {
+    if text.is_empty() || text.starts_with('#') {
+        ValueKind::Empty
+    } else if text.starts_with('|') {
+        ValueKind::Block(ScalarStyle::Literal)
+    } else if text.starts_with('>') {
+        ValueKind::Block(ScalarStyle::Folded)
+    } else {
+        ValueKind::Inline
+    }
+}
+
+/// Parse mapping key at the beginning of text, returns key and length up to `:`
+fn mapping_key(text: &str) -> Option<(String, usize)> {
+    if text.starts_with(['"', '\'']) {
+        let (key, len) = quoted(text)?;
+        let rest = &text[len..];
+        let colon = len + (rest.len() - rest.trim_start_matches(' ').len());
+        return is_mapping_colon(text, colon).then_some((key, colon + 1));
+    }
+    if text.starts_with(['[', '{', '#', '&', '*', '!', '|', '>'])
+        || text == "-"
+        || text.starts_with("- ")
+    {
+        return None;
+    }
+    let colon = text
+        .char_indices()
+        .find(|(i, c)| *c == ':' && is_mapping_colon(text, *i))
+        .map(|(i, _)| i)?;
+    let key = text[..colon].trim_end();
+    if key.is_empty() || key.contains(" #") {
+        return None;
+    }
+    Some((key.to_string(), colon + 1))
+}
+
+/// `:` followed by space or end of line
+fn is_mapping_colon(text: &str, pos: usize) -> bool {
+    text[pos..].starts_with(':') && matches!(text[pos + 1..].chars().next(), None | Some(' '))
+}
+
+/// Parse value written in the same line, from byte position `col`
+fn inline_value(
+    line: &str,
+    offset: usize,
+    col: usize,
+    path: Vec<PathSegment>,
+    found: &mut Vec<YamlScalar>,
+) {
+    let text = &line[col..];
+    if text.starts_with('[') {
+        flow_sequence(text, offset + col, path, found);
+    } else if let Some(scalar) = flow_scalar(text, offset + col, path, false) {
+        found.push(scalar);
+    }
+}
+
+/// Remove plain scalar just found, if it continues to the next line
+///
+/// Multi-line plain scalars are left untouched, rather than translating only the first line.
+fn drop_multi_line_plain(
+    lines: &[(usize, &str)],
+    next: usize,
+    owner_indent: usize,
+    found: &mut Vec<YamlScalar>,
+) {
+    let is_plain = found
+        .last()
+        .is_some_and(|scalar| scalar.style == ScalarStyle::Plain && !scalar.in_flow);
+    let next_line = lines[next.min(lines.len())..]
+        .iter()
+        .map(|(_, line)| *line)
+        .find(|line| !line.trim().is_empty());
+    let is_continued = next_line.is_some_and(|line| {
+        let body = line.trim_start_matches(' ');
+        line.len() - body.len() > owner_indent && !body.starts_with('#')
+    });
+    if is_plain && is_continued {
+        found.pop();
+    }
+}
+
+/// Parse single line flow sequence `[a, "b", 'c']`
+fn flow_sequence(text: &str, offset: usize, path: Vec<PathSegment>, found: &mut Vec<YamlScalar>) {
+    let mut items = Vec::new();
+    let mut pos = 1;
+    loop {
+        let rest = &text[pos..];
+        pos += rest.len() - rest.trim_start_matches(' ').len();
+        let rest = &text[pos..];
+        if rest.starts_with(']') {
+            break;
+        }
+        if rest.is_empty() || rest.starts_with(['[', '{']) {
+            // Multi-line or nested collection
+            return;
+        }
+        let len = if rest.starts_with(['"', '\'']) {
+            let Some((_, len)) = quoted(rest) else {
+                return;
+            };
+            len
+        } else {
+            rest.find([',', ']']).unwrap_or(rest.len())
+        };
+        let item_path = [path.as_slice(), &[PathSegment::Index(items.len())]].concat();
+        items.push(flow_scalar(&rest[..len], offset + pos, item_path, true));
+        pos += len;
+
+        let rest = &text[pos..];
+        pos += rest.len() - rest.trim_start_matches(' ').len();
+        match text[pos..].chars().next() {
+            Some(',') => pos += 1,
+            Some(']') => break,
+            _ => return,
+        }
+    }
+    found.extend(items.into_iter().flatten());
+}
+
+/// Parse quoted or plain scalar at the beginning of text
+fn flow_scalar(
+    text: &str,
+    offset: usize,
+    path: Vec<PathSegment>,
+    in_flow: bool,
+) -> Option<YamlScalar> {
+    let (value, len, style) = if text.starts_with(['"', '\'']) {
+        let (value, len) = quoted(text)?;
+        let style = if text.starts_with('"') {
+            ScalarStyle::DoubleQuoted
+        } else {
+            ScalarStyle::SingleQuoted
+        };
+        (value, len, style)
+    } else {
+        if text.starts_with(['{', '*', '&', '!']) {
+            // Flow mapping, alias, anchor or tag
+            return None;
+        }
+        let end = text.find(" #").unwrap_or(text.len());
+        let value = text[..end].trim_end();
+        if value.is_empty() || !is_string(value) {
+            return None;
+        }
+        (value.to_string(), value.len(), ScalarStyle::Plain)
+    };
+    Some(YamlScalar {
+        path,
+        value,
+        style,
+        span: offset..offset + len,
+        indent: 0,
+        in_flow,
+    })
+}
+
+/// Parse block scalar following the line of `|` or `>`, returns next line position
+fn block_scalar(
+    lines: &[(usize, &str)],
+    start: usize,
+    owner_indent: usize,
+    style: ScalarStyle,
+    path: Vec<PathSegment>,
+    found: &mut Vec<YamlScalar>,
+) -> usize {
+    let mut pos = start;
+    let mut indent = None;
+    let mut content = Vec::new();
+    while let Some((offset, line)) = lines.get(pos) {
+        let body = line.trim_start_matches(' ');
+        if body.is_empty() {
+            pos += 1;
+            continue;
+        }
+        let line_indent = line.len() - body.len();
+        if line_indent <= owner_indent {
+            break;
+        }
+        let indent = *indent.get_or_insert(line_indent);
+        if line_indent < indent {
+            break;
+        }
+        content.push((pos, *offset, *line));
+        pos += 1;
+    }
+
+    let (Some(indent), Some((first, first_offset, _)), Some((last, last_offset, last_line))) =
+        (indent, content.first(), content.last())
+    else {
+        return pos;
+    };
+    let lines = lines[*first..=*last]
+        .iter()
+        .map(|(_, line)| line.get(indent..).unwrap_or_default())
+        .collect::<Vec<_>>();
+    let value = match style {
+        ScalarStyle::Folded => fold_lines(&lines),
+        _ => lines.join("\n"),
+    };
+    found.push(YamlScalar {
+        path,
+        value,
+        style,
+        span: first_offset + indent..last_offset + last_line.len(),
+        indent,
+        in_flow: false,
+    });
+    pos
+}
+
+/// Folded block scalar, lines are joined with space and empty lines are newlines
+fn fold_lines(lines: &[&str]) -> String {
+    let mut folded = String::new();
+    for line in lines {
+        if line.is_empty() {
+            folded.push('\n');
+        } else {
+            if !folded.is_empty() && !folded.ends_with('\n') {
+                folded.push(' ');
+            }
+            folded.push_str(line);
+        }
+    }
+    folded
+}
+
+/// Parse quoted scalar at the beginning of text, returns value and length including quotes
+fn quoted(text: &str) -> Option<(String, usize)> {
+    let mut chars = text.char_indices();
+    let (_, quote) = chars.next()?;
+    let mut value = String::new();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            ('\'', '\'') => {
+                if text[i + 1..].starts_with('\'') {
+                    // '' is escaped '
+                    chars.next();
+                    value.push('\'');
+                } else {
+                    return Some((value, i + 1));
+                }
+            }
+            ('"', '"') => return Some((value, i + 1)),
+            ('"', '\\') => {
+                let (_, escaped) = chars.next()?;
+                match escaped {
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    '0' => value.push('\0'),
+                    'x' | 'u' | 'U' => {
+                        let len = match escaped {
+                            'x' => 2,
+                            'u' => 4,
+                            _ => 8,
+                        };
+                        let hex = (0..len)
+                            .map(|_| chars.next().map(|(_, c)| c))
+                            .collect::<Option<String>>()?;
+                        value.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                    }
+                    c => value.push(c),
+                }
+            }
+            _ => value.push(c),
+        }
+    }
+    // Closing quote is not in this line
+    None
+}
+
+/// Plain scalar is string, not null, bool or number
+fn is_string(value: &str) -> bool {
+    !matches!(
+        value,
+        "~" | "null" | "Null" | "NULL" | "true" | "True" | "TRUE" | "false" | "False" | "FALSE"
+    ) && value.parse::<f64>().is_err()
+}
+
+/// Encode value in quoting style of scalar
+fn encode(scalar: &YamlScalar, value: &str) -> String {
+    match scalar.style {
+        ScalarStyle::Plain if is_plain_safe(value, scalar.in_flow) => value.to_string(),
+        ScalarStyle::SingleQuoted if !value.contains('\n') => {
+            format!("'{}'", value.replace('\'', "''"))
+        }
+        ScalarStyle::Literal | ScalarStyle::Folded => {
+            let indent = " ".repeat(scalar.indent);
+            value
+                .trim_end_matches('\n')
+                .split('\n')
+                .enumerate()
+                .map(|(i, line)| match (i, line.is_empty()) {
+                    (0, _) | (_, true) => line.to_string(),
+                    _ => format!("{}{}", indent, line),
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        _ => double_quoted(value),
+    }
+}
+
+/// Value can be written without quotes
+fn is_plain_safe(value: &str, in_flow: bool) -> bool {
+    !value.is_empty()
+        && value.trim() == value
+        && is_string(value)
+        && !value.starts_with([
+            '-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%',
+            '@', '`',
+        ])
+        && !value.contains(['\n', '\t'])
+        && !value.contains(": ")
+        && !value.contains(" #")
+        && !value.ends_with(':')
+        && !(in_flow && value.contains([',', '[', ']', '{', '}']))
+}
+
+fn double_quoted(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_path(keys: &[&str]) -> Vec<PathSegment> {
+        keys.iter()
+            .map(|k| match k.parse::<usize>() {
+                Ok(i) => PathSegment::Index(i),
+                Err(_) => PathSegment::Key(k.to_string()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_scalars() {
+        let yaml = r#"
+# comment
+title: Hello World # trailing comment
+description: 'It''s a test'
+sidebar_label: "Say \"Hi\""
+draft: true
+weight: 10
+keywords: [docs, "guide", 'intro']
+tags:
+- first
+-   second
+seo:
+  meta:
+    - name: og:title
+      text: Open Graph
+summary: >
+  Folded
+  text
+folded: Hello
+  world
+list:
+  - first
+    item
+  - key: value
+    continued: Multi
+      line
+"#;
+        let found = scalars(yaml)
+            .into_iter()
+            .map(|s| (s.path, s.value, s.style))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    key_path(&["title"]),
+                    String::from("Hello World"),
+                    ScalarStyle::Plain
+                ),
+                (
+                    key_path(&["description"]),
+                    String::from("It's a test"),
+                    ScalarStyle::SingleQuoted
+                ),
+                (
+                    key_path(&["sidebar_label"]),
+                    String::from("Say \"Hi\""),
+                    ScalarStyle::DoubleQuoted
+                ),
+                (
+                    key_path(&["keywords", "0"]),
+                    String::from("docs"),
+                    ScalarStyle::Plain
+                ),
+                (
+                    key_path(&["keywords", "1"]),
+                    String::from("guide"),
+                    ScalarStyle::DoubleQuoted
+                ),
+                (
+                    key_path(&["keywords", "2"]),
+                    String::from("intro"),
+                    ScalarStyle::SingleQuoted
+                ),
+                (
+                    key_path(&["tags", "0"]),
+                    String::from("first"),
+                    ScalarStyle::Plain
+                ),
+                (
+                    key_path(&["tags", "1"]),
+                    String::from("second"),
+                    ScalarStyle::Plain
+                ),
+                (
+                    key_path(&["seo", "meta", "0", "name"]),
+                    String::from("og:title"),
+                    ScalarStyle::Plain
+                ),
+                (
+                    key_path(&["seo", "meta", "0", "text"]),
+                    String::from("Open Graph"),
+                    ScalarStyle::Plain
+                ),
+                (
+                    key_path(&["summary"]),
+                    String::from("Folded text"),
+                    ScalarStyle::Folded
+                ),
+                // Multi-line plain scalars are not found
+                (
+                    key_path(&["list", "1", "key"]),
+                    String::from("value"),
+                    ScalarStyle::Plain
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replace_scalars() {
+        let yaml = "title: Hello # comment\ndescription: 'Desc'\nkeywords: [a, b]\nbody: |\n  line 1\n  line 2\nnote: \"Note\"\n";
+        let found = scalars(yaml);
+        let replacements = found
+            .iter()
+            .map(|s| {
+                let value = match s.style {
+                    ScalarStyle::Literal => String::from("行 1\n行 2\n行 3"),
+                    _ => format!("{}: it's", s.value),
+                };
+                (s, value)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            replace_scalars(yaml, &replacements),
+            "title: \"Hello: it's\" # comment\ndescription: 'Desc: it''s'\nkeywords: [\"a: it's\", \"b: it's\"]\nbody: |\n  行 1\n  行 2\n  行 3\nnote: \"Note: it's\"\n"
+        );
+
+        // Plain style is kept if possible
+        let found = scalars("title: Hello\n");
+        assert_eq!(
+            replace_scalars("title: Hello\n", &[(&found[0], String::from("こんにちは"))]),
+            "title: こんにちは\n"
+        );
+    }
+}
@@ -0,0 +1,515 @@
+//!
+//! DeepL API client tests against a local HTTP stand-in
+//!
+
+use cmark_translate::{Deepl, Error, Formality, SourceLanguage, TargetLanguage, Translator};
+use wiremock::matchers::{body_string_contains, header, method, path};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+/// Write config file pointing to the local server and load it
+fn deepl_with_server(server: &MockServer, name: &str) -> Deepl {
+    let config_path = std::env::temp_dir().join(format!(
+        "cmark-translate-{}-{}.toml",
+        name,
+        std::process::id()
+    ));
+    let config = format!(
+        "api_key = \"test-key\"\nendpoint = \"{}/v2/\"\nproject_name = \"test\"\nbackup_original_text = false\n[glossaries]\n",
+        server.uri()
+    );
+    std::fs::write(&config_path, config).unwrap();
+    let deepl = Deepl::with_config(&config_path).unwrap();
+    std::fs::remove_file(&config_path).unwrap();
+    deepl
+}
+
+/// Decode application/x-www-form-urlencoded request body
+fn form_params(req: &Request) -> Vec<(String, String)> {
+    let body = String::from_utf8_lossy(&req.body);
+    reqwest::Url::parse(&format!("http://localhost/?{}", body))
+        .unwrap()
+        .query_pairs()
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect()
+}
+
+/// Escape string as JSON string literal
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Translation response which echoes `text` parameters in upper case
+struct EchoUpper;
+
+impl Respond for EchoUpper {
+    fn respond(&self, req: &Request) -> ResponseTemplate {
+        let translations = form_params(req)
+            .into_iter()
+            .filter(|(k, _)| k == "text")
+            .map(|(_, text)| {
+                format!(
+                    "{{\"detected_source_language\":\"EN\",\"text\":{}}}",
+                    json_string(&upper_outside_tags(&text))
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        ResponseTemplate::new(200).set_body_raw(
+            format!("{{\"translations\":[{}]}}", translations),
+            "application/json",
+        )
+    }
+}
+
+/// Upper case texts, keep XML tags as is
+fn upper_outside_tags(text: &str) -> String {
+    let mut in_tag = false;
+    text.chars()
+        .map(|c| {
+            match c {
+                '<' => in_tag = true,
+                '>' => in_tag = false,
+                _ => (),
+            }
+            if in_tag {
+                c
+            } else {
+                c.to_ascii_uppercase()
+            }
+        })
+        .collect()
+}
+
+fn glossaries_json(glossaries: &[(&str, &str)]) -> String {
+    let glossaries = glossaries
+        .iter()
+        .map(|(id, name)| {
+            format!(
+                "{{\"glossary_id\":\"{}\",\"name\":\"{}\",\"ready\":true,\"source_lang\":\"en\",\"target_lang\":\"ja\",\"creation_time\":\"2023-08-17T05:57:06.339196Z\",\"entry_count\":2}}",
+                id, name
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{{\"glossaries\":[{}]}}", glossaries)
+}
+
+#[tokio::test]
+async fn test_translate_strings() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/glossaries"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            glossaries_json(&[("other-id", "other"), ("test-id", "test")]),
+            "application/json",
+        ))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v2/translate"))
+        .and(header("authorization", "DeepL-Auth-Key test-key"))
+        .and(body_string_contains("source_lang=en"))
+        .and(body_string_contains("target_lang=ja"))
+        .and(body_string_contains("formality=prefer_more"))
+        .and(body_string_contains("glossary_id=test-id"))
+        .respond_with(EchoUpper)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let deepl = deepl_with_server(&server, "translate_strings");
+    let translated = deepl
+        .translate_strings(
+            SourceLanguage::En,
+            TargetLanguage::Ja,
+            Formality::Formal,
+            &["Hello", "World"],
+        )
+        .await
+        .unwrap();
+    assert_eq!(translated, vec!["HELLO", "WORLD"]);
+}
+
+#[tokio::test]
+async fn test_translate_xml_with_glossary() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/glossaries"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            glossaries_json(&[("other-id", "other"), ("test-id", "test")]),
+            "application/json",
+        ))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v2/translate"))
+        .and(body_string_contains("tag_handling=xml"))
+        .and(body_string_contains("glossary_id=test-id"))
+        .respond_with(EchoUpper)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let deepl = deepl_with_server(&server, "translate_xml");
+    let translated = deepl
+        .translate_xml(
+            SourceLanguage::En,
+            TargetLanguage::Ja,
+            Formality::Default,
+            "<body xmlns='markdown'><p>Hello</p></body>",
+        )
+        .await
+        .unwrap();
+    assert_eq!(translated, "<body xmlns='markdown'><p>HELLO</p></body>");
+}
+
+#[tokio::test]
+async fn test_glossary_listed_once() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/glossaries"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(glossaries_json(&[("test-id", "test")]), "application/json"),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v2/translate"))
+        .and(body_string_contains("glossary_id=test-id"))
+        .respond_with(EchoUpper)
+        .expect(3)
+        .mount(&server)
+        .await;
+
+    let deepl = deepl_with_server(&server, "glossary_once");
+    for _ in 0..2 {
+        deepl
+            .translate_xml(
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                Formality::Default,
+                "<body xmlns='markdown'><p>Hello</p></body>",
+            )
+            .await
+            .unwrap();
+    }
+    deepl
+        .translate_strings(
+            SourceLanguage::En,
+            TargetLanguage::Ja,
+            Formality::Default,
+            &["Hello"],
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_glossary_not_ready() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/glossaries"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            glossaries_json(&[("test-id", "test")]).replace("\"ready\":true", "\"ready\":false"),
+            "application/json",
+        ))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v2/translate"))
+        .respond_with(EchoUpper)
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let deepl = deepl_with_server(&server, "glossary_not_ready");
+    let err = deepl
+        .translate_strings(
+            SourceLanguage::En,
+            TargetLanguage::Ja,
+            Formality::Default,
+            &["Hello"],
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Config(msg) if msg.contains("not ready")));
+}
+
+#[tokio::test]
+async fn test_translate_cmark() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/glossaries"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(glossaries_json(&[]), "application/json"),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v2/translate"))
+        .respond_with(EchoUpper)
+        .mount(&server)
+        .await;
+
+    let deepl = deepl_with_server(&server, "translate_cmark");
+    let translated = cmark_translate::translate_cmark(
+        &deepl,
+        SourceLanguage::En,
+        TargetLanguage::Ja,
+        Formality::Default,
+        "# Title\n\nThis is **bold** and `code`.\n",
+    )
+    .await
+    .unwrap();
+    assert_eq!(translated, "# TITLE\n\nTHIS IS **BOLD** AND `code`.\n");
+}
+
+#[tokio::test]
+async fn test_glossaries() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v2/glossaries"))
+        .and(body_string_contains("name=test"))
+        .and(body_string_contains("entries_format=tsv"))
+        .respond_with(ResponseTemplate::new(201).set_body_raw(
+            "{\"glossary_id\":\"new-id\",\"name\":\"test\",\"ready\":true,\"source_lang\":\"en\",\"target_lang\":\"ja\",\"creation_time\":\"2023-08-17T05:57:06.339196Z\",\"entry_count\":2}",
+            "application/json",
+        ))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v2/glossaries"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(glossaries_json(&[("new-id", "test")]), "application/json"),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v2/glossaries/new-id/entries"))
+        .and(header("accept", "text/tab-separated-values"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw("red\t赤\nblue\t青", "text/tab-separated-values"),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path("/v2/glossaries/new-id"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let deepl = deepl_with_server(&server, "glossaries");
+    let glossary = deepl
+        .register_glossaries(
+            "test",
+            SourceLanguage::En,
+            TargetLanguage::Ja,
+            &[("red", "赤"), ("blue", "青")],
+        )
+        .await
+        .unwrap();
+    assert_eq!(glossary.glossary_id, "new-id");
+
+    let glossaries = deepl.list_glossaries().await.unwrap();
+    assert_eq!(glossaries.len(), 1);
+    assert_eq!(glossaries[0].name, "test");
+
+    let entries = deepl.glossary_entries("new-id").await.unwrap();
+    assert_eq!(
+        entries,
+        vec![
+            ("red".to_string(), "赤".to_string()),
+            ("blue".to_string(), "青".to_string())
+        ]
+    );
+
+    deepl.remove_glossary("new-id").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_detect_language() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v2/translate"))
+        .and(body_string_contains("target_lang=ja"))
+        .respond_with(EchoUpper)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let deepl = deepl_with_server(&server, "detect_language");
+    let detected = deepl
+        .detect_language(TargetLanguage::Ja, "Hello")
+        .await
+        .unwrap();
+    assert_eq!(detected, Some(SourceLanguage::En));
+
+    // source_lang is omitted
+    let requests = server.received_requests().await.unwrap();
+    assert!(!form_params(&requests[0])
+        .iter()
+        .any(|(k, _)| k == "source_lang"));
+}
+
+#[tokio::test]
+async fn test_languages() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/languages"))
+        .and(wiremock::matchers::query_param("type", "target"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            "[{\"language\":\"DE\",\"name\":\"German\",\"supports_formality\":true},{\"language\":\"EN-US\",\"name\":\"English (American)\",\"supports_formality\":false}]",
+            "application/json",
+        ))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v2/languages"))
+        .and(wiremock::matchers::query_param("type", "source"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            "[{\"language\":\"EN\",\"name\":\"English\"}]",
+            "application/json",
+        ))
+        .mount(&server)
+        .await;
+
+    let deepl = deepl_with_server(&server, "languages");
+    let sources = deepl.source_languages().await.unwrap();
+    assert_eq!(sources[0].language, "EN");
+    assert!(!sources[0].supports_formality);
+
+    deepl
+        .check_target_language(TargetLanguage::De, Formality::Formal)
+        .await
+        .unwrap();
+    deepl
+        .check_target_language(TargetLanguage::En, Formality::Default)
+        .await
+        .unwrap();
+    // Preferred formality is ignored for languages without formality support
+    deepl
+        .check_target_language(TargetLanguage::EnUs, Formality::Formal)
+        .await
+        .unwrap();
+    assert!(deepl
+        .check_target_language(TargetLanguage::Ja, Formality::Default)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_get_usage() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/usage"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            "{\"character_count\":1234,\"character_limit\":500000}",
+            "application/json",
+        ))
+        .mount(&server)
+        .await;
+
+    let deepl = deepl_with_server(&server, "usage");
+    assert_eq!(deepl.get_usage().await.unwrap(), 1234);
+}
+
+#[tokio::test]
+async fn test_error_status() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/usage"))
+        .respond_with(ResponseTemplate::new(403))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v2/glossaries"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("Bad request"))
+        .mount(&server)
+        .await;
+
+    let deepl = deepl_with_server(&server, "error_status");
+    assert!(matches!(deepl.get_usage().await, Err(Error::Auth(_))));
+    assert!(matches!(
+        deepl.list_glossaries().await,
+        Err(Error::ApiStatus { status: 400, .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_retry_after() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/usage"))
+        .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "1"))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v2/usage"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            "{\"character_count\":42,\"character_limit\":500000}",
+            "application/json",
+        ))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let deepl = deepl_with_server(&server, "retry_after");
+    let start = std::time::Instant::now();
+    assert_eq!(deepl.get_usage().await.unwrap(), 42);
+    // Retried after waiting Retry-After
+    assert!(std::time::Duration::from_secs(1) <= start.elapsed());
+}
+
+#[tokio::test]
+async fn test_glossary_registration_not_retried() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v2/glossaries"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path("/v2/glossaries/old-id"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // Retry may register the same glossary twice
+    let deepl = deepl_with_server(&server, "registration_not_retried");
+    assert!(matches!(
+        deepl
+            .register_glossaries(
+                "test",
+                SourceLanguage::En,
+                TargetLanguage::Ja,
+                &[("red", "赤")]
+            )
+            .await,
+        Err(Error::ApiStatus { status: 503, .. })
+    ));
+    assert!(deepl.remove_glossary("old-id").await.is_err());
+}